@@ -0,0 +1,109 @@
+//! Crosshair in the center of the frame, drawn by a custom pass of the render graph
+
+use crate::renderer::{PassContext, RenderGraphPass};
+
+/// Length of a line in physical pixels
+const SIZE: u32 = 21;
+
+/// The two lines in clip space of the square in the center, they need no buffers
+const SHADER: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    var positions = array<vec2<f32>, 4>(
+        vec2<f32>(-1.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    return vec4<f32>(positions[index], 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0, 1.0, 1.0, 0.8);
+}
+";
+
+pub struct Crosshair {
+    surface_format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Crosshair {
+    pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> Self
+    {
+        Self {
+            surface_format,
+            pipeline: Self::create_pipeline(device, surface_format),
+        }
+    }
+
+    fn create_pipeline(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> wgpu::RenderPipeline
+    {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Crosshair Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Crosshair Pipeline"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+}
+
+impl RenderGraphPass for Crosshair {
+    fn record(&self, context: &mut PassContext)
+    {
+        let size = context.frame_size;
+        if size.width < SIZE || size.height < SIZE {
+            return;
+        }
+
+        let mut render_pass = context.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Crosshair Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: context.frame_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        });
+
+        let x = (size.width - SIZE) / 2;
+        let y = (size.height - SIZE) / 2;
+        render_pass.set_viewport(x as f32, y as f32, SIZE as f32, SIZE as f32, 0.0, 1.0);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(0..4, 0..1);
+    }
+
+    fn recreate(&mut self, device: &wgpu::Device) {
+        self.pipeline = Self::create_pipeline(device, self.surface_format);
+    }
+}
@@ -0,0 +1,123 @@
+//! Runs the simulation in fixed ticks, independent of the frame rate
+//!
+//! The frame time is collected in an accumulator, every full tick in it runs one fixed
+//! update. What is left is less than a tick, its fraction of the tick is the alpha to
+//! interpolate between the last two simulated states when rendering. A slow frame runs at
+//! most `max_steps` ticks, the simulation falls behind instead of taking ever longer.
+
+/// Apps with a simulation which only advances in fixed ticks
+pub trait FixedUpdate {
+    /// Advances the simulation by exactly one tick
+    fn fixed_update(&mut self, dt: instant::Duration);
+}
+
+pub struct FixedTimestep {
+    tick: instant::Duration,
+    max_steps: u32,
+    accumulator: instant::Duration,
+}
+
+impl FixedTimestep {
+    /// Ticks per second, and the most ticks run in a single frame
+    pub fn new(tick_rate: u32, max_steps: u32) -> Self
+    {
+        Self {
+            tick: instant::Duration::from_secs_f64(1.0 / tick_rate.max(1) as f64),
+            max_steps: max_steps.max(1),
+            accumulator: instant::Duration::ZERO,
+        }
+    }
+
+    /// Adds the frame time, returns the number of ticks to run this frame
+    pub fn advance(&mut self, dt: instant::Duration) -> u32
+    {
+        self.accumulator += dt;
+
+        let mut steps = 0;
+        while self.accumulator >= self.tick && steps < self.max_steps {
+            self.accumulator -= self.tick;
+            steps += 1;
+        }
+
+        // drop the ticks which did not fit, but keep the part of the current one
+        if self.accumulator >= self.tick {
+            let remainder = self.accumulator.as_nanos() % self.tick.as_nanos();
+            self.accumulator = instant::Duration::from_nanos(remainder as u64);
+        }
+
+        steps
+    }
+
+    /// How far the time is between the last tick and the next one, from 0 to 1
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.tick.as_secs_f64()) as f32
+    }
+
+    /// Runs the ticks of the frame time on the app, returns the alpha to render with
+    pub fn run(&mut self, app: &mut impl FixedUpdate, dt: instant::Duration) -> f32
+    {
+        for _ in 0..self.advance(dt) {
+            app.fixed_update(self.tick);
+        }
+
+        self.alpha()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        ticks: u32,
+        time: instant::Duration,
+    }
+
+    impl FixedUpdate for Counter {
+        fn fixed_update(&mut self, dt: instant::Duration) {
+            self.ticks += 1;
+            self.time += dt;
+        }
+    }
+
+    #[test]
+    fn accumulates_partial_ticks()
+    {
+        let mut timestep = FixedTimestep::new(100, 5);
+
+        assert_eq!(timestep.advance(instant::Duration::from_millis(4)), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-4);
+        assert_eq!(timestep.advance(instant::Duration::from_millis(17)), 2);
+        assert!((timestep.alpha() - 0.1).abs() < 1e-4);
+    }
+
+    #[test]
+    fn catch_up_is_limited()
+    {
+        let mut timestep = FixedTimestep::new(100, 3);
+
+        assert_eq!(timestep.advance(instant::Duration::from_millis(1005)), 3);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(timestep.advance(instant::Duration::from_millis(5)), 1);
+    }
+
+    #[test]
+    fn same_time_runs_same_ticks_at_any_frame_rate()
+    {
+        let run = |frame_ms: u64, frames: u32| {
+            let mut timestep = FixedTimestep::new(50, 8);
+            let mut counter = Counter { ticks: 0, time: instant::Duration::ZERO };
+            for _ in 0..frames {
+                timestep.run(&mut counter, instant::Duration::from_millis(frame_ms));
+            }
+            counter
+        };
+
+        let slow = run(40, 25);
+        let fast = run(5, 200);
+
+        assert_eq!(slow.ticks, 50);
+        assert_eq!(slow.ticks, fast.ticks);
+        assert_eq!(slow.time, fast.time);
+    }
+}
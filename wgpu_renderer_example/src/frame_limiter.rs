@@ -0,0 +1,79 @@
+//! Caps the frame rate by sleeping on the CPU
+//!
+//! Without vsync the example renders as many frames as the machine can. The limiter
+//! sleeps away what is left of the frame time of the target frame rate. Browsers pace
+//! the frames themselves and can not sleep on the main thread, so it never sleeps there.
+
+pub struct FrameLimiter {
+    target_fps: Option<u32>,
+    frame_start: instant::Instant,
+}
+
+impl FrameLimiter {
+    /// None does not limit the frame rate
+    pub fn new(target_fps: Option<u32>) -> Self
+    {
+        Self {
+            target_fps,
+            frame_start: instant::Instant::now(),
+        }
+    }
+
+    pub fn target_fps(&self) -> Option<u32> {
+        self.target_fps
+    }
+
+    pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+        self.target_fps = target_fps;
+    }
+
+    /// Time left of the frame which started with the last call to [FrameLimiter::wait]
+    fn remaining(&self, now: instant::Instant) -> instant::Duration
+    {
+        let Some(target_fps) = self.target_fps.filter(|fps| *fps > 0) else {
+            return instant::Duration::ZERO;
+        };
+
+        let frame_time = instant::Duration::from_secs_f64(1.0 / target_fps as f64);
+        frame_time.saturating_sub(now.saturating_duration_since(self.frame_start))
+    }
+
+    /// Sleeps until the frame time has passed and starts the next frame, returns the time slept
+    pub fn wait(&mut self) -> instant::Duration
+    {
+        let remaining = self.remaining(instant::Instant::now());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if !remaining.is_zero() {
+            std::thread::sleep(remaining);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let remaining = instant::Duration::ZERO;
+
+        self.frame_start = instant::Instant::now();
+        remaining
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlimited_never_waits()
+    {
+        let limiter = FrameLimiter::new(None);
+
+        assert_eq!(limiter.remaining(limiter.frame_start), instant::Duration::ZERO);
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_the_frame()
+    {
+        let limiter = FrameLimiter::new(Some(100));
+        let start = limiter.frame_start;
+
+        assert_eq!(limiter.remaining(start + instant::Duration::from_millis(4)), instant::Duration::from_millis(6));
+        assert_eq!(limiter.remaining(start + instant::Duration::from_millis(25)), instant::Duration::ZERO);
+    }
+}
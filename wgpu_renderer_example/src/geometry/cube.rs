@@ -0,0 +1,51 @@
+use crate::lit_shader::Vertex as Vertex;
+
+pub struct Cube {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Cube {
+    /// Centered at the origin, every face has its own vertices so the normals stay flat
+    pub fn new(size: f32, color: [f32; 3]) -> Self
+    {
+        let h = size / 2.0;
+
+        // normal, tangent and bitangent of each face
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([ 1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]), // +x
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]), // -x
+            ([0.0,  1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]), // +y
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]), // -y
+            ([0.0, 0.0,  1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]), // +z
+            ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]), // -z
+        ];
+
+        let mut vertices = Vec::<Vertex>::with_capacity(24);
+        let mut indices = Vec::<u32>::with_capacity(36);
+
+        for (normal, tangent, bitangent) in faces {
+            let start = vertices.len() as u32;
+
+            for (u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                let position = [
+                    h * (normal[0] + u * tangent[0] + v * bitangent[0]),
+                    h * (normal[1] + u * tangent[1] + v * bitangent[1]),
+                    h * (normal[2] + u * tangent[2] + v * bitangent[2]),
+                ];
+
+                vertices.push(Vertex { position, normal, color });
+            }
+
+            indices.extend_from_slice(&[
+                start, start + 1, start + 2,
+                start + 2, start + 3, start,
+            ]);
+        }
+
+        Self {
+            vertices,
+            indices,
+        }
+    }
+}
@@ -1,4 +1,8 @@
 
-
 mod quad;
 mod circle; 
+mod cube;
+
+pub use quad::Quad;
+pub use circle::Circle;
+pub use cube::Cube;
\ No newline at end of file
@@ -0,0 +1,469 @@
+//! Golden image tests
+//!
+//! Renders fixed scenes offscreen and compares them pixel by pixel against the
+//! reference images in tests/golden. Set GOLDEN_BLESS=1 to overwrite the references
+//! with the current output. On a mismatch the output and a diff image are written
+//! to target/golden. The tests need an adapter and are ignored by default, run them
+//! with `cargo test -- --include-ignored`. On CI install a software adapter like
+//! llvmpipe (mesa-vulkan-drivers). The references have to be blessed with the
+//! wgpu_renderer submodule checked out, a missing reference fails its test.
+
+use std::path::PathBuf;
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use wgpu_renderer::vertex_color_shader;
+
+use crate::geometry;
+use crate::lit_shader;
+use crate::performance_monitor::PerformanceMonitor;
+use wgpu_renderer::vertex_color_shader::VertexColorShaderDraw;
+use crate::renderer::{Batched, Bounded, ColorDrawable, LitDrawable, MaterialDesc, MaterialHandle, MaterialShaderDraw};
+use crate::renderer::{OffscreenRenderer, RenderQueue, Renderer, TextureDrawable};
+use crate::textured_quad::TexturedQuad;
+
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 240;
+
+/// Multisampling patterns differ between adapters
+const SAMPLE_COUNT: u32 = 1;
+
+/// Maximum difference per channel, software and hardware rasterizers round differently
+const TOLERANCE: u8 = 2;
+
+struct Comparison {
+    mismatched_pixels: usize,
+    max_difference: u8,
+    diff: image::RgbaImage,
+}
+
+/// Compares two images of the same size, mismatched pixels are red in the diff image
+fn compare(actual: &image::RgbaImage, expected: &image::RgbaImage, tolerance: u8) -> Comparison
+{
+    assert_eq!(actual.dimensions(), expected.dimensions(), "image sizes differ");
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff = image::RgbaImage::new(actual.width(), actual.height());
+
+    for (x, y, actual_pixel) in actual.enumerate_pixels() {
+        let expected_pixel = expected.get_pixel(x, y);
+
+        let difference = actual_pixel.0.iter()
+            .zip(expected_pixel.0.iter())
+            .map(|(a, e)| a.abs_diff(*e))
+            .max()
+            .unwrap_or(0);
+        max_difference = max_difference.max(difference);
+
+        let diff_pixel = if difference > tolerance {
+            mismatched_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            // dimmed grayscale of the expected image for orientation
+            let gray = (expected_pixel[0] as u16 + expected_pixel[1] as u16 + expected_pixel[2] as u16) / 12;
+            image::Rgba([gray as u8, gray as u8, gray as u8, 255])
+        };
+        diff.put_pixel(x, y, diff_pixel);
+    }
+
+    Comparison {
+        mismatched_pixels,
+        max_difference,
+        diff,
+    }
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.png"))
+}
+
+fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("target")
+        .join("golden")
+}
+
+fn bless_requested() -> bool {
+    std::env::var("GOLDEN_BLESS").map(|value| value == "1").unwrap_or(false)
+}
+
+/// Compares the image against its reference, a missing reference fails unless blessing is requested
+fn check_golden(name: &str, actual: &image::RgbaImage)
+{
+    let reference_path = reference_path(name);
+
+    if bless_requested() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        actual.save(&reference_path).unwrap();
+        log::info!("golden: blessed {}", reference_path.display());
+        return;
+    }
+
+    let expected = match image::open(&reference_path) {
+        Ok(expected) => expected.to_rgba8(),
+        Err(err) => panic!("{name}: no reference at {} ({err}), rerun with GOLDEN_BLESS=1",
+            reference_path.display()),
+    };
+    assert_eq!(actual.dimensions(), expected.dimensions(),
+        "{name}: size differs from {}, rerun with GOLDEN_BLESS=1", reference_path.display());
+
+    let comparison = compare(actual, &expected, TOLERANCE);
+    if comparison.mismatched_pixels > 0 {
+        let output_dir = output_dir();
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let actual_path = output_dir.join(format!("{name}_actual.png"));
+        let diff_path = output_dir.join(format!("{name}_diff.png"));
+        actual.save(&actual_path).unwrap();
+        comparison.diff.save(&diff_path).unwrap();
+
+        panic!("{name}: {} pixels differ by more than {TOLERANCE} (max {}), see {} and {}",
+            comparison.mismatched_pixels,
+            comparison.max_difference,
+            actual_path.display(),
+            diff_path.display());
+    }
+}
+
+/// The overlays are hidden, they would cover most of the small frame
+fn create_renderer() -> Renderer<OffscreenRenderer>
+{
+    let mut renderer = pollster::block_on(Renderer::new_headless(WIDTH, HEIGHT, SAMPLE_COUNT))
+        .expect("golden: no adapter available");
+    renderer.set_minimap_visible(false);
+    renderer.set_gizmo_visible(false);
+
+    renderer
+}
+
+fn render_scene(
+    renderer: &mut Renderer<OffscreenRenderer>,
+    meshes: &[&dyn TextureDrawable],
+    meshes_color: &[&dyn ColorDrawable],
+    meshes_lit: &[&dyn LitDrawable],
+) -> image::RgbaImage
+{
+    let mut queue = RenderQueue::new();
+    for mesh in meshes {
+        queue.push_texture(*mesh);
+    }
+    for mesh in meshes_color {
+        queue.push_color(*mesh);
+    }
+    for mesh in meshes_lit {
+        queue.push_lit(*mesh);
+    }
+
+    render_queue(renderer, &mut queue)
+}
+
+fn render_queue(renderer: &mut Renderer<OffscreenRenderer>, queue: &mut RenderQueue) -> image::RgbaImage
+{
+    let mut performance_monitor = PerformanceMonitor::new(&mut renderer.wgpu_renderer);
+    renderer.update(instant::Duration::ZERO, 1.0);
+    renderer.render(queue, &mut performance_monitor).unwrap();
+
+    renderer.read_frame()
+}
+
+#[test]
+fn compare_respects_tolerance()
+{
+    let expected = image::RgbaImage::from_pixel(2, 1, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(0, 0, image::Rgba([100 + TOLERANCE, 100, 100, 255]));
+    actual.put_pixel(1, 0, image::Rgba([100, 100 - TOLERANCE - 1, 100, 255]));
+
+    let comparison = compare(&actual, &expected, TOLERANCE);
+
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(comparison.max_difference, TOLERANCE + 1);
+    assert_eq!(comparison.diff.get_pixel(1, 0), &image::Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn captured_frame_is_the_presented_frame()
+{
+    let mut renderer = create_renderer();
+
+    let textured_quad = TexturedQuad::new(
+        &mut renderer.resources,
+        &mut renderer.wgpu_renderer,
+        &renderer.texture_bind_group_layout);
+
+    renderer.capture_frame();
+    let presented = render_scene(&mut renderer, &[&textured_quad], &[], &[]);
+    let captured = renderer.take_captured_frame().unwrap().unwrap();
+
+    assert!(captured == presented, "the capture differs from the presented frame");
+    assert!(renderer.take_captured_frame().is_none());
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn debug_lines_last_one_frame()
+{
+    let mut renderer = create_renderer();
+
+    renderer.debug_draw().world().line(glam::Vec3::ZERO, glam::Vec3::X, [1.0, 1.0, 1.0]);
+    render_queue(&mut renderer, &mut RenderQueue::new());
+    assert!(!renderer.debug_draw().is_empty());
+
+    // nothing was added for the second frame
+    render_queue(&mut renderer, &mut RenderQueue::new());
+    assert!(renderer.debug_draw().is_empty());
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn golden_textured_quad()
+{
+    let mut renderer = create_renderer();
+
+    let textured_quad = TexturedQuad::new(
+        &mut renderer.resources,
+        &mut renderer.wgpu_renderer,
+        &renderer.texture_bind_group_layout);
+
+    let image = render_scene(&mut renderer, &[&textured_quad], &[], &[]);
+
+    check_golden("textured_quad", &image);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn golden_geometry()
+{
+    let mut renderer = create_renderer();
+
+    let quad = geometry::Quad::new(1.0);
+    let circle = geometry::Circle::new(0.5, 32);
+
+    let instances = [vertex_color_shader::Instance {
+        position: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+    }];
+    let quad_mesh = vertex_color_shader::Mesh::new(
+        renderer.wgpu_renderer.device(),
+        &quad.vertices,
+        &quad.colors,
+        &quad.indices,
+        &instances,
+    );
+
+    let instances = [vertex_color_shader::Instance {
+        position: glam::Vec3::new(-1.0, 0.5, 0.0),
+        rotation: glam::Quat::IDENTITY,
+    }];
+    let circle_mesh = vertex_color_shader::Mesh::new(
+        renderer.wgpu_renderer.device(),
+        &circle.vertices,
+        &circle.colors,
+        &circle.indices,
+        &instances,
+    );
+
+    let image = render_scene(&mut renderer, &[], &[&quad_mesh, &circle_mesh], &[]);
+
+    check_golden("geometry", &image);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn golden_lit_cube()
+{
+    let mut renderer = create_renderer();
+
+    let cube = geometry::Cube::new(1.0, [0.8, 0.3, 0.2]);
+
+    let instances = [vertex_color_shader::Instance {
+        position: glam::Vec3::new(0.0, 0.0, 0.5),
+        rotation: glam::Quat::from_rotation_z(0.5),
+    }];
+    let cube_mesh = lit_shader::Mesh::new(
+        &mut renderer.resources,
+        renderer.wgpu_renderer.device(),
+        &cube.vertices,
+        &cube.indices,
+        &instances,
+    );
+
+    let mut lights = lit_shader::Lights::default();
+    lights.point_lights.push(lit_shader::PointLight {
+        position: glam::Vec3::new(1.0, -1.5, 2.0),
+        color: glam::Vec3::new(0.2, 0.4, 1.0),
+        intensity: 4.0,
+        range: 10.0,
+    });
+    renderer.set_lights(lights);
+
+    let image = render_scene(&mut renderer, &[], &[], &[&cube_mesh]);
+
+    check_golden("lit_cube", &image);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn golden_transparent_over_lit()
+{
+    let mut renderer = create_renderer();
+
+    let cube = geometry::Cube::new(1.0, [0.8, 0.3, 0.2]);
+
+    let instances = [vertex_color_shader::Instance {
+        position: glam::Vec3::new(0.0, 0.0, 0.5),
+        rotation: glam::Quat::IDENTITY,
+    }];
+    let cube_mesh = lit_shader::Mesh::new(
+        &mut renderer.resources,
+        renderer.wgpu_renderer.device(),
+        &cube.vertices,
+        &cube.indices,
+        &instances,
+    );
+
+    let textured_quad = TexturedQuad::new(
+        &mut renderer.resources,
+        &mut renderer.wgpu_renderer,
+        &renderer.texture_bind_group_layout);
+
+    let mut queue = RenderQueue::new();
+    queue.push_lit(&cube_mesh);
+    queue.push_transparent(&textured_quad);
+    let image = render_queue(&mut renderer, &mut queue);
+
+    check_golden("transparent_over_lit", &image);
+}
+
+/// Vertex color layout, the colors are inverted
+const INVERTED_COLOR_SHADER: &str = "
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(model_matrix_0, model_matrix_1, model_matrix_2, model_matrix_3);
+
+    var out: VertexOutput;
+    out.color = vec3<f32>(1.0) - color;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
+";
+
+struct MaterialMesh {
+    material: MaterialHandle,
+    mesh: vertex_color_shader::Mesh,
+}
+
+impl MaterialShaderDraw for MaterialMesh {
+    fn material(&self) -> MaterialHandle {
+        self.material
+    }
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.mesh.draw(render_pass);
+    }
+}
+
+impl Bounded for MaterialMesh {}
+impl Batched for MaterialMesh {}
+
+/// A quad at the origin drawn with the inverted color material
+fn inverted_quad(renderer: &mut Renderer<OffscreenRenderer>) -> MaterialMesh
+{
+    let material = renderer.register_material(MaterialDesc::new(
+        "Inverted Color Material",
+        INVERTED_COLOR_SHADER,
+        vec![
+            vertex_color_shader::Vertex::desc(),
+            vertex_color_shader::Color::desc(),
+            vertex_color_shader::InstanceRaw::desc(),
+        ]))
+        .expect("the material is valid");
+
+    let quad = geometry::Quad::new(1.0);
+    let instances = [vertex_color_shader::Instance {
+        position: glam::Vec3::ZERO,
+        rotation: glam::Quat::IDENTITY,
+    }];
+
+    MaterialMesh {
+        material,
+        mesh: vertex_color_shader::Mesh::new(
+            renderer.wgpu_renderer.device(),
+            &quad.vertices,
+            &quad.colors,
+            &quad.indices,
+            &instances,
+        ),
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn golden_material()
+{
+    let mut renderer = create_renderer();
+    let quad_mesh = inverted_quad(&mut renderer);
+
+    let mut queue = RenderQueue::new();
+    queue.push_material(&quad_mesh);
+    let image = render_queue(&mut renderer, &mut queue);
+
+    check_golden("material", &image);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn material_meshes_are_picked()
+{
+    let mut renderer = create_renderer();
+    let quad_mesh = inverted_quad(&mut renderer);
+
+    let background = render_queue(&mut renderer, &mut RenderQueue::new());
+    let mut queue = RenderQueue::new();
+    queue.push_material(&quad_mesh);
+    let image = render_queue(&mut renderer, &mut queue);
+    let (x, y, _) = image.enumerate_pixels()
+        .find(|(x, y, pixel)| background.get_pixel(*x, *y) != *pixel)
+        .expect("the quad is visible");
+
+    renderer.request_pick(x as f32, y as f32);
+    let mut queue = RenderQueue::new();
+    let id = queue.push_material(&quad_mesh);
+    render_queue(&mut renderer, &mut queue);
+
+    let result = (0..100)
+        .find_map(|_| {
+            renderer.wgpu_renderer.device().poll(wgpu::Maintain::Wait);
+            renderer.poll_pick()
+        })
+        .expect("the pick is read back");
+
+    assert_eq!(result.hit.map(|hit| hit.draw_id), Some(id));
+}
@@ -0,0 +1,176 @@
+//! Checkered ground under the scene, drawn with a material of its own and a custom bind group
+
+use wgpu::util::DeviceExt;
+use wgpu_renderer::vertex_color_shader::{self, VertexColorShaderDraw};
+
+use crate::geometry;
+use crate::renderer::{Batched, Bounded, MaterialBindGroup, MaterialDesc, MaterialHandle, MaterialShaderDraw};
+use crate::renderer::{RenderTarget, Renderer};
+
+/// Width and depth of the ground
+const SIZE: f32 = 8.0;
+/// Just below the meshes standing on the xy plane, so they do not fight over the depth
+const HEIGHT: f32 = -0.01;
+const CELL_SIZE: f32 = 1.0;
+
+/// Vertex color layout, the cells are taken from the world position
+const SHADER: &str = "
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct GroundUniform {
+    color_a: vec4<f32>,
+    color_b: vec4<f32>,
+    cell_size: f32,
+};
+@group(1) @binding(0)
+var<uniform> ground: GroundUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @location(0) position: vec3<f32>,
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(model_matrix_0, model_matrix_1, model_matrix_2, model_matrix_3);
+    let world_position = model_matrix * vec4<f32>(position, 1.0);
+
+    var out: VertexOutput;
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = floor(in.world_position.xy / ground.cell_size);
+    // 0 for the even cells and 0.5 for the odd ones
+    let odd = fract((cell.x + cell.y) * 0.5);
+    return select(ground.color_a, ground.color_b, odd > 0.25);
+}
+";
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GroundUniform {
+    color_a: [f32; 4],
+    color_b: [f32; 4],
+    cell_size: f32,
+    _padding: [f32; 3],
+}
+
+pub struct Ground {
+    material: MaterialHandle,
+    mesh: vertex_color_shader::Mesh,
+
+    // the bind group keeps the buffer alive
+    _uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Ground {
+    fn material_desc() -> MaterialDesc
+    {
+        MaterialDesc {
+            bind_groups: vec![MaterialBindGroup::Custom(vec![wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }])],
+            pickable: false,
+            casts_shadows: false,
+            ..MaterialDesc::new("Ground Material", SHADER, vec![
+                vertex_color_shader::Vertex::desc(),
+                vertex_color_shader::Color::desc(),
+                vertex_color_shader::InstanceRaw::desc(),
+            ])
+        }
+    }
+
+    /// The material is kept when the device is lost, only the ground is created again
+    pub fn register_material<R: RenderTarget>(renderer: &mut Renderer<R>) -> MaterialHandle
+    {
+        renderer.register_material(Self::material_desc())
+            .unwrap_or_else(|diagnostic| panic!("ground material: {diagnostic}"))
+    }
+
+    pub fn new<R: RenderTarget>(renderer: &mut Renderer<R>, material: MaterialHandle) -> Self
+    {
+        let quad = geometry::Quad::new(SIZE);
+        let instance = vertex_color_shader::Instance {
+            position: glam::Vec3::new(-SIZE / 2.0, -SIZE / 2.0, HEIGHT),
+            rotation: glam::Quat::IDENTITY,
+        };
+        let mesh = vertex_color_shader::Mesh::new(
+            renderer.wgpu_renderer.device(),
+            &quad.vertices,
+            &quad.colors,
+            &quad.indices,
+            &[instance]);
+
+        let uniform = GroundUniform {
+            color_a: [0.25, 0.25, 0.25, 1.0],
+            color_b: [0.35, 0.35, 0.35, 1.0],
+            cell_size: CELL_SIZE,
+            _padding: [0.0; 3],
+        };
+        let uniform_buffer = renderer.wgpu_renderer.device().create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Ground Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = renderer.create_material_bind_group(material, 1, &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }]).expect("group 1 of the ground material is a custom one");
+
+        Self {
+            material,
+            mesh,
+
+            _uniform_buffer: uniform_buffer,
+            bind_group,
+        }
+    }
+}
+
+impl MaterialShaderDraw for Ground {
+    fn material(&self) -> MaterialHandle {
+        self.material
+    }
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        self.mesh.draw(render_pass);
+    }
+}
+
+impl Bounded for Ground {}
+impl Batched for Ground {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn material_is_valid()
+    {
+        assert_eq!(Ground::material_desc().validate(), Ok(()));
+    }
+}
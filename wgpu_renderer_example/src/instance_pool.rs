@@ -0,0 +1,392 @@
+//! Instances of a mesh which can be added, removed and updated every frame
+//!
+//! The instances are kept densely packed on the host, removing one moves the last
+//! instance into its place. Changes are collected into one dirty range which is
+//! written with a single upload, the GPU buffer is only recreated when it has to grow.
+
+use std::ops::Range;
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use wgpu_renderer::{vertex_color_shader, vertex_texture_shader};
+
+/// An instance type of one of the shaders
+pub trait PoolInstance {
+    type Raw: bytemuck::Pod;
+
+    fn to_raw(&self) -> Self::Raw;
+
+    fn transform(&self) -> glam::Mat4;
+}
+
+impl PoolInstance for vertex_color_shader::Instance {
+    type Raw = vertex_color_shader::InstanceRaw;
+
+    fn to_raw(&self) -> Self::Raw {
+        vertex_color_shader::Instance::to_raw(self)
+    }
+
+    fn transform(&self) -> glam::Mat4 {
+        glam::Mat4::from_rotation_translation(self.rotation, self.position)
+    }
+}
+
+impl PoolInstance for vertex_texture_shader::Instance {
+    type Raw = vertex_texture_shader::InstanceRaw;
+
+    fn to_raw(&self) -> Self::Raw {
+        vertex_texture_shader::Instance::to_raw(self)
+    }
+
+    fn transform(&self) -> glam::Mat4 {
+        glam::Mat4::from_rotation_translation(self.rotation, self.position)
+    }
+}
+
+/// Stays valid until the instance is removed, a handle of a removed instance is never reused
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct InstanceHandle {
+    slot: u32,
+    generation: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Slot {
+    generation: u32,
+    // position in the dense instance list, None if the slot is free
+    index: Option<u32>,
+}
+
+const MIN_CAPACITY: usize = 64;
+
+/// The host side of a pool, densely packed instances and the range changed since the last upload
+struct Instances<I: PoolInstance> {
+    instances: Vec<I>,
+    raw: Vec<I::Raw>,
+    owners: Vec<u32>,
+
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+
+    dirty: Option<Range<usize>>,
+}
+
+impl<I: PoolInstance> Instances<I> {
+    fn with_capacity(capacity: usize) -> Self
+    {
+        Self {
+            instances: Vec::with_capacity(capacity),
+            raw: Vec::with_capacity(capacity),
+            owners: Vec::with_capacity(capacity),
+
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+
+            dirty: None,
+        }
+    }
+
+    fn add(&mut self, instance: I) -> InstanceHandle
+    {
+        let index = self.instances.len() as u32;
+
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                self.slots[slot as usize].index = Some(index);
+                slot
+            }
+            None => {
+                self.slots.push(Slot { generation: 0, index: Some(index) });
+                self.slots.len() as u32 - 1
+            }
+        };
+
+        self.raw.push(instance.to_raw());
+        self.instances.push(instance);
+        self.owners.push(slot);
+        self.mark_dirty(index as usize);
+
+        InstanceHandle {
+            slot,
+            generation: self.slots[slot as usize].generation,
+        }
+    }
+
+    fn remove(&mut self, handle: InstanceHandle) -> Option<I>
+    {
+        let index = self.index(handle)?;
+
+        let slot = &mut self.slots[handle.slot as usize];
+        slot.index = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(handle.slot);
+
+        // move the last instance into the gap
+        let instance = self.instances.swap_remove(index);
+        self.raw.swap_remove(index);
+        self.owners.swap_remove(index);
+
+        if index < self.instances.len() {
+            let moved = self.owners[index];
+            self.slots[moved as usize].index = Some(index as u32);
+            self.mark_dirty(index);
+        }
+
+        Some(instance)
+    }
+
+    fn update(&mut self, handle: InstanceHandle, instance: I) -> bool
+    {
+        let Some(index) = self.index(handle) else {
+            return false;
+        };
+
+        self.raw[index] = instance.to_raw();
+        self.instances[index] = instance;
+        self.mark_dirty(index);
+
+        true
+    }
+
+    fn index(&self, handle: InstanceHandle) -> Option<usize>
+    {
+        let slot = self.slots.get(handle.slot as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+
+        slot.index.map(|index| index as usize)
+    }
+
+    fn handle(&self, index: usize) -> Option<InstanceHandle>
+    {
+        let slot = *self.owners.get(index)?;
+
+        Some(InstanceHandle {
+            slot,
+            generation: self.slots[slot as usize].generation,
+        })
+    }
+
+    fn mark_dirty(&mut self, index: usize)
+    {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => dirty.start.min(index)..dirty.end.max(index + 1),
+            None => index..index + 1,
+        });
+    }
+
+    /// The changed instances which still exist, resets the range
+    fn take_dirty(&mut self) -> Option<Range<usize>>
+    {
+        let dirty = self.dirty.take()?;
+        let end = dirty.end.min(self.raw.len());
+
+        (dirty.start < end).then_some(dirty.start..end)
+    }
+}
+
+pub struct InstancePool<I: PoolInstance> {
+    // host data
+    host: Instances<I>,
+
+    // device data
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    uploaded_len: u32,
+}
+
+impl<I: PoolInstance> InstancePool<I> {
+    pub fn new(device: &wgpu::Device) -> Self
+    {
+        Self::with_capacity(device, MIN_CAPACITY)
+    }
+
+    pub fn with_capacity(device: &wgpu::Device, capacity: usize) -> Self
+    {
+        let capacity = capacity.max(1);
+
+        Self {
+            host: Instances::with_capacity(capacity),
+
+            buffer: Self::create_buffer(device, capacity),
+            capacity,
+            uploaded_len: 0,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer
+    {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Pool Buffer"),
+            size: (capacity * std::mem::size_of::<I::Raw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Creates the buffer on a new device, all instances are written by the next upload
+    pub fn recreate(&mut self, device: &wgpu::Device)
+    {
+        self.buffer = Self::create_buffer(device, self.capacity);
+        self.uploaded_len = 0;
+        self.host.dirty = Some(0..self.host.raw.len());
+    }
+
+    pub fn add(&mut self, instance: I) -> InstanceHandle
+    {
+        self.host.add(instance)
+    }
+
+    /// Returns the removed instance, or None if the handle is stale
+    pub fn remove(&mut self, handle: InstanceHandle) -> Option<I>
+    {
+        self.host.remove(handle)
+    }
+
+    /// Returns false if the handle is stale
+    pub fn update(&mut self, handle: InstanceHandle, instance: I) -> bool
+    {
+        self.host.update(handle, instance)
+    }
+
+    pub fn get(&self, handle: InstanceHandle) -> Option<&I>
+    {
+        self.host.index(handle).map(|index| &self.host.instances[index])
+    }
+
+    /// The handle of the instance at the index of the GPU buffer, e.g. of a picked instance
+    pub fn handle(&self, index: usize) -> Option<InstanceHandle>
+    {
+        self.host.handle(index)
+    }
+
+    /// All instances in the order of the GPU buffer
+    pub fn instances(&self) -> &[I] {
+        &self.host.instances
+    }
+
+    /// Writes the changed instances to the GPU, the buffer grows to the next power of two if it is too small
+    pub fn upload(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
+    {
+        let len = self.host.raw.len();
+        if len > self.capacity {
+            self.capacity = len.next_power_of_two().max(MIN_CAPACITY);
+            self.buffer = Self::create_buffer(wgpu_renderer.device(), self.capacity);
+            self.host.dirty = Some(0..len);
+        }
+
+        // removing instances only shrinks the drawn range
+        self.uploaded_len = len as u32;
+
+        let Some(dirty) = self.host.take_dirty() else {
+            return;
+        };
+
+        let offset = (dirty.start * std::mem::size_of::<I::Raw>()) as wgpu::BufferAddress;
+        wgpu_renderer.queue().write_buffer(&self.buffer, offset, bytemuck::cast_slice(&self.host.raw[dirty]));
+    }
+
+    /// Number of instances to draw, as of the last upload
+    pub fn size(&self) -> u32 {
+        self.uploaded_len
+    }
+
+    pub fn bind_slot<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, slot: u32) {
+        render_pass.set_vertex_buffer(slot, self.buffer.slice(..));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(x: f32) -> vertex_color_shader::Instance {
+        vertex_color_shader::Instance {
+            position: glam::Vec3::new(x, 0.0, 0.0),
+            rotation: glam::Quat::IDENTITY,
+        }
+    }
+
+    fn positions(instances: &Instances<vertex_color_shader::Instance>) -> Vec<f32> {
+        instances.instances.iter().map(|instance| instance.position.x).collect()
+    }
+
+    #[test]
+    fn handles_find_their_instance()
+    {
+        let mut instances = Instances::with_capacity(4);
+        let a = instances.add(instance(1.0));
+        let b = instances.add(instance(2.0));
+
+        assert_ne!(a, b);
+        assert_eq!(instances.index(a), Some(0));
+        assert_eq!(instances.index(b), Some(1));
+    }
+
+    #[test]
+    fn stale_handles_are_rejected()
+    {
+        let mut instances = Instances::with_capacity(4);
+        let a = instances.add(instance(1.0));
+        assert!(instances.remove(a).is_some());
+
+        // the slot is reused with a new generation
+        let b = instances.add(instance(2.0));
+        assert_eq!(b.slot, a.slot);
+
+        assert!(instances.remove(a).is_none());
+        assert!(!instances.update(a, instance(3.0)));
+        assert_eq!(instances.index(a), None);
+        assert_eq!(positions(&instances), [2.0]);
+    }
+
+    #[test]
+    fn handles_follow_the_moved_instance()
+    {
+        let mut instances = Instances::with_capacity(4);
+        let a = instances.add(instance(1.0));
+        let b = instances.add(instance(2.0));
+        assert_eq!(instances.handle(1), Some(b));
+
+        instances.remove(a);
+        assert_eq!(instances.handle(0), Some(b));
+        assert_eq!(instances.handle(1), None);
+    }
+
+    #[test]
+    fn remove_moves_the_last_instance_into_the_gap()
+    {
+        let mut instances = Instances::with_capacity(4);
+        let a = instances.add(instance(1.0));
+        let b = instances.add(instance(2.0));
+        let c = instances.add(instance(3.0));
+
+        assert_eq!(instances.remove(a).map(|removed| removed.position.x), Some(1.0));
+
+        assert_eq!(positions(&instances), [3.0, 2.0]);
+        assert_eq!(instances.index(c), Some(0));
+        assert_eq!(instances.index(b), Some(1));
+        assert!(instances.update(c, instance(4.0)));
+        assert_eq!(positions(&instances), [4.0, 2.0]);
+    }
+
+    #[test]
+    fn dirty_range_covers_all_changes()
+    {
+        let mut instances = Instances::with_capacity(8);
+        let handles: Vec<InstanceHandle> = (0..6).map(|x| instances.add(instance(x as f32))).collect();
+        assert_eq!(instances.take_dirty(), Some(0..6));
+        assert_eq!(instances.take_dirty(), None);
+
+        instances.update(handles[1], instance(10.0));
+        instances.update(handles[3], instance(11.0));
+        assert_eq!(instances.take_dirty(), Some(1..4));
+
+        // the last instance moves into the gap, the removed end is not uploaded
+        instances.remove(handles[2]);
+        assert_eq!(instances.take_dirty(), Some(2..3));
+
+        instances.remove(handles[4]);
+        assert_eq!(instances.take_dirty(), None);
+    }
+}
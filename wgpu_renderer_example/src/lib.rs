@@ -4,25 +4,117 @@
 
 mod renderer;
 mod geometry;
+mod lit_shader;
 mod performance_monitor;
+mod frame_limiter;
+mod fixed_timestep;
 mod textured_quad;
+mod crosshair;
+mod ground;
+mod instance_pool;
+mod math;
+
+#[cfg(test)]
+mod golden_tests;
+#[cfg(test)]
+mod recovery_tests;
+#[cfg(test)]
+mod render_graph_tests;
 
 
 use wgpu_renderer::default_window;
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use wgpu_renderer::vertex_color_shader;
+use renderer::{Bounded, MaterialShaderDraw, RenderTarget};
+use fixed_timestep::FixedUpdate;
 use winit::event::{ElementState, MouseButton, TouchPhase, WindowEvent};
 
 #[cfg(target_arch="wasm32")]
 use wasm_bindgen::prelude::*;
 
+/// MSAA sample count of the forward pass, clamped to what the adapter supports
+const SAMPLE_COUNT: u32 = 4;
+
+/// Present mode at startup, F1 switches between the modes
+const PRESENT_MODE: wgpu::PresentMode = wgpu::PresentMode::AutoVsync;
+const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+];
+
+/// Distance a selected quad is moved with page up and page down
+const MOVE_STEP: f32 = 0.5;
+
+/// Shadow map resolutions H switches between
+const SHADOW_RESOLUTIONS: [u32; 3] = [1024, 2048, 4096];
+
+/// The post effects in the order of the chain, each one is switched with its key
+const POST_EFFECTS: [(winit::keyboard::KeyCode, renderer::PostEffectKind); 5] = [
+    (winit::keyboard::KeyCode::F5, renderer::PostEffectKind::Bloom),
+    (winit::keyboard::KeyCode::F6, renderer::PostEffectKind::Tonemapping),
+    (winit::keyboard::KeyCode::F7, renderer::PostEffectKind::ColorGrading),
+    (winit::keyboard::KeyCode::F8, renderer::PostEffectKind::Vignette),
+    (winit::keyboard::KeyCode::F9, renderer::PostEffectKind::Fxaa),
+];
+
+/// Keeps presenting without vsync from using a whole core, None renders as fast as possible
+const FRAME_LIMIT: Option<u32> = Some(240);
+/// Frame limits L switches between
+const FRAME_LIMITS: [Option<u32>; 4] = [Some(30), Some(60), Some(240), None];
+
+/// Simulation ticks per second, None advances the simulation once per frame
+const TICK_RATE: Option<u32> = Some(60);
+/// Most ticks run in one frame, the simulation slows down when frames take longer
+const MAX_CATCH_UP_STEPS: u32 = 8;
+
+const POINT_LIGHT: lit_shader::PointLight = lit_shader::PointLight {
+    position: glam::Vec3::new(-1.0, -1.5, 2.0),
+    color: glam::Vec3::new(1.0, 0.9, 0.7),
+    intensity: 4.0,
+    range: 10.0,
+};
+
+
+/// Objects of the example which can be picked
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SceneObject {
+    TexturedQuad,
+    Cube,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Selection {
+    object: SceneObject,
+    instance: u32,
+}
 
 struct WgpuRendererExample<'a>{
     scale_factor: f32,
 
-    renderer: renderer::Renderer<'a>,
+    renderer: renderer::Renderer<renderer::WindowTarget<'a>>,
     performance_monitor: performance_monitor::PerformanceMonitor,
+    frame_limiter: frame_limiter::FrameLimiter,
+    fixed_timestep: Option<fixed_timestep::FixedTimestep>,
 
     // data
     textured_quad: textured_quad::TexturedQuad,
+    cube: lit_shader::Mesh,
+    /// Kept for casting rays against the triangles of the cube
+    cube_geometry: geometry::Cube,
+    /// Unlit disc in the color of the point light, where the light is
+    light_marker: vertex_color_shader::Mesh,
+    ground: ground::Ground,
+
+    show_bounds: bool,
+    cursor_position: winit::dpi::PhysicalPosition<f64>,
+
+    // picking
+    draw_ids: Vec<(renderer::DrawId, SceneObject)>,
+    selection: Option<Selection>,
+
+    /// The device could not be recreated, nothing is drawn anymore
+    device_failed: bool,
 }
 
 impl<'a> WgpuRendererExample<'a> {
@@ -30,41 +122,248 @@ impl<'a> WgpuRendererExample<'a> {
     {
         let scale_factor = window.scale_factor() as f32;
 
-        let mut renderer = renderer::Renderer::new(window).await;
+        let mut renderer = renderer::Renderer::new(window, SAMPLE_COUNT, PRESENT_MODE).await;
         let performance_monitor = performance_monitor::PerformanceMonitor::new(
             &mut renderer.wgpu_renderer);
 
         // data
         let textured_quad = textured_quad::TexturedQuad::new(
+            &mut renderer.resources,
             &mut renderer.wgpu_renderer, 
             &renderer.texture_bind_group_layout);
+
+        let cube_geometry = geometry::Cube::new(1.0, [0.8, 0.3, 0.2]);
+        let cube = Self::create_cube(&mut renderer.resources, renderer.wgpu_renderer.device(), &cube_geometry);
+        let light_marker = Self::create_light_marker(renderer.wgpu_renderer.device());
+
+        let ground_material = ground::Ground::register_material(&mut renderer);
+        let ground = ground::Ground::new(&mut renderer, ground_material);
+
+        let mut lights = lit_shader::Lights::default();
+        lights.point_lights.push(POINT_LIGHT);
+        renderer.set_lights(lights);
+
+        renderer.set_post_effects(POST_EFFECTS.iter()
+            .map(|(_, kind)| renderer::PostEffect { kind: *kind, enabled: false })
+            .collect());
+
+        let surface_format = renderer.wgpu_renderer.surface_format();
+        let crosshair = crosshair::Crosshair::new(renderer.wgpu_renderer.device(), surface_format);
+        renderer.render_graph_mut()
+            .add_pass(renderer::RenderGraphNode::custom("crosshair", crosshair).after("composite").before("performance_monitor"))
+            .unwrap();
         
         Self {
             scale_factor,
 
             renderer,
             performance_monitor,
+            frame_limiter: frame_limiter::FrameLimiter::new(FRAME_LIMIT),
+            fixed_timestep: TICK_RATE.map(|tick_rate| fixed_timestep::FixedTimestep::new(tick_rate, MAX_CATCH_UP_STEPS)),
 
             textured_quad,
+            cube,
+            cube_geometry,
+            light_marker,
+            ground,
+
+            show_bounds: false,
+            cursor_position: winit::dpi::PhysicalPosition::new(0.0, 0.0),
+
+            draw_ids: Vec::new(),
+            selection: None,
+
+            device_failed: false,
         }
     }
+
+    fn create_cube(resources: &mut renderer::ResourceManager, 
+        device: &wgpu::Device, 
+        cube_geometry: &geometry::Cube,
+    ) -> lit_shader::Mesh 
+    {
+        let cube_instance = vertex_color_shader::Instance {
+            position: glam::Vec3::new(-2.0, 0.0, 0.5),
+            rotation: glam::Quat::from_rotation_z(0.5),
+        };
+
+        lit_shader::Mesh::new(
+            resources,
+            device, 
+            &cube_geometry.vertices, 
+            &cube_geometry.indices, 
+            &[cube_instance])
+    }
+
+    fn create_light_marker(device: &wgpu::Device) -> vertex_color_shader::Mesh 
+    {
+        let mut circle = geometry::Circle::new(0.1, 16);
+        circle.colors.fill(vertex_color_shader::Color { color: POINT_LIGHT.color.to_array() });
+        let instance = vertex_color_shader::Instance {
+            position: POINT_LIGHT.position,
+            rotation: glam::Quat::IDENTITY,
+        };
+
+        vertex_color_shader::Mesh::new(
+            device, 
+            &circle.vertices, 
+            &circle.colors, 
+            &circle.indices, 
+            &[instance])
+    }
+
+    /// Creates the device and every GPU resource again, the scene keeps its state
+    ///
+    /// After the first failure it is not tried again, e.g. the web can not request a device within a frame.
+    fn recover_device(&mut self) 
+    {
+        if self.device_failed {
+            return;
+        }
+
+        if let Err(error) = self.renderer.recover_device() {
+            log::error!("device lost: {error}, nothing is drawn anymore");
+            self.device_failed = true;
+            return;
+        }
+
+        self.performance_monitor.recreate(&mut self.renderer.wgpu_renderer);
+        self.textured_quad.recreate(
+            &mut self.renderer.resources,
+            &mut self.renderer.wgpu_renderer, 
+            &self.renderer.texture_bind_group_layout);
+        self.cube = Self::create_cube(&mut self.renderer.resources, self.renderer.wgpu_renderer.device(), &self.cube_geometry);
+        self.light_marker = Self::create_light_marker(self.renderer.wgpu_renderer.device());
+        self.ground = ground::Ground::new(&mut self.renderer, self.ground.material());
+
+        log::info!("device lost: recreated");
+    }
+
+    /// Takes the position of a cursor or touch event, it is converted like the other input
+    fn pick(&mut self, position: winit::dpi::PhysicalPosition<f64>) 
+    {
+        let Some((x, y)) = frame_position(position, self.scale_factor, self.renderer.size()) else {
+            log::warn!("pick: {position:?} is outside of the frame");
+            return;
+        };
+        if !self.renderer.click_minimap(x, y) && !self.renderer.click_gizmo(x, y) {
+            self.renderer.request_pick(x, y);
+        }
+    }
+
+    fn selection_changed(&mut self, selection: Option<Selection>) 
+    {
+        self.selection = selection;
+
+        match selection {
+            Some(selection) => log::info!("selection: {:?} instance {}", selection.object, selection.instance),
+            None => log::info!("selection: none"),
+        }
+    }
+
+    fn bounded(&self, object: SceneObject) -> &dyn renderer::Bounded 
+    {
+        match object {
+            SceneObject::TexturedQuad => &self.textured_quad,
+            SceneObject::Cube => &self.cube,
+        }
+    }
+
+    fn instance_bounds(&self, selection: Selection) -> Option<renderer::Aabb> 
+    {
+        let bounded = self.bounded(selection.object);
+        bounded.instance_bounding_volumes()
+            .and_then(|volumes| volumes.get(selection.instance as usize).copied())
+            .or_else(|| bounded.bounding_volume())
+    }
+
+    /// The object under the cursor, the ray is cast on the CPU so hovering needs no readback like picking
+    fn hover(&self) -> Option<Selection> 
+    {
+        let (x, y) = frame_position(self.cursor_position, self.scale_factor, self.renderer.size())?;
+        let ray = self.renderer.cursor_ray(x, y);
+
+        // the quad is flat, its bounds are as good as its triangles
+        let quad = self.textured_quad.instance_bounding_volumes()
+            .and_then(|volumes| ray.intersect_aabbs(volumes))
+            .map(|(instance, distance)| (distance, Selection { object: SceneObject::TexturedQuad, instance: instance as u32 }));
+        let cube = ray.intersect_instances(
+                &self.cube_geometry.vertices, 
+                &self.cube_geometry.indices, 
+                self.cube.instance_transforms().iter().copied())
+            .map(|hit| (hit.hit.distance, Selection { object: SceneObject::Cube, instance: hit.instance as u32 }));
+
+        [quad, cube].into_iter()
+            .flatten()
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, selection)| selection)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_screenshot(&mut self, image: Result<image::RgbaImage, renderer::CaptureError>) 
+    {
+        let image = match image {
+            Ok(image) => image,
+            Err(err) => {
+                log::error!("screenshot: {err}");
+                return;
+            }
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = format!("screenshot_{timestamp}.png");
+
+        match image.save(&path) {
+            Ok(()) => log::info!("screenshot: saved {path}"),
+            Err(err) => log::error!("screenshot: failed to save {path}: {err}"),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn save_screenshot(&mut self, _image: Result<image::RgbaImage, renderer::CaptureError>) 
+    {
+        // there is no file system to write to on the web
+    }
+}
+
+/// The position of an event in pixels of the frame, None if it is outside of the frame
+fn frame_position(position: winit::dpi::PhysicalPosition<f64>, 
+    scale_factor: f32, 
+    frame_size: winit::dpi::PhysicalSize<u32>,
+) -> Option<(f32, f32)>
+{
+    let position = apply_scale_factor(position, scale_factor);
+    let inside = position.x >= 0.0 && position.y >= 0.0
+        && position.x < frame_size.width as f64 && position.y < frame_size.height as f64;
+
+    inside.then_some((position.x as f32, position.y as f32))
 }
 
-#[allow(unused)]
 fn apply_scale_factor(position: winit::dpi::PhysicalPosition<f64>, scale_factor: f32) 
 -> winit::dpi::PhysicalPosition<f64> 
 {
-    cfg_if::cfg_if! {
-        // apply scale factor for the web
-        if #[cfg(target_arch = "wasm32")] {
-            let mut res = position;
-            res.x = res.x / scale_factor as f64;
-            res.y = res.y / scale_factor as f64;
-            res
-        }
-        else {
-            position
-        }
+    // apply scale factor for the web
+    if cfg!(target_arch = "wasm32") {
+        divide_by_scale_factor(position, scale_factor)
+    }
+    else {
+        position
+    }
+}
+
+fn divide_by_scale_factor(position: winit::dpi::PhysicalPosition<f64>, scale_factor: f32) 
+-> winit::dpi::PhysicalPosition<f64> 
+{
+    winit::dpi::PhysicalPosition::new(position.x / scale_factor as f64, position.y / scale_factor as f64)
+}
+
+impl<'a> FixedUpdate for WgpuRendererExample<'a>
+{
+    fn fixed_update(&mut self, dt: instant::Duration) {
+        self.renderer.fixed_update(dt);
     }
 }
 
@@ -83,13 +382,78 @@ impl<'a> default_window::DefaultWindowApp for WgpuRendererExample<'a>
     }
 
     fn update(&mut self, dt: instant::Duration) {
-        self.renderer.update(dt);
+        self.performance_monitor.watch.start(performance_monitor::WATCH_FRAME_LIMITER);
+        self.frame_limiter.wait();
+        self.performance_monitor.watch.stop(performance_monitor::WATCH_FRAME_LIMITER);
+
+        // nothing can be written to a lost device
+        if self.renderer.device_lost() {
+            self.recover_device();
+            if self.renderer.device_lost() {
+                return;
+            }
+        }
+
+        // simulation in fixed ticks, rendered between the last two,
+        // the timestep is taken out of the app while it runs the ticks on it
+        let alpha = match self.fixed_timestep.take() {
+            Some(mut fixed_timestep) => {
+                let alpha = fixed_timestep.run(self, dt);
+                self.fixed_timestep = Some(fixed_timestep);
+                alpha
+            }
+            None => {
+                self.fixed_update(dt);
+                1.0
+            }
+        };
+
+        self.renderer.update(dt, alpha);
+
+        if let Some(pick) = self.renderer.poll_pick() {
+            let selection = pick.hit.and_then(|hit| {
+                self.draw_ids.iter()
+                    .find(|(draw_id, _)| *draw_id == hit.draw_id)
+                    .map(|(_, object)| Selection { object: *object, instance: hit.instance })
+            });
+
+            if selection != self.selection {
+                self.selection_changed(selection);
+            }
+        }
+
+        if let Some(bounds) = self.selection.and_then(|selection| self.instance_bounds(selection)) {
+            self.renderer.debug_draw().overlay().aabb(bounds.min, bounds.max, [1.0, 0.6, 0.0]);
+        }
+
+        let hover = self.hover();
+        if let Some(bounds) = hover.filter(|hover| Some(*hover) != self.selection).and_then(|hover| self.instance_bounds(hover)) {
+            self.renderer.debug_draw().overlay().aabb(bounds.min, bounds.max, [0.6, 0.6, 0.6]);
+        }
+
+        // name of the hovered object next to the cursor, the screen lines start at the bottom left corner
+        let cursor = frame_position(self.cursor_position, self.scale_factor, self.renderer.size());
+        if let Some((hover, (x, y))) = hover.zip(cursor) {
+            let height = self.renderer.size().height as f32;
+            let origin = glam::Vec3::new(x + renderer::TEXT_HEIGHT, height - y, 0.0);
+            let label = format!("{:?} {}", hover.object, hover.instance);
+            self.renderer.debug_draw().screen().text(origin, renderer::TEXT_HEIGHT, f32::INFINITY, &label, [1.0, 1.0, 1.0]);
+        }
+
+        if self.show_bounds {
+            let debug_lines = self.renderer.debug_draw().world();
+            debug_lines.axes(glam::Mat4::IDENTITY, 1.0);
+            for bounds in [self.textured_quad.bounding_volume(), self.cube.bounding_volume()].into_iter().flatten() {
+                debug_lines.aabb(bounds.min, bounds.max, [1.0, 1.0, 0.0]);
+            }
+            debug_lines.sphere(POINT_LIGHT.position, 0.25, POINT_LIGHT.color.to_array());
+        }
 
         self.performance_monitor.update(&mut self.renderer.wgpu_renderer);
     }
 
     fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
-        self.performance_monitor.watch.start(2);
+        self.performance_monitor.watch.start(performance_monitor::WATCH_INPUT);
             let res = match event {
                 WindowEvent::KeyboardInput {
                     event:
@@ -103,6 +467,250 @@ impl<'a> default_window::DefaultWindowApp for WgpuRendererExample<'a>
                     self.performance_monitor.show = !self.performance_monitor.show;
                     true
                 },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F3),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    self.renderer.capture_frame();
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F4),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    self.show_bounds = !self.show_bounds;
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F10),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let visible = self.renderer.grid_visible();
+                    self.renderer.set_grid_visible(!visible);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyG),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let visible = self.renderer.gizmo_visible();
+                    self.renderer.set_gizmo_visible(!visible);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyM),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let visible = self.renderer.minimap_visible();
+                    self.renderer.set_minimap_visible(!visible);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyH),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let mut settings = self.renderer.shadow_settings().clone();
+                    settings.resolution = SHADOW_RESOLUTIONS.iter()
+                        .position(|resolution| *resolution == settings.resolution)
+                        .map_or(SHADOW_RESOLUTIONS[0], |index| SHADOW_RESOLUTIONS[(index + 1) % SHADOW_RESOLUTIONS.len()]);
+                    log::info!("shadow resolution: {}", settings.resolution);
+                    self.renderer.set_shadow_settings(settings);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyL),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let target_fps = self.frame_limiter.target_fps();
+                    let target_fps = FRAME_LIMITS.iter()
+                        .position(|limit| *limit == target_fps)
+                        .map_or(FRAME_LIMITS[0], |index| FRAME_LIMITS[(index + 1) % FRAME_LIMITS.len()]);
+                    log::info!("frame limit: {target_fps:?}");
+                    self.frame_limiter.set_target_fps(target_fps);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyN),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    self.textured_quad.add_instance(&mut self.renderer.wgpu_renderer);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Delete),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    match self.selection {
+                        Some(Selection { object: SceneObject::TexturedQuad, instance }) => {
+                            self.textured_quad.remove_instance(instance, &mut self.renderer.wgpu_renderer);
+                            self.selection_changed(None);
+                            true
+                        }
+                        _ => false,
+                    }
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(key @ (
+                                winit::keyboard::KeyCode::PageUp | 
+                                winit::keyboard::KeyCode::PageDown)),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let step = match key {
+                        winit::keyboard::KeyCode::PageUp => MOVE_STEP,
+                        _ => -MOVE_STEP,
+                    };
+                    match self.selection {
+                        Some(Selection { object: SceneObject::TexturedQuad, instance }) => 
+                            self.textured_quad.move_instance(instance, glam::Vec3::Z * step, &mut self.renderer.wgpu_renderer),
+                        _ => false,
+                    }
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::F1),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let current = self.renderer.present_mode();
+                    let next = PRESENT_MODES.iter()
+                        .position(|mode| *mode == current)
+                        .map_or(PRESENT_MODES[0], |index| PRESENT_MODES[(index + 1) % PRESENT_MODES.len()]);
+                    let applied = self.renderer.set_present_mode(next);
+                    log::info!("present mode: {applied:?} (requested {next:?})");
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyT),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let mut settings = self.renderer.post_settings();
+                    settings.tonemapper = settings.tonemapper.next();
+                    log::info!("tonemapper: {:?}", settings.tonemapper);
+                    self.renderer.set_post_settings(settings);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyE),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let mut settings = self.renderer.post_settings();
+                    settings.auto_exposure = match settings.auto_exposure {
+                        Some(_) => None,
+                        None => Some(renderer::AutoExposure::default()),
+                    };
+                    log::info!("auto exposure: {}", settings.auto_exposure.is_some());
+                    self.renderer.set_post_settings(settings);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::Tab),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => { 
+                    let layout = self.renderer.viewport_layout().next();
+                    self.renderer.set_viewport_layout(layout);
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(winit::keyboard::KeyCode::KeyR),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    log::info!("resources:\n{}", self.renderer.resources.debug_listing());
+                    true
+                },
+                WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(key),
+                            state: ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } if POST_EFFECTS.iter().any(|(effect_key, _)| effect_key == key) => { 
+                    let (_, kind) = POST_EFFECTS.iter()
+                        .find(|(effect_key, _)| effect_key == key)
+                        .unwrap();
+                    self.renderer.toggle_post_effect(*kind);
+
+                    let enabled: Vec<_> = self.renderer.post_effects().iter()
+                        .filter(|effect| effect.enabled)
+                        .map(|effect| effect.kind)
+                        .collect();
+                    log::info!("post effects: {enabled:?}");
+                    true
+                },
                 WindowEvent::KeyboardInput {
                     event:
                         winit::event::KeyEvent {
@@ -121,13 +729,19 @@ impl<'a> default_window::DefaultWindowApp for WgpuRendererExample<'a>
                     state,//ElementState::Pressed,
                     ..
                 } => {
-                    let _is_pressed = *state == ElementState::Pressed;
+                    let is_pressed = *state == ElementState::Pressed;
                     
                     // let _res = self.handle_gui_mouse_pressed(is_pressed);
 
+                    if is_pressed {
+                        self.pick(self.cursor_position);
+                    }
+
                     true
                 } 
                 WindowEvent::CursorMoved { position, .. } => {
+                    self.cursor_position = *position;
+                    self.renderer.process_cursor_moved(position.x as f32, position.y as f32);
                     let _pos = apply_scale_factor(*position, self.scale_factor);
 
                     // let _res = self.handle_gui_mouse_moved(pos.x as u32, pos.y as u32);
@@ -141,6 +755,8 @@ impl<'a> default_window::DefaultWindowApp for WgpuRendererExample<'a>
                         TouchPhase::Started => {
                             // let _res = self.handle_gui_mouse_moved(pos.x as u32, pos.y as u32);
                             // let _res = self.handle_gui_mouse_pressed(true);
+                            self.renderer.process_cursor_moved(touch.location.x as f32, touch.location.y as f32);
+                            self.pick(touch.location);
                         }
                         TouchPhase::Ended => {
                             // let _res = self.handle_gui_mouse_pressed(false);
@@ -156,16 +772,36 @@ impl<'a> default_window::DefaultWindowApp for WgpuRendererExample<'a>
                 } 
                 _ => false,
             };
-        self.performance_monitor.watch.stop(2);
+        self.performance_monitor.watch.stop(performance_monitor::WATCH_INPUT);
 
         res
     }
 
     fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        self.renderer.render(
-            &[&self.textured_quad],
-            &[],
-            &mut self.performance_monitor)
+        if self.renderer.device_lost() {
+            self.recover_device();
+            if self.renderer.device_lost() {
+                return Ok(());
+            }
+        }
+
+        let mut queue = renderer::RenderQueue::new();
+        let textured_quad_id = queue.push_transparent(&self.textured_quad);
+        let cube_id = queue.push_lit(&self.cube);
+        queue.push_color(&self.light_marker);
+        queue.push_material(&self.ground);
+        self.draw_ids = vec![
+            (textured_quad_id, SceneObject::TexturedQuad),
+            (cube_id, SceneObject::Cube),
+        ];
+
+        self.renderer.render(&mut queue, &mut self.performance_monitor)?;
+
+        if let Some(image) = self.renderer.take_captured_frame() {
+            self.save_screenshot(image);
+        }
+
+        Ok(())
     }
 
 
@@ -187,4 +823,34 @@ pub async fn run()
 
     let app = WgpuRendererExample::new(&window).await;
     default_window::run(event_loop, &window, app);
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+    use super::*;
+
+    #[test]
+    fn frame_position_is_inside_of_the_frame()
+    {
+        let size = PhysicalSize::new(800, 600);
+
+        assert_eq!(frame_position(PhysicalPosition::new(0.0, 0.0), 1.0, size), Some((0.0, 0.0)));
+        assert_eq!(frame_position(PhysicalPosition::new(799.5, 599.5), 1.0, size), Some((799.5, 599.5)));
+        assert_eq!(frame_position(PhysicalPosition::new(800.0, 10.0), 1.0, size), None);
+        assert_eq!(frame_position(PhysicalPosition::new(10.0, -1.0), 1.0, size), None);
+    }
+
+    #[test]
+    fn frame_position_with_a_scale_factor_of_2()
+    {
+        // a window of 800 x 600 logical pixels
+        let size = PhysicalSize::new(1600, 1200);
+        let position = PhysicalPosition::new(1200.0, 800.0);
+
+        let expected = if cfg!(target_arch = "wasm32") { (600.0, 400.0) } else { (1200.0, 800.0) };
+        assert_eq!(frame_position(position, 2.0, size), Some(expected));
+        assert_eq!(divide_by_scale_factor(position, 2.0), PhysicalPosition::new(600.0, 400.0));
+    }
+}
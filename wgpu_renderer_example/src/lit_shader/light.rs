@@ -0,0 +1,183 @@
+//! Light description and its uniform buffer
+//!
+
+use bytemuck::Zeroable;
+use wgpu::util::DeviceExt;
+
+/// Point lights beyond this count are ignored
+pub const MAX_POINT_LIGHTS: usize = 16;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DirectionalLight {
+    /// Direction the light travels in, does not need to be normalized
+    pub direction: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub position: glam::Vec3,
+    pub color: glam::Vec3,
+    pub intensity: f32,
+    /// The light fades out towards this distance and has no effect beyond it
+    pub range: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Lights {
+    pub ambient: glam::Vec3,
+    pub directional: DirectionalLight,
+    pub point_lights: Vec<PointLight>,
+}
+
+impl Default for Lights {
+    fn default() -> Self {
+        Self {
+            ambient: glam::Vec3::splat(0.1),
+            directional: DirectionalLight {
+                direction: glam::Vec3::new(-0.5, -1.0, -0.8),
+                color: glam::Vec3::ONE,
+                intensity: 1.0,
+            },
+            point_lights: Vec::new(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DirectionalLightRaw {
+    direction: [f32; 3],
+    intensity: f32,
+    color: [f32; 3],
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightRaw {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    view_position: [f32; 3],
+    point_light_count: u32,
+    ambient: [f32; 3],
+    _padding: f32,
+    directional: DirectionalLightRaw,
+    point_lights: [PointLightRaw; MAX_POINT_LIGHTS],
+}
+
+impl LightUniform {
+    /// The view position is needed for the specular highlights
+    pub fn new(lights: &Lights, view_position: glam::Vec3) -> Self 
+    {
+        let mut point_lights = [PointLightRaw::zeroed(); MAX_POINT_LIGHTS];
+        for (raw, light) in point_lights.iter_mut().zip(lights.point_lights.iter()) {
+            *raw = PointLightRaw {
+                position: light.position.to_array(),
+                range: light.range,
+                color: light.color.to_array(),
+                intensity: light.intensity,
+            };
+        }
+
+        let directional = &lights.directional;
+
+        Self {
+            view_position: view_position.to_array(),
+            point_light_count: lights.point_lights.len().min(MAX_POINT_LIGHTS) as u32,
+            ambient: lights.ambient.to_array(),
+            _padding: 0.0,
+            directional: DirectionalLightRaw {
+                direction: directional.direction.normalize_or_zero().to_array(),
+                intensity: directional.intensity,
+                color: directional.color.to_array(),
+                _padding: 0.0,
+            },
+            point_lights,
+        }
+    }
+}
+
+pub struct LightBindGroupLayout {
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl LightBindGroupLayout {
+    pub fn new(device: &wgpu::Device) -> Self 
+    {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        Self {
+            bind_group_layout,
+        }
+    }
+
+    pub fn get(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+}
+
+pub struct LightUniformBuffer {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl LightUniformBuffer {
+    pub fn new(device: &wgpu::Device, light_bind_group_layout: &LightBindGroupLayout) -> Self 
+    {
+        let uniform = LightUniform::new(&Lights::default(), glam::Vec3::ZERO);
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Bind Group"),
+            layout: light_bind_group_layout.get(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            buffer,
+            bind_group,
+        }
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, uniform: LightUniform) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// At the group of the material which holds the lights
+    pub fn bind_at<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, index: u32) {
+        render_pass.set_bind_group(index, &self.bind_group, &[]);
+    }
+}
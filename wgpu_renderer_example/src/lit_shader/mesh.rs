@@ -0,0 +1,126 @@
+use wgpu::util::DeviceExt;
+use wgpu_renderer::vertex_color_shader::Instance;
+
+use super::Vertex;
+use crate::renderer::{Aabb, Batched, Bounded, DrawInstances, MeshHandle, ResourceManager};
+
+pub trait LitShaderDraw {
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+/// The vertices and indices are shared with the meshes of the same geometry, the instances are its own
+pub struct Mesh {
+    geometry: MeshHandle,
+    instance_buffer: wgpu::Buffer,
+
+    num_instances: u32,
+
+    // bounds
+    transforms: Vec<glam::Mat4>,
+    local_bounds: Option<Aabb>,
+    instance_bounds: Vec<Aabb>,
+    bounds: Option<Aabb>,
+}
+
+impl Mesh {
+    pub fn new(resources: &mut ResourceManager,
+        device: &wgpu::Device,
+        vertices: &[Vertex],
+        indices: &[u32],
+        instances: &[Instance],
+    ) -> Self
+    {
+        let geometry = resources.mesh(device, "Lit Mesh", vertices, indices);
+
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lit Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mut mesh = Self {
+            geometry,
+            instance_buffer,
+
+            num_instances: instances.len() as u32,
+
+            transforms: Self::transforms(instances),
+            local_bounds: Self::local_bounds(vertices),
+            instance_bounds: Vec::new(),
+            bounds: None,
+        };
+        mesh.update_bounds();
+
+        mesh
+    }
+
+    fn transforms(instances: &[Instance]) -> Vec<glam::Mat4> {
+        instances.iter()
+            .map(|instance| glam::Mat4::from_rotation_translation(instance.rotation, instance.position))
+            .collect()
+    }
+
+    fn local_bounds(vertices: &[Vertex]) -> Option<Aabb> {
+        Aabb::from_points(vertices.iter().map(|vertex| glam::Vec3::from(vertex.position)))
+    }
+
+    fn update_bounds(&mut self) 
+    {
+        if let Some(local_bounds) = &self.local_bounds {
+            self.instance_bounds = local_bounds.instanced(self.transforms.iter().copied());
+            self.bounds = Aabb::union_all(&self.instance_bounds);
+        }
+    }
+
+    pub fn instance_transforms(&self) -> &[glam::Mat4] {
+        &self.transforms
+    }
+
+    /// At most as many instances as the mesh was created with, fewer draw fewer
+    pub fn _update_instance_buffer(&mut self, queue: &wgpu::Queue, instances: &[Instance]) 
+    {
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        let contents: &[u8] = bytemuck::cast_slice(&instance_data);
+        assert!(contents.len() as u64 <= self.instance_buffer.size(),
+            "{} instances do not fit into the instance buffer of the mesh", instances.len());
+        queue.write_buffer(&self.instance_buffer, 0, contents);
+
+        self.num_instances = instances.len() as u32;
+        self.transforms = Self::transforms(instances);
+        self.update_bounds();
+    }
+}
+
+impl LitShaderDraw for Mesh {
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) 
+    {
+        self.draw_instances(render_pass, 0..self.num_instances);
+    }
+}
+
+impl Bounded for Mesh {
+    fn bounding_volume(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn instance_bounding_volumes(&self) -> Option<&[Aabb]> {
+        self.local_bounds.map(|_| self.instance_bounds.as_slice())
+    }
+
+    fn instance_draw(&self) -> Option<&dyn DrawInstances> {
+        Some(self)
+    }
+}
+
+impl DrawInstances for Mesh {
+    fn draw_instances<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: std::ops::Range<u32>) 
+    {
+        self.geometry.bind(render_pass);
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        render_pass.draw_indexed(0..self.geometry.num_indices(), 0, instances);
+    }
+}
+
+impl Batched for Mesh {}
@@ -0,0 +1,19 @@
+//! Shader family for lit 3D meshes
+//!
+//! Vertices carry a normal and a color, which are shaded with Blinn-Phong by
+//! one directional light and up to [light::MAX_POINT_LIGHTS] point lights.
+//! The light uniform is bound at group 1, next to the camera uniform of the vertex_color_shader.
+
+mod vertex;
+mod light;
+mod mesh;
+
+pub use vertex::Vertex;
+pub use light::{
+    PointLight, 
+    Lights, 
+    LightUniform, 
+    LightBindGroupLayout, 
+    LightUniformBuffer, 
+};
+pub use mesh::{Mesh, LitShaderDraw};
@@ -0,0 +1,3 @@
+mod ray;
+
+pub use ray::Ray;
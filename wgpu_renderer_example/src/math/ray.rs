@@ -0,0 +1,335 @@
+//! Ray casting against triangles, instances and bounding volumes
+//!
+//! A CPU fallback for picking, it needs no readback from the GPU. Distances along
+//! a ray are always in world units, rays moved into the local space of an instance
+//! keep their unnormalized direction for that reason.
+
+use wgpu_renderer::{vertex_color_shader, vertex_texture_shader};
+
+use crate::lit_shader;
+use crate::renderer::Aabb;
+
+/// Vertices a ray can be cast against
+pub trait VertexPosition {
+    fn position(&self) -> glam::Vec3;
+}
+
+impl VertexPosition for glam::Vec3 {
+    fn position(&self) -> glam::Vec3 {
+        *self
+    }
+}
+
+impl VertexPosition for vertex_color_shader::Vertex {
+    fn position(&self) -> glam::Vec3 {
+        glam::Vec3::from(self.position)
+    }
+}
+
+impl VertexPosition for vertex_texture_shader::Vertex {
+    fn position(&self) -> glam::Vec3 {
+        glam::Vec3::from(self.position)
+    }
+}
+
+impl VertexPosition for lit_shader::Vertex {
+    fn position(&self) -> glam::Vec3 {
+        glam::Vec3::from(self.position)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: glam::Vec3,
+    pub direction: glam::Vec3,
+}
+
+/// The closest intersection of a ray with a triangle mesh
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RayHit {
+    /// Along the ray, in world units
+    pub distance: f32,
+    /// Index of the triangle, the first index of the triangle is at `3 * triangle`
+    pub triangle: usize,
+    /// Weights of the first, second and third vertex of the triangle
+    pub barycentrics: glam::Vec3,
+    pub position: glam::Vec3,
+}
+
+/// A hit on one instance of a mesh
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InstanceHit {
+    pub instance: usize,
+    pub hit: RayHit,
+}
+
+impl Ray {
+    /// The direction is normalized
+    pub fn new(origin: glam::Vec3, direction: glam::Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// Ray through the position in pixels for a projection with a depth range of 0 to 1
+    pub fn from_screen(view_proj: glam::Mat4, x: f32, y: f32, width: u32, height: u32) -> Self
+    {
+        let ndc_x = (x / width.max(1) as f32) * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y / height.max(1) as f32) * 2.0;
+
+        let inverse = view_proj.inverse();
+        let near = inverse.project_point3(glam::Vec3::new(ndc_x, ndc_y, 0.0));
+        let far = inverse.project_point3(glam::Vec3::new(ndc_x, ndc_y, 1.0));
+
+        Self::new(near, far - near)
+    }
+
+    pub fn at(&self, distance: f32) -> glam::Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in the space the transform maps from, distances stay the same
+    pub fn to_local(self, transform: glam::Mat4) -> Self
+    {
+        let inverse = transform.inverse();
+
+        Self {
+            origin: inverse.transform_point3(self.origin),
+            direction: inverse.transform_vector3(self.direction),
+        }
+    }
+
+    /// Distance and the weights of the second and third vertex, both sides of the triangle are hit
+    pub fn intersect_triangle(&self, a: glam::Vec3, b: glam::Vec3, c: glam::Vec3) -> Option<(f32, f32, f32)>
+    {
+        // Möller–Trumbore
+        let edge_ab = b - a;
+        let edge_ac = c - a;
+
+        let p = self.direction.cross(edge_ac);
+        let determinant = edge_ab.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = to_origin.cross(edge_ab);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge_ac.dot(q) * inverse_determinant;
+        (distance >= 0.0).then_some((distance, u, v))
+    }
+
+    /// The closest hit on a triangle list
+    pub fn intersect_mesh<V: VertexPosition>(&self, vertices: &[V], indices: &[u32]) -> Option<RayHit>
+    {
+        let mut closest: Option<RayHit> = None;
+
+        for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+            let [Some(a), Some(b), Some(c)] = [0, 1, 2].map(|corner| vertices.get(corners[corner] as usize)) else {
+                continue;
+            };
+
+            let Some((distance, u, v)) = self.intersect_triangle(a.position(), b.position(), c.position()) else {
+                continue;
+            };
+
+            if !matches!(closest, Some(closest) if closest.distance <= distance) {
+                closest = Some(RayHit {
+                    distance,
+                    triangle,
+                    barycentrics: glam::Vec3::new(1.0 - u - v, u, v),
+                    position: self.at(distance),
+                });
+            }
+        }
+
+        closest
+    }
+
+    /// The closest hit on any instance of a triangle list, positions are in world space
+    pub fn intersect_instances<V: VertexPosition>(&self,
+        vertices: &[V],
+        indices: &[u32],
+        transforms: impl IntoIterator<Item = glam::Mat4>,
+    ) -> Option<InstanceHit>
+    {
+        transforms.into_iter()
+            .enumerate()
+            .filter_map(|(instance, transform)| {
+                let mut hit = self.to_local(transform).intersect_mesh(vertices, indices)?;
+                hit.position = self.at(hit.distance);
+                Some(InstanceHit { instance, hit })
+            })
+            .min_by(|a, b| a.hit.distance.total_cmp(&b.hit.distance))
+    }
+
+    /// Distance to the box, 0 if the origin is inside
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32>
+    {
+        // slabs, divisions by 0 give infinities which compare correctly
+        let inverse_direction = self.direction.recip();
+        let t0 = (aabb.min - self.origin) * inverse_direction;
+        let t1 = (aabb.max - self.origin) * inverse_direction;
+
+        let entry = t0.min(t1).max_element().max(0.0);
+        let exit = t0.max(t1).min_element();
+
+        (entry <= exit).then_some(entry)
+    }
+
+    /// Index and distance of the closest box
+    pub fn intersect_aabbs(&self, aabbs: &[Aabb]) -> Option<(usize, f32)>
+    {
+        aabbs.iter()
+            .enumerate()
+            .filter_map(|(index, aabb)| self.intersect_aabb(aabb).map(|distance| (index, distance)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn down_at(x: f32, y: f32) -> Ray {
+        Ray::new(glam::Vec3::new(x, y, 5.0), -glam::Vec3::Z)
+    }
+
+    #[test]
+    fn screen_center_looks_along_the_camera()
+    {
+        let view = glam::Mat4::look_at_rh(glam::Vec3::new(0.0, 0.0, 10.0), glam::Vec3::ZERO, glam::Vec3::Y);
+        let projection = glam::Mat4::perspective_rh(45_f32.to_radians(), 2.0, 0.1, 100.0);
+
+        let ray = Ray::from_screen(projection * view, 400.0, 200.0, 800, 400);
+
+        assert!(ray.origin.abs_diff_eq(glam::Vec3::new(0.0, 0.0, 9.9), EPSILON));
+        assert!(ray.direction.abs_diff_eq(-glam::Vec3::Z, EPSILON));
+    }
+
+    #[test]
+    fn screen_corner_points_up_left()
+    {
+        let view = glam::Mat4::look_at_rh(glam::Vec3::new(0.0, 0.0, 10.0), glam::Vec3::ZERO, glam::Vec3::Y);
+        let projection = glam::Mat4::perspective_rh(90_f32.to_radians(), 1.0, 0.1, 100.0);
+
+        let ray = Ray::from_screen(projection * view, 0.0, 0.0, 100, 100);
+
+        let expected = glam::Vec3::new(-1.0, 1.0, -1.0).normalize();
+        assert!(ray.direction.abs_diff_eq(expected, EPSILON));
+    }
+
+    #[test]
+    fn triangle_hit_has_barycentrics()
+    {
+        let ray = down_at(0.25, 0.25);
+
+        let (distance, u, v) = ray.intersect_triangle(
+            glam::Vec3::ZERO,
+            glam::Vec3::X,
+            glam::Vec3::Y).unwrap();
+
+        assert!((distance - 5.0).abs() < EPSILON);
+        assert!((u - 0.25).abs() < EPSILON);
+        assert!((v - 0.25).abs() < EPSILON);
+    }
+
+    #[test]
+    fn triangle_behind_the_ray_is_missed()
+    {
+        let ray = Ray::new(glam::Vec3::new(0.25, 0.25, -1.0), -glam::Vec3::Z);
+
+        assert!(ray.intersect_triangle(glam::Vec3::ZERO, glam::Vec3::X, glam::Vec3::Y).is_none());
+    }
+
+    #[test]
+    fn quad_reports_triangle_and_position()
+    {
+        let quad = geometry::Quad::new(1.0);
+
+        let hit = down_at(0.25, 0.75).intersect_mesh(&quad.vertices, &quad.indices).unwrap();
+
+        assert_eq!(hit.triangle, 1);
+        assert!((hit.distance - 5.0).abs() < EPSILON);
+        assert!(hit.position.abs_diff_eq(glam::Vec3::new(0.25, 0.75, 0.0), EPSILON));
+        assert!((hit.barycentrics.dot(glam::Vec3::ONE) - 1.0).abs() < EPSILON);
+
+        assert!(down_at(1.5, 0.5).intersect_mesh(&quad.vertices, &quad.indices).is_none());
+    }
+
+    #[test]
+    fn circle_is_hit_inside_its_radius()
+    {
+        let circle = geometry::Circle::new(0.5, 32);
+
+        assert!(down_at(0.3, 0.2).intersect_mesh(&circle.vertices, &circle.indices).is_some());
+        assert!(down_at(0.5, 0.5).intersect_mesh(&circle.vertices, &circle.indices).is_none());
+    }
+
+    #[test]
+    fn closest_instance_wins()
+    {
+        let quad = geometry::Quad::new(1.0);
+        let transforms = [
+            glam::Mat4::from_translation(glam::Vec3::new(10.0, 0.0, 0.0)),
+            glam::Mat4::from_translation(glam::Vec3::new(0.0, 0.0, 1.0)),
+            glam::Mat4::from_translation(glam::Vec3::new(0.0, 0.0, 2.0)),
+        ];
+
+        let hit = down_at(0.5, 0.25).intersect_instances(&quad.vertices, &quad.indices, transforms).unwrap();
+
+        assert_eq!(hit.instance, 2);
+        assert!((hit.hit.distance - 3.0).abs() < EPSILON);
+        assert!(hit.hit.position.abs_diff_eq(glam::Vec3::new(0.5, 0.25, 2.0), EPSILON));
+    }
+
+    #[test]
+    fn scaled_instance_keeps_world_distances()
+    {
+        let quad = geometry::Quad::new(1.0);
+        let transform = glam::Mat4::from_scale_rotation_translation(
+            glam::Vec3::splat(4.0),
+            glam::Quat::IDENTITY,
+            glam::Vec3::new(-2.0, -2.0, 1.0));
+
+        let hit = down_at(1.5, 1.5).intersect_instances(&quad.vertices, &quad.indices, [transform]).unwrap();
+
+        assert!((hit.hit.distance - 4.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn aabb_entry_distance()
+    {
+        let aabb = Aabb::new(glam::Vec3::splat(-1.0), glam::Vec3::splat(1.0));
+
+        assert_eq!(down_at(0.0, 0.0).intersect_aabb(&aabb), Some(4.0));
+        assert_eq!(down_at(2.0, 0.0).intersect_aabb(&aabb), None);
+        assert_eq!(Ray::new(glam::Vec3::ZERO, glam::Vec3::X).intersect_aabb(&aabb), Some(0.0));
+    }
+
+    #[test]
+    fn closest_aabb_wins()
+    {
+        let aabbs = [
+            Aabb::new(glam::Vec3::new(-1.0, -1.0, -1.0), glam::Vec3::new(1.0, 1.0, 0.0)),
+            Aabb::new(glam::Vec3::new(-1.0, -1.0, 1.0), glam::Vec3::new(1.0, 1.0, 2.0)),
+            Aabb::new(glam::Vec3::new(5.0, 5.0, 3.0), glam::Vec3::new(6.0, 6.0, 4.0)),
+        ];
+
+        assert_eq!(down_at(0.0, 0.0).intersect_aabbs(&aabbs), Some((1, 3.0)));
+    }
+}
@@ -1,13 +1,28 @@
 use wgpu_renderer::{performance_monitor, vertex_color_shader::{self, VertexColorShaderDraw}, renderer::WgpuRendererInterface};
 
+use crate::renderer::CullingStats;
+
 const WATCHPOINTS_SIZE: usize  = 5;
 
+// watch points, the graph shows them in this order
+pub const WATCH_GET_FRAME: usize = 0;
+pub const WATCH_RENDER: usize = 1;
+pub const WATCH_INPUT: usize = 2;
+/// CPU time of recording the shadow pass, the GPU time of the pass is not measured
+pub const WATCH_SHADOW_ENCODE: usize = 3;
+pub const WATCH_FRAME_LIMITER: usize = 4;
+
 pub struct PerformanceMonitor 
 {
     pub watch: performance_monitor::Watch<5>,
     graph_host: performance_monitor::Graph,
     graph_device: vertex_color_shader::Mesh,
 
+    /// Reported by the renderer for the last frame
+    pub culling: CullingStats,
+    culling_bars: CullingBars,
+    culling_device: vertex_color_shader::Mesh,
+
     pub show: bool,
 }
 
@@ -17,26 +32,68 @@ impl PerformanceMonitor {
         
         let watch: performance_monitor::Watch<WATCHPOINTS_SIZE> = performance_monitor::Watch::new(); 
         let graph_host = performance_monitor::Graph::new(WATCHPOINTS_SIZE);
+        let graph_device = Self::create_graph_device(wgpu_renderer, &graph_host);
+
+        let culling = CullingStats::default();
+        let culling_bars = CullingBars::new(&graph_host);
+        let culling_device = Self::create_culling_device(wgpu_renderer, &culling_bars, &culling);
+
+        Self {
+            watch,
+            graph_host,
+            graph_device,
+
+            culling,
+            culling_bars,
+            culling_device,
+
+            show: false,
+        }
+    }
+
+    fn create_graph_device(wgpu_renderer: &mut impl WgpuRendererInterface, 
+        graph_host: &performance_monitor::Graph,
+    ) -> vertex_color_shader::Mesh
+    {
         let graph_instance = vertex_color_shader::Instance{
             position: glam::Vec3::ZERO,
             rotation: glam::Quat::IDENTITY,
         };
         let graph_instances = [graph_instance];
-        let graph_device = vertex_color_shader::Mesh::new(
+
+        vertex_color_shader::Mesh::new(
             wgpu_renderer.device(),
             graph_host.vertices.as_slice(),
             graph_host.colors.as_slice(),
             graph_host.indices.as_slice(),
             &graph_instances,
-        );
+        )
+    }
 
-        Self {
-            watch,
-            graph_host,
-            graph_device,
+    fn create_culling_device(wgpu_renderer: &mut impl WgpuRendererInterface, 
+        culling_bars: &CullingBars,
+        culling: &CullingStats,
+    ) -> vertex_color_shader::Mesh
+    {
+        let instance = vertex_color_shader::Instance{
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+        };
 
-            show: false,
-        }
+        vertex_color_shader::Mesh::new(
+            wgpu_renderer.device(),
+            &culling_bars.vertices(culling),
+            &CullingBars::colors(),
+            &CullingBars::indices(),
+            &[instance],
+        )
+    }
+
+    /// Creates the graph meshes on a new device from the host graph
+    pub fn recreate(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
+    {
+        self.graph_device = Self::create_graph_device(wgpu_renderer, &self.graph_host);
+        self.culling_device = Self::create_culling_device(wgpu_renderer, &self.culling_bars, &self.culling);
     }
 
     pub fn update(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
@@ -44,6 +101,90 @@ impl PerformanceMonitor {
         self.watch.update();
         self.watch.update_viewer(&mut self.graph_host);
         self.graph_device.update_vertex_buffer(wgpu_renderer.queue(), self.graph_host.vertices.as_slice());
+        self.culling_device.update_vertex_buffer(wgpu_renderer.queue(), &self.culling_bars.vertices(&self.culling));
+    }
+}
+
+/// Two bars right of the graph, objects and instances, split into the drawn and the culled share
+struct CullingBars {
+    left: f32,
+    bottom: f32,
+    height: f32,
+}
+
+impl CullingBars {
+    const WIDTH: f32 = 15.0;
+    const SPACING: f32 = 5.0;
+
+    const DRAWN: [f32; 3] = [0.2, 0.8, 0.2];
+    const CULLED: [f32; 3] = [0.8, 0.2, 0.2];
+
+    fn new(graph_host: &performance_monitor::Graph) -> Self
+    {
+        let xs = graph_host.vertices.iter().map(|vertex| vertex.position[0]);
+        let ys = graph_host.vertices.iter().map(|vertex| vertex.position[1]);
+        let right = xs.fold(0.0, f32::max);
+        let bottom = ys.clone().fold(f32::INFINITY, f32::min);
+        let top = ys.fold(f32::NEG_INFINITY, f32::max);
+
+        let (bottom, height) = if bottom < top { (bottom, top - bottom) } else { (0.0, 50.0) };
+
+        Self {
+            left: right + Self::SPACING,
+            bottom,
+            height,
+        }
+    }
+
+    /// Share of the drawn ones, 1 if there was nothing to draw
+    fn drawn_share(drawn: u32, culled: u32) -> f32
+    {
+        match drawn + culled {
+            0 => 1.0,
+            total => drawn as f32 / total as f32,
+        }
+    }
+
+    /// A drawn quad below a culled quad per bar
+    fn vertices(&self, culling: &CullingStats) -> [vertex_color_shader::Vertex; 16]
+    {
+        let shares = [
+            Self::drawn_share(culling.drawn_objects, culling.culled_objects),
+            Self::drawn_share(culling.drawn_instances, culling.culled_instances),
+        ];
+
+        let quad = |left: f32, bottom: f32, top: f32| [
+            [left, bottom, 0.0],
+            [left + Self::WIDTH, bottom, 0.0],
+            [left + Self::WIDTH, top, 0.0],
+            [left, top, 0.0],
+        ];
+
+        let mut positions = Vec::with_capacity(16);
+        for (index, share) in shares.into_iter().enumerate() {
+            let left = self.left + index as f32 * (Self::WIDTH + Self::SPACING);
+            let split = self.bottom + share * self.height;
+
+            positions.extend(quad(left, self.bottom, split));
+            positions.extend(quad(left, split, self.bottom + self.height));
+        }
+
+        std::array::from_fn(|index| vertex_color_shader::Vertex { position: positions[index] })
+    }
+
+    fn colors() -> [vertex_color_shader::Color; 16]
+    {
+        std::array::from_fn(|index| vertex_color_shader::Color {
+            color: if (index / 4) % 2 == 0 { Self::DRAWN } else { Self::CULLED },
+        })
+    }
+
+    fn indices() -> [u32; 24]
+    {
+        std::array::from_fn(|index| {
+            let quad = (index / 6) as u32 * 4;
+            quad + [0, 1, 2, 2, 3, 0][index % 6]
+        })
     }
 }
 
@@ -51,6 +192,37 @@ impl VertexColorShaderDraw for PerformanceMonitor {
     fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
         if self.show{
             self.graph_device.draw(render_pass);
+            self.culling_device.draw(render_pass);
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drawn_share_of_the_stats()
+    {
+        assert_eq!(CullingBars::drawn_share(3, 1), 0.75);
+        assert_eq!(CullingBars::drawn_share(0, 4), 0.0);
+        assert_eq!(CullingBars::drawn_share(0, 0), 1.0);
+    }
+
+    #[test]
+    fn bars_split_at_the_drawn_share()
+    {
+        let bars = CullingBars { left: 100.0, bottom: 10.0, height: 40.0 };
+        let culling = CullingStats { drawn_objects: 1, culled_objects: 1, drawn_instances: 4, culled_instances: 0 };
+
+        let vertices = bars.vertices(&culling);
+
+        // objects, the drawn quad ends half way up
+        assert_eq!(vertices[2].position[1], 30.0);
+        assert_eq!(vertices[7].position[1], 50.0);
+        // instances, the culled quad is empty
+        assert_eq!(vertices[10].position[1], 50.0);
+        assert_eq!(vertices[12].position[1], 50.0);
+        assert_eq!(vertices[8].position[0], 100.0 + CullingBars::WIDTH + CullingBars::SPACING);
+    }
+}
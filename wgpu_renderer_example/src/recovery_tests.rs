@@ -0,0 +1,111 @@
+//! Recovery from surface errors and the loss of the device
+//!
+//! The errors are injected into the offscreen target. After the device is lost the
+//! scene is created again from the host data and has to look like before the loss.
+//! Like the golden tests they need an adapter and are ignored by default, run them
+//! with `cargo test -- --include-ignored`.
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use wgpu_renderer::vertex_color_shader;
+
+use crate::geometry;
+use crate::lit_shader;
+use crate::performance_monitor::PerformanceMonitor;
+use crate::renderer::{FrameStatus, OffscreenRenderer, RenderQueue, Renderer, ResourceManager};
+use crate::textured_quad::TexturedQuad;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+const SAMPLE_COUNT: u32 = 1;
+
+fn create_renderer() -> Renderer<OffscreenRenderer>
+{
+    pollster::block_on(Renderer::new_headless(WIDTH, HEIGHT, SAMPLE_COUNT))
+        .expect("recovery: no adapter available")
+}
+
+fn create_cube(resources: &mut ResourceManager, device: &wgpu::Device) -> lit_shader::Mesh
+{
+    let cube = geometry::Cube::new(1.0, [0.8, 0.3, 0.2]);
+    let instances = [vertex_color_shader::Instance {
+        position: glam::Vec3::new(0.0, 0.0, 0.5),
+        rotation: glam::Quat::from_rotation_z(0.5),
+    }];
+
+    lit_shader::Mesh::new(resources, device, &cube.vertices, &cube.indices, &instances)
+}
+
+fn render(
+    renderer: &mut Renderer<OffscreenRenderer>,
+    performance_monitor: &mut PerformanceMonitor,
+    textured_quad: &TexturedQuad,
+    cube: &lit_shader::Mesh,
+) -> Result<FrameStatus, wgpu::SurfaceError>
+{
+    let mut queue = RenderQueue::new();
+    queue.push_transparent(textured_quad);
+    queue.push_lit(cube);
+
+    renderer.update(instant::Duration::ZERO, 1.0);
+    renderer.render(&mut queue, performance_monitor)
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn surface_errors_skip_the_frame()
+{
+    let mut renderer = create_renderer();
+    let mut performance_monitor = PerformanceMonitor::new(&mut renderer.wgpu_renderer);
+    let textured_quad = TexturedQuad::new(&mut renderer.resources, &mut renderer.wgpu_renderer, &renderer.texture_bind_group_layout);
+    let cube = create_cube(&mut renderer.resources, renderer.wgpu_renderer.device());
+
+    let cases = [
+        (wgpu::SurfaceError::Lost, Ok(FrameStatus::Reconfigured)),
+        (wgpu::SurfaceError::Outdated, Ok(FrameStatus::Reconfigured)),
+        (wgpu::SurfaceError::Timeout, Ok(FrameStatus::Skipped)),
+        (wgpu::SurfaceError::OutOfMemory, Err(wgpu::SurfaceError::OutOfMemory)),
+    ];
+
+    for (error, expected) in cases {
+        renderer.wgpu_renderer.inject_surface_error(error.clone());
+        let status = render(&mut renderer, &mut performance_monitor, &textured_quad, &cube);
+        assert_eq!(status, expected, "{error:?}");
+
+        // the next frame is drawn again
+        let status = render(&mut renderer, &mut performance_monitor, &textured_quad, &cube);
+        assert_eq!(status, Ok(FrameStatus::Presented), "after {error:?}");
+    }
+
+    assert_eq!(renderer.wgpu_renderer.reconfigured(), 2);
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn device_loss_recreates_every_resource()
+{
+    let mut renderer = create_renderer();
+    let mut performance_monitor = PerformanceMonitor::new(&mut renderer.wgpu_renderer);
+    performance_monitor.show = true;
+    let mut textured_quad = TexturedQuad::new(&mut renderer.resources, &mut renderer.wgpu_renderer, &renderer.texture_bind_group_layout);
+    let mut cube = create_cube(&mut renderer.resources, renderer.wgpu_renderer.device());
+
+    render(&mut renderer, &mut performance_monitor, &textured_quad, &cube).unwrap();
+    let before = renderer.read_frame();
+
+    renderer.wgpu_renderer.lose_device();
+    assert!(renderer.device_lost());
+    let status = render(&mut renderer, &mut performance_monitor, &textured_quad, &cube);
+    assert_eq!(status, Ok(FrameStatus::DeviceLost));
+
+    renderer.recover_device().unwrap();
+    assert!(!renderer.device_lost());
+    performance_monitor.recreate(&mut renderer.wgpu_renderer);
+    textured_quad.recreate(&mut renderer.resources, &mut renderer.wgpu_renderer, &renderer.texture_bind_group_layout);
+    cube = create_cube(&mut renderer.resources, renderer.wgpu_renderer.device());
+
+    let status = render(&mut renderer, &mut performance_monitor, &textured_quad, &cube);
+    assert_eq!(status, Ok(FrameStatus::Presented));
+    let after = renderer.read_frame();
+
+    assert!(before == after, "the scene differs after the device was recreated");
+}
@@ -0,0 +1,100 @@
+//! Custom passes of the render graph and their attachments
+//!
+//! Like the golden tests they need an adapter and are ignored by default, run them
+//! with `cargo test -- --include-ignored`.
+
+use std::rc::Rc;
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+
+use crate::performance_monitor::PerformanceMonitor;
+use crate::renderer::{AttachmentDesc, OffscreenRenderer, PassContext, RenderGraphNode, RenderGraphPass};
+use crate::renderer::{RenderQueue, Renderer};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+const SAMPLE_COUNT: u32 = 1;
+
+const ATTACHMENT: &str = "custom";
+const ATTACHMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+/// Clears the declared attachment
+struct ClearPass;
+
+impl RenderGraphPass for ClearPass {
+    fn record(&self, context: &mut PassContext)
+    {
+        let attachment = context.attachment(ATTACHMENT).expect("the attachment is declared");
+        context.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear Attachment Render Pass"),
+            color_attachments: &[Some(attachment.color_attachment(wgpu::LoadOp::Clear(wgpu::Color {
+                r: 1.0,
+                g: 0.0,
+                b: 1.0,
+                a: 1.0,
+            })))],
+            depth_stencil_attachment: None,
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        });
+    }
+}
+
+/// Copies the first texel of the attachment into a buffer the test maps
+struct ReadPass {
+    buffer: Rc<wgpu::Buffer>,
+}
+
+impl RenderGraphPass for ReadPass {
+    fn record(&self, context: &mut PassContext)
+    {
+        let texture = context.attachment_texture(ATTACHMENT).expect("the attachment is declared");
+        context.encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout::default(),
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            });
+    }
+}
+
+#[test]
+#[ignore = "needs an adapter"]
+fn custom_pass_writes_and_reads_its_attachment()
+{
+    let mut renderer = pollster::block_on(Renderer::<OffscreenRenderer>::new_headless(WIDTH, HEIGHT, SAMPLE_COUNT))
+        .expect("render graph: no adapter available");
+    let mut performance_monitor = PerformanceMonitor::new(&mut renderer.wgpu_renderer);
+
+    let buffer = Rc::new(renderer.wgpu_renderer.device().create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Attachment Readback Buffer"),
+        size: 4,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    }));
+
+    let desc = AttachmentDesc::new(ATTACHMENT_FORMAT,
+        wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC);
+    renderer.render_graph_mut()
+        .add_pass(RenderGraphNode::custom("clear", ClearPass)
+            .after("forward")
+            .with_attachment(ATTACHMENT, desc))
+        .unwrap();
+    renderer.render_graph_mut()
+        .add_pass(RenderGraphNode::custom("read", ReadPass { buffer: buffer.clone() }).after("clear"))
+        .unwrap();
+
+    renderer.update(instant::Duration::ZERO, 1.0);
+    renderer.render(&mut RenderQueue::new(), &mut performance_monitor).unwrap();
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    renderer.wgpu_renderer.device().poll(wgpu::Maintain::Wait);
+
+    assert_eq!(&slice.get_mapped_range()[..], &[255, 0, 255, 255]);
+}
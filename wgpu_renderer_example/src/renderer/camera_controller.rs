@@ -10,7 +10,7 @@ use wgpu_renderer::renderer::camera::Camera;
 
 use std::f32::consts::FRAC_PI_2;
 
-const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
+pub const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
 #[derive(Debug)]
 pub struct CameraController {
@@ -77,6 +77,19 @@ impl CameraController {
         }
     }
 
+    /// Stops all movement, for when the input goes to another controller while keys are held
+    pub fn release(&mut self) {
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        self.scroll = 0.0;
+    }
+
     pub fn _process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
         self.rotate_horizontal = mouse_dx as f32;
         self.rotate_vertical = mouse_dy as f32;
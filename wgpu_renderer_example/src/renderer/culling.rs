@@ -0,0 +1,314 @@
+//! Frustum culling of the drawables passed to the renderer
+//!
+//! Drawables report world space bounding boxes for themselves and optionally for
+//! each of their instances. Drawables outside of the view frustum are skipped,
+//! of the others only the visible runs of instances are drawn.
+
+use std::ops::Range;
+
+use wgpu_renderer::vertex_color_shader::{self, VertexColorShaderDraw};
+use wgpu_renderer::vertex_texture_shader::VertexTextureShaderDraw;
+use crate::lit_shader::LitShaderDraw;
+use super::render_queue::Batched;
+use super::material::MaterialShaderDraw;
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: glam::Vec3, max: glam::Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// None if there are no points
+    pub fn from_points(points: impl IntoIterator<Item = glam::Vec3>) -> Option<Self>
+    {
+        points.into_iter().fold(None, |aabb: Option<Aabb>, point| Some(match aabb {
+            Some(aabb) => Aabb::new(aabb.min.min(point), aabb.max.max(point)),
+            None => Aabb::new(point, point),
+        }))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    /// None if there are no boxes
+    pub fn union_all(boxes: &[Aabb]) -> Option<Aabb> {
+        boxes.iter().copied().reduce(|a, b| a.union(&b))
+    }
+
+    pub fn center(&self) -> glam::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> glam::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// The box around the transformed box
+    pub fn transformed(&self, transform: glam::Mat4) -> Aabb
+    {
+        let center = transform.transform_point3(self.center());
+
+        let half_extents = self.half_extents();
+        let extent = transform.x_axis.truncate().abs() * half_extents.x
+            + transform.y_axis.truncate().abs() * half_extents.y
+            + transform.z_axis.truncate().abs() * half_extents.z;
+
+        Aabb::new(center - extent, center + extent)
+    }
+
+    /// One box per transform
+    pub fn instanced(&self, transforms: impl IntoIterator<Item = glam::Mat4>) -> Vec<Aabb> {
+        transforms.into_iter().map(|transform| self.transformed(transform)).collect()
+    }
+}
+
+/// The six planes of the view frustum, normals point inwards
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [glam::Vec4; 6],
+}
+
+impl Frustum {
+    /// Expects a projection with a depth range of 0 to 1
+    pub fn from_view_proj(view_proj: glam::Mat4) -> Self
+    {
+        let row0 = view_proj.row(0);
+        let row1 = view_proj.row(1);
+        let row2 = view_proj.row(2);
+        let row3 = view_proj.row(3);
+
+        let planes = [
+            row3 + row0, // left
+            row3 - row0, // right
+            row3 + row1, // bottom
+            row3 - row1, // top
+            row2,        // near
+            row3 - row2, // far
+        ].map(|plane| plane / plane.truncate().length().max(f32::EPSILON));
+
+        Self { planes }
+    }
+
+    /// Conservative, boxes close to a corner of the frustum may pass although they are outside
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool
+    {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let radius = half_extents.dot(normal.abs());
+            normal.dot(center) + plane.w >= -radius
+        })
+    }
+}
+
+/// World space bounding volumes of a drawable
+pub trait Bounded {
+    /// None if the drawable is always drawn
+    fn bounding_volume(&self) -> Option<Aabb> {
+        None
+    }
+
+    /// One box per instance in the order of the instance buffer,
+    /// the visible instances are drawn with [Bounded::instance_draw]
+    fn instance_bounding_volumes(&self) -> Option<&[Aabb]> {
+        None
+    }
+
+    /// None if the drawable can only be drawn whole, then its instances are not culled
+    fn instance_draw(&self) -> Option<&dyn DrawInstances> {
+        None
+    }
+}
+
+/// Drawables which can draw a part of their instance buffer
+pub trait DrawInstances {
+    /// Draws only the given range of the instance buffer, after [Batched::bind_texture]
+    fn draw_instances<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: Range<u32>);
+}
+
+pub trait TextureDrawable: VertexTextureShaderDraw + Bounded + Batched {}
+impl<T: VertexTextureShaderDraw + Bounded + Batched + ?Sized> TextureDrawable for T {}
+
+pub trait ColorDrawable: VertexColorShaderDraw + Bounded + Batched {}
+impl<T: VertexColorShaderDraw + Bounded + Batched + ?Sized> ColorDrawable for T {}
+
+pub trait LitDrawable: LitShaderDraw + Bounded + Batched {}
+impl<T: LitShaderDraw + Bounded + Batched + ?Sized> LitDrawable for T {}
+
+pub trait MaterialDrawable: MaterialShaderDraw + Bounded + Batched {}
+impl<T: MaterialShaderDraw + Bounded + Batched + ?Sized> MaterialDrawable for T {}
+
+/// Meshes of wgpu_renderer do not know their bounds and do not expose their buffers,
+/// they are drawn whole
+impl Bounded for vertex_color_shader::Mesh {}
+
+/// Drawables and instances of the last frame
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub drawn_objects: u32,
+    pub culled_objects: u32,
+    pub drawn_instances: u32,
+    pub culled_instances: u32,
+}
+
+impl std::ops::AddAssign for CullingStats {
+    fn add_assign(&mut self, other: Self) {
+        self.drawn_objects += other.drawn_objects;
+        self.culled_objects += other.culled_objects;
+        self.drawn_instances += other.drawn_instances;
+        self.culled_instances += other.culled_instances;
+    }
+}
+
+/// Draws the mesh if it is visible, falls back to `draw` if it has no instance volumes
+///
+/// With a range only these instances are considered, drawables without instance volumes
+/// draw the whole range with [Bounded::instance_draw]. Drawables which can only be drawn
+/// whole are drawn once with `draw` and the range is ignored.
+pub fn draw_culled<'a, D: Bounded + ?Sized>(
+    frustum: &Frustum,
+    stats: &mut CullingStats,
+    render_pass: &mut wgpu::RenderPass<'a>,
+    mesh: &'a D,
+    instances: Option<Range<u32>>,
+    draw: impl FnOnce(&'a D, &mut wgpu::RenderPass<'a>),
+)
+{
+    let Some(instance_draw) = mesh.instance_draw() else {
+        if mesh.bounding_volume().is_some_and(|volume| !frustum.intersects_aabb(&volume)) {
+            stats.culled_objects += 1;
+        }
+        else {
+            stats.drawn_objects += 1;
+            draw(mesh, render_pass);
+        }
+        return;
+    };
+
+    let offset = instances.as_ref().map_or(0, |range| range.start);
+    let instance_volumes = mesh.instance_bounding_volumes().map(|volumes| match &instances {
+        Some(range) => {
+            let end = (range.end as usize).min(volumes.len());
+            &volumes[(range.start as usize).min(end)..end]
+        }
+        None => volumes,
+    });
+
+    if let Some(volume) = mesh.bounding_volume() {
+        if !frustum.intersects_aabb(&volume) {
+            stats.culled_objects += 1;
+            stats.culled_instances += instance_volumes.map_or(0, |volumes| volumes.len() as u32);
+            return;
+        }
+    }
+    stats.drawn_objects += 1;
+
+    let Some(instance_volumes) = instance_volumes else {
+        match instances {
+            Some(range) => instance_draw.draw_instances(render_pass, range),
+            None => draw(mesh, render_pass),
+        }
+        return;
+    };
+
+    // one draw per run of visible instances
+    let mut run_start = None;
+    for (index, volume) in instance_volumes.iter().enumerate() {
+        if frustum.intersects_aabb(volume) {
+            stats.drawn_instances += 1;
+            run_start.get_or_insert(offset + index as u32);
+        }
+        else {
+            stats.culled_instances += 1;
+            if let Some(start) = run_start.take() {
+                instance_draw.draw_instances(render_pass, start..offset + index as u32);
+            }
+        }
+    }
+
+    if let Some(start) = run_start {
+        instance_draw.draw_instances(render_pass, start..offset + instance_volumes.len() as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frustum() -> Frustum
+    {
+        // looks from z = 5 down -z, near 1 and far 10
+        let view = glam::Mat4::look_at_rh(glam::Vec3::new(0.0, 0.0, 5.0), glam::Vec3::ZERO, glam::Vec3::Y);
+        let proj = glam::Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 10.0);
+        Frustum::from_view_proj(proj * view)
+    }
+
+    fn unit_box(center: glam::Vec3) -> Aabb {
+        Aabb::new(center - glam::Vec3::splat(0.5), center + glam::Vec3::splat(0.5))
+    }
+
+    #[test]
+    fn box_inside_intersects()
+    {
+        assert!(frustum().intersects_aabb(&unit_box(glam::Vec3::ZERO)));
+    }
+
+    #[test]
+    fn boxes_outside_are_culled()
+    {
+        let frustum = frustum();
+
+        assert!(!frustum.intersects_aabb(&unit_box(glam::Vec3::new(20.0, 0.0, 0.0))), "right");
+        assert!(!frustum.intersects_aabb(&unit_box(glam::Vec3::new(0.0, -20.0, 0.0))), "below");
+        assert!(!frustum.intersects_aabb(&unit_box(glam::Vec3::new(0.0, 0.0, 10.0))), "behind");
+        assert!(!frustum.intersects_aabb(&unit_box(glam::Vec3::new(0.0, 0.0, -10.0))), "beyond far");
+    }
+
+    #[test]
+    fn box_on_a_plane_intersects()
+    {
+        let frustum = frustum();
+
+        // the near plane is at z = 4, the right plane at x = z distance
+        assert!(frustum.intersects_aabb(&unit_box(glam::Vec3::new(0.0, 0.0, 4.0))));
+        assert!(frustum.intersects_aabb(&unit_box(glam::Vec3::new(5.0, 0.0, 0.0))));
+        assert!(frustum.intersects_aabb(&Aabb::new(glam::Vec3::new(-50.0, -0.5, -0.5), glam::Vec3::new(50.0, 0.5, 0.5))));
+    }
+
+    #[test]
+    fn from_points_and_union()
+    {
+        assert_eq!(Aabb::from_points([]), None);
+        assert_eq!(Aabb::union_all(&[]), None);
+
+        let aabb = Aabb::from_points([glam::Vec3::new(1.0, -2.0, 0.0), glam::Vec3::new(-1.0, 2.0, 3.0)]).unwrap();
+        assert_eq!(aabb, Aabb::new(glam::Vec3::new(-1.0, -2.0, 0.0), glam::Vec3::new(1.0, 2.0, 3.0)));
+
+        let union = Aabb::union_all(&[unit_box(glam::Vec3::ZERO), unit_box(glam::Vec3::X * 4.0)]).unwrap();
+        assert_eq!(union, Aabb::new(glam::Vec3::new(-0.5, -0.5, -0.5), glam::Vec3::new(4.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn transformed_box_encloses_the_rotated_box()
+    {
+        let transform = glam::Mat4::from_translation(glam::Vec3::new(20.0, 0.0, 0.0))
+            * glam::Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4);
+        let aabb = unit_box(glam::Vec3::ZERO).transformed(transform);
+
+        let half_diagonal = std::f32::consts::SQRT_2 * 0.5;
+        assert!((aabb.center() - glam::Vec3::new(20.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((aabb.half_extents() - glam::Vec3::new(half_diagonal, half_diagonal, 0.5)).length() < 1e-5);
+
+        // moved out of the frustum by the transform
+        assert!(!frustum().intersects_aabb(&aabb));
+    }
+}
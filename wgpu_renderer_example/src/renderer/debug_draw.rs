@@ -0,0 +1,450 @@
+//! Immediate mode debug lines
+//!
+//! Any code holding the renderer can add lines during `update`, they are collected
+//! into one dynamic line list per mode and drawn by the next `render`. Uploading
+//! clears them, so lines have to be added again every frame to stay visible.
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use wgpu_renderer::vertex_color_shader::{Color, Instance, Vertex};
+
+const MIN_CAPACITY: usize = 256;
+
+/// Segments of a full circle of [DebugLines::sphere]
+const CIRCLE_SEGMENTS: u32 = 32;
+
+pub const RED: [f32; 3] = [1.0, 0.0, 0.0];
+pub const GREEN: [f32; 3] = [0.0, 1.0, 0.0];
+pub const BLUE: [f32; 3] = [0.0, 0.0, 1.0];
+
+/// Glyph height of [DebugLines::text] in pixels which stays readable
+pub const TEXT_HEIGHT: f32 = 16.0;
+
+// segments of the glyphs of [DebugLines::text], like a sixteen segment display with two dots,
+// in half glyph heights from the bottom left corner
+const SEGMENTS: [([f32; 2], [f32; 2]); 18] = [
+    ([0.0, 2.0], [0.5, 2.0]),   // A1, top left
+    ([0.5, 2.0], [1.0, 2.0]),   // A2, top right
+    ([1.0, 2.0], [1.0, 1.0]),   // B, right upper
+    ([1.0, 1.0], [1.0, 0.0]),   // C, right lower
+    ([1.0, 0.0], [0.5, 0.0]),   // D2, bottom right
+    ([0.5, 0.0], [0.0, 0.0]),   // D1, bottom left
+    ([0.0, 0.0], [0.0, 1.0]),   // E, left lower
+    ([0.0, 1.0], [0.0, 2.0]),   // F, left upper
+    ([0.0, 1.0], [0.5, 1.0]),   // G1, middle left
+    ([0.5, 1.0], [1.0, 1.0]),   // G2, middle right
+    ([0.0, 2.0], [0.5, 1.0]),   // H, diagonal upper left
+    ([0.5, 2.0], [0.5, 1.0]),   // I, center upper
+    ([1.0, 2.0], [0.5, 1.0]),   // J, diagonal upper right
+    ([0.5, 1.0], [1.0, 0.0]),   // K, diagonal lower right
+    ([0.5, 1.0], [0.5, 0.0]),   // L, center lower
+    ([0.5, 1.0], [0.0, 0.0]),   // M, diagonal lower left
+    ([0.5, 0.0], [0.5, 0.25]),  // DOT
+    ([0.5, 0.75], [0.5, 1.0]),  // COLON, the upper dot
+];
+
+const A1: u32 = 1 << 0;
+const A2: u32 = 1 << 1;
+const B: u32 = 1 << 2;
+const C: u32 = 1 << 3;
+const D2: u32 = 1 << 4;
+const D1: u32 = 1 << 5;
+const E: u32 = 1 << 6;
+const F: u32 = 1 << 7;
+const G1: u32 = 1 << 8;
+const G2: u32 = 1 << 9;
+const H: u32 = 1 << 10;
+const I: u32 = 1 << 11;
+const J: u32 = 1 << 12;
+const K: u32 = 1 << 13;
+const L: u32 = 1 << 14;
+const M: u32 = 1 << 15;
+const DOT: u32 = 1 << 16;
+const COLON: u32 = 1 << 17;
+
+const A: u32 = A1 | A2;
+const D: u32 = D1 | D2;
+const G: u32 = G1 | G2;
+
+/// Segments of the character, lower case letters look like upper case ones,
+/// characters without a glyph are drawn as a question mark
+fn glyph(character: char) -> u32
+{
+    match character.to_ascii_uppercase() {
+        ' ' | '\t' => 0,
+        '0' => A | B | C | D | E | F | J | M,
+        '1' => B | C | J,
+        '2' => A | B | G | E | D,
+        '3' => A | B | G2 | C | D,
+        '4' => F | G | B | C,
+        '5' => A | F | G | C | D,
+        '6' => A | F | E | D | C | G,
+        '7' => A | B | C,
+        '8' => A | B | C | D | E | F | G,
+        '9' => A | B | C | D | F | G,
+        'A' => A | B | C | E | F | G,
+        'B' => A | B | C | D | I | L | G2,
+        'C' => A | F | E | D,
+        'D' => A | B | C | D | I | L,
+        'E' => A | F | E | D | G1,
+        'F' => A | F | E | G1,
+        'G' => A | F | E | D | C | G2,
+        'H' => F | E | B | C | G,
+        'I' => A | I | L | D,
+        'J' => B | C | D | E,
+        'K' => F | E | G1 | J | K,
+        'L' => F | E | D,
+        'M' => F | E | B | C | H | J,
+        'N' => F | E | B | C | H | K,
+        'O' => A | B | C | D | E | F,
+        'P' => A | B | F | E | G,
+        'Q' => A | B | C | D | E | F | K,
+        'R' => A | B | F | E | G | K,
+        'S' => A | F | G | C | D,
+        'T' => A | I | L,
+        'U' => F | E | D | C | B,
+        'V' => F | E | M | J,
+        'W' => F | E | B | C | M | K,
+        'X' => H | J | K | M,
+        'Y' => H | J | L,
+        'Z' => A | J | M | D,
+        '.' | ',' => DOT,
+        ':' | ';' => DOT | COLON,
+        '-' => G,
+        '_' => D,
+        '+' => G | I | L,
+        '=' => G | D,
+        '*' => G | H | I | J | K | L | M,
+        '/' => J | M,
+        '\\' => H | K,
+        '(' | '<' | '{' => J | K,
+        ')' | '>' | '}' => H | M,
+        '[' => A2 | I | L | D2,
+        ']' => A1 | I | L | D1,
+        '|' => I | L,
+        '!' => I | DOT,
+        '\'' | '"' | '`' => I,
+        '#' => B | C | E | F | G | I | L,
+        _ => A | B | G2 | L,
+    }
+}
+
+/// Start and end of each segment of the text, see [DebugLines::text]
+fn text_segments(origin: glam::Vec3, height: f32, max_width: f32, text: &str) -> (Vec<(glam::Vec3, glam::Vec3)>, f32)
+{
+    let scale = height * 0.5;
+    let advance = height * 0.75;
+    let line_height = height * 1.5;
+
+    let mut segments = Vec::new();
+    let mut x = origin.x;
+    let mut top = origin.y;
+
+    for character in text.chars() {
+        if character == '\n' || x + advance > origin.x + max_width.max(advance) {
+            x = origin.x;
+            top -= line_height;
+            if character == '\n' {
+                continue;
+            }
+        }
+
+        let glyph = glyph(character);
+        let corner = glam::Vec3::new(x, top - height, origin.z);
+        for (index, (a, b)) in SEGMENTS.iter().enumerate() {
+            if glyph & (1 << index) != 0 {
+                segments.push((
+                    corner + glam::Vec3::new(a[0], a[1], 0.0) * scale,
+                    corner + glam::Vec3::new(b[0], b[1], 0.0) * scale,
+                ));
+            }
+        }
+
+        x += advance;
+    }
+
+    (segments, top - line_height)
+}
+
+/// Lines of one mode, positions are in world space or in pixels for the screen space mode
+pub struct DebugLines {
+    // host data
+    vertices: Vec<Vertex>,
+    colors: Vec<Color>,
+
+    // device data
+    vertex_buffer: wgpu::Buffer,
+    color_buffer: wgpu::Buffer,
+    capacity: usize,
+    uploaded_len: u32,
+}
+
+impl DebugLines {
+    fn new(device: &wgpu::Device) -> Self
+    {
+        Self {
+            vertices: Vec::new(),
+            colors: Vec::new(),
+
+            vertex_buffer: Self::create_buffer::<Vertex>(device, "Debug Vertex Buffer", MIN_CAPACITY),
+            color_buffer: Self::create_buffer::<Color>(device, "Debug Color Buffer", MIN_CAPACITY),
+            capacity: MIN_CAPACITY,
+            uploaded_len: 0,
+        }
+    }
+
+    fn create_buffer<T>(device: &wgpu::Device, label: &str, capacity: usize) -> wgpu::Buffer
+    {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn line(&mut self, a: glam::Vec3, b: glam::Vec3, color: [f32; 3])
+    {
+        self.vertices.push(Vertex { position: a.into() });
+        self.vertices.push(Vertex { position: b.into() });
+        self.colors.push(Color { color });
+        self.colors.push(Color { color });
+    }
+
+    pub fn aabb(&mut self, min: glam::Vec3, max: glam::Vec3, color: [f32; 3])
+    {
+        let corner = |x: bool, y: bool, z: bool| glam::Vec3::new(
+            if x { max.x } else { min.x },
+            if y { max.y } else { min.y },
+            if z { max.z } else { min.z },
+        );
+
+        for a in [false, true] {
+            for b in [false, true] {
+                self.line(corner(false, a, b), corner(true, a, b), color);
+                self.line(corner(a, false, b), corner(a, true, b), color);
+                self.line(corner(a, b, false), corner(a, b, true), color);
+            }
+        }
+    }
+
+    /// One circle around each axis
+    pub fn sphere(&mut self, center: glam::Vec3, radius: f32, color: [f32; 3])
+    {
+        self.circle(center, glam::Vec3::X * radius, glam::Vec3::Y * radius, color);
+        self.circle(center, glam::Vec3::Y * radius, glam::Vec3::Z * radius, color);
+        self.circle(center, glam::Vec3::Z * radius, glam::Vec3::X * radius, color);
+    }
+
+    /// Circle in the plane of the two axes, their lengths are the radii
+    pub fn circle(&mut self, center: glam::Vec3, axis_a: glam::Vec3, axis_b: glam::Vec3, color: [f32; 3])
+    {
+        let point = |segment: u32| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + axis_a * angle.cos() + axis_b * angle.sin()
+        };
+
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.line(point(segment), point(segment + 1), color);
+        }
+    }
+
+    /// Line with a head at `to`, the head is a fifth of the length
+    pub fn arrow(&mut self, from: glam::Vec3, to: glam::Vec3, color: [f32; 3])
+    {
+        self.line(from, to, color);
+
+        let direction = to - from;
+        let length = direction.length();
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        let direction = direction / length;
+        let (side_a, side_b) = direction.any_orthonormal_pair();
+        let head = length * 0.2;
+        let base = to - direction * head;
+
+        for side in [side_a, -side_a, side_b, -side_b] {
+            self.line(to, base + side * head * 0.5, color);
+        }
+    }
+
+    /// The x, y and z axis of the transform in red, green and blue
+    pub fn axes(&mut self, transform: glam::Mat4, size: f32)
+    {
+        let origin = transform.transform_point3(glam::Vec3::ZERO);
+
+        self.arrow(origin, transform.transform_point3(glam::Vec3::X * size), RED);
+        self.arrow(origin, transform.transform_point3(glam::Vec3::Y * size), GREEN);
+        self.arrow(origin, transform.transform_point3(glam::Vec3::Z * size), BLUE);
+    }
+
+    /// Text with line segments, `origin` is the top left corner of the first line
+    ///
+    /// Lines wrap at `max_width` and at line breaks. Returns the top of the next line.
+    pub fn text(&mut self, origin: glam::Vec3, height: f32, max_width: f32, text: &str, color: [f32; 3]) -> f32
+    {
+        let (segments, next_line) = text_segments(origin, height, max_width, text);
+        for (a, b) in segments {
+            self.line(a, b, color);
+        }
+
+        next_line
+    }
+
+    fn clear(&mut self) {
+        self.vertices.clear();
+        self.colors.clear();
+    }
+
+    fn upload(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
+    {
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_buffer::<Vertex>(wgpu_renderer.device(), "Debug Vertex Buffer", self.capacity);
+            self.color_buffer = Self::create_buffer::<Color>(wgpu_renderer.device(), "Debug Color Buffer", self.capacity);
+        }
+
+        self.uploaded_len = self.vertices.len() as u32;
+        if self.vertices.is_empty() {
+            return;
+        }
+
+        wgpu_renderer.queue().write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        wgpu_renderer.queue().write_buffer(&self.color_buffer, 0, bytemuck::cast_slice(&self.colors));
+    }
+
+    /// Expects a line list pipeline of the vertex color shader with its camera bound
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instance_buffer: &'a wgpu::Buffer)
+    {
+        if self.uploaded_len == 0 {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.color_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, instance_buffer.slice(..));
+        render_pass.draw(0..self.uploaded_len, 0..1);
+    }
+}
+
+pub struct DebugDraw {
+    world: DebugLines,
+    overlay: DebugLines,
+    screen: DebugLines,
+
+    // identity transform for all lines
+    instance_buffer: wgpu::Buffer,
+}
+
+impl DebugDraw {
+    pub fn new(device: &wgpu::Device) -> Self
+    {
+        use wgpu::util::DeviceExt;
+
+        let instance = Instance {
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+        };
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug Instance Buffer"),
+            contents: bytemuck::cast_slice(&[instance.to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        Self {
+            world: DebugLines::new(device),
+            overlay: DebugLines::new(device),
+            screen: DebugLines::new(device),
+
+            instance_buffer,
+        }
+    }
+
+    /// World space lines hidden behind the scene
+    pub fn world(&mut self) -> &mut DebugLines {
+        &mut self.world
+    }
+
+    /// World space lines drawn on top of the scene
+    pub fn overlay(&mut self) -> &mut DebugLines {
+        &mut self.overlay
+    }
+
+    /// Lines in pixels with the orthographic camera, drawn on top of the scene
+    pub fn screen(&mut self) -> &mut DebugLines {
+        &mut self.screen
+    }
+
+    /// Writes the lines of this frame to the GPU and clears them for the next frame
+    pub fn upload(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
+    {
+        for lines in [&mut self.world, &mut self.overlay, &mut self.screen] {
+            lines.upload(wgpu_renderer);
+            lines.clear();
+        }
+    }
+
+    /// True if the last upload had no lines
+    pub fn is_empty(&self) -> bool {
+        self.world.uploaded_len == 0 && self.overlay.uploaded_len == 0 && self.screen.uploaded_len == 0
+    }
+
+    pub fn draw_world<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.world.draw(render_pass, &self.instance_buffer);
+    }
+
+    pub fn draw_overlay<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.overlay.draw(render_pass, &self.instance_buffer);
+    }
+
+    pub fn draw_screen<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.screen.draw(render_pass, &self.instance_buffer);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_ignore_the_case()
+    {
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph(' '), 0);
+        assert_eq!(glyph('1'), B | C | J);
+    }
+
+    #[test]
+    fn unknown_characters_are_question_marks()
+    {
+        assert_eq!(glyph('~'), glyph('?'));
+        assert_ne!(glyph('~'), 0);
+    }
+
+    #[test]
+    fn text_advances_and_wraps()
+    {
+        let origin = glam::Vec3::new(10.0, 100.0, 0.0);
+
+        // the segments of "1" on the second line, the first is wrapped after two characters
+        let (segments, next_line) = text_segments(origin, 16.0, 24.0, "-- 1");
+        let ones: Vec<_> = segments.iter().filter(|(a, _)| a.y < 100.0 - 16.0).collect();
+        assert_eq!(ones.len(), 3);
+        assert!(ones.iter().all(|(a, b)| a.x >= 10.0 && b.x <= 10.0 + 24.0));
+        assert_eq!(next_line, 100.0 - 16.0 * 3.0);
+    }
+
+    #[test]
+    fn line_breaks_start_a_new_line()
+    {
+        let origin = glam::Vec3::new(0.0, 0.0, 0.0);
+
+        let (segments, next_line) = text_segments(origin, 10.0, 1000.0, "-\n-");
+
+        assert_eq!(segments.len(), 4);
+        assert_eq!(segments[2].0, glam::Vec3::new(0.0, -15.0 - 5.0, 0.0));
+        assert_eq!(next_line, -30.0);
+    }
+}
@@ -0,0 +1,164 @@
+//! Copies a rendered texture back to the host
+//!
+//! A frame which is captured is drawn into a [CaptureTexture] instead of the surface,
+//! which can not be copied from. It is then copied into the surface and back to the host,
+//! so the image is exactly the frame which was presented.
+
+/// Why a frame could not be read back
+#[derive(Debug)]
+pub enum CaptureError {
+    /// Only 8 bit RGBA and BGRA formats are converted
+    UnsupportedFormat(wgpu::TextureFormat),
+    /// The buffer could not be mapped, e.g. because the device was lost
+    Map(wgpu::BufferAsyncError),
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::UnsupportedFormat(format) => write!(f, "{format:?} can not be converted to rgba"),
+            CaptureError::Map(error) => write!(f, "reading the frame failed: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+/// Target of a captured frame in the format and size of the surface
+pub struct CaptureTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl CaptureTexture {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, size: winit::dpi::PhysicalSize<u32>) -> Self
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Color Texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+        }
+    }
+}
+
+/// Readback of a texture into a mappable buffer
+///
+/// Record the copy with [FrameCapture::new], submit the encoder and then
+/// read the image with [FrameCapture::read].
+pub struct FrameCapture {
+    buffer: wgpu::Buffer,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl FrameCapture {
+    pub fn new(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) -> Self
+    {
+        let format = texture.format();
+        let width = texture.width();
+        let height = texture.height();
+
+        // rows of a texture copy must be aligned to 256 bytes
+        let unpadded_bytes_per_row = width * 4;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Capture Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Self {
+            buffer,
+            format,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Blocks until the copy has finished
+    pub fn read(self, device: &wgpu::Device) -> Result<image::RgbaImage, CaptureError>
+    {
+        let swap_red_blue = match self.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(CaptureError::UnsupportedFormat(format)),
+        };
+
+        let buffer_slice = self.buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+            // the receiver waits below and is only gone if reading panicked
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+
+        // the callback is dropped without being called if the device is lost
+        receiver.recv()
+            .unwrap_or(Err(wgpu::BufferAsyncError))
+            .map_err(CaptureError::Map)?;
+
+        let unpadded_bytes_per_row = (self.width * 4) as usize;
+        let mut pixels = Vec::<u8>::with_capacity(unpadded_bytes_per_row * self.height as usize);
+        {
+            let data = buffer_slice.get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+            }
+        }
+        self.buffer.unmap();
+
+        if swap_red_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .expect("the pixels are unpadded to the size of the image"))
+    }
+}
@@ -0,0 +1,194 @@
+//! XYZ orientation gizmo in the top right corner of the active viewport
+//!
+//! The axes are drawn into a small viewport with their own camera, which looks at
+//! the origin from the direction of the main camera. The ends of the axes can be
+//! clicked to look along them.
+
+use wgpu_renderer::renderer;
+use wgpu_renderer::vertex_color_shader::{self, Color, Vertex, VertexColorShaderDraw};
+
+use super::viewport::ViewportRect;
+
+/// Width and height of the viewport in pixels
+const GIZMO_SIZE: u32 = 100;
+const GIZMO_MARGIN: u32 = 10;
+
+/// Clicks closer than this to the end of an axis hit it, in pixels
+const HIT_RADIUS: f32 = 12.0;
+
+const CAMERA_DISTANCE: f32 = 5.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoAxis {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl GizmoAxis {
+    const ALL: [GizmoAxis; 6] = [
+        GizmoAxis::PositiveX,
+        GizmoAxis::NegativeX,
+        GizmoAxis::PositiveY,
+        GizmoAxis::NegativeY,
+        GizmoAxis::PositiveZ,
+        GizmoAxis::NegativeZ,
+    ];
+
+    pub fn direction(&self) -> glam::Vec3 {
+        match self {
+            GizmoAxis::PositiveX => glam::Vec3::X,
+            GizmoAxis::NegativeX => -glam::Vec3::X,
+            GizmoAxis::PositiveY => glam::Vec3::Y,
+            GizmoAxis::NegativeY => -glam::Vec3::Y,
+            GizmoAxis::PositiveZ => glam::Vec3::Z,
+            GizmoAxis::NegativeZ => -glam::Vec3::Z,
+        }
+    }
+}
+
+pub struct OrientationGizmo {
+    pub visible: bool,
+
+    camera: renderer::camera::Camera,
+    projection: renderer::camera::Projection,
+    camera_uniform: vertex_color_shader::CameraUniform,
+    camera_uniform_buffer: vertex_color_shader::CameraUniformBuffer,
+
+    mesh: vertex_color_shader::Mesh,
+}
+
+impl OrientationGizmo {
+    pub fn new(device: &wgpu::Device, camera_bind_group_layout: &vertex_color_shader::CameraBindGroupLayout) -> Self
+    {
+        let camera = renderer::camera::Camera::new(
+            cgmath::Point3::new(0.0, 0.0, CAMERA_DISTANCE),
+            cgmath::Deg(-90.0),
+            cgmath::Deg(0.0));
+        let projection = renderer::camera::Projection::new(GIZMO_SIZE, GIZMO_SIZE, cgmath::Deg(30.0), 0.1, 10.0);
+
+        let camera_uniform = vertex_color_shader::CameraUniform::new();
+        let camera_uniform_buffer = vertex_color_shader::CameraUniformBuffer::new(device, camera_bind_group_layout);
+
+        let (vertices, colors, indices) = Self::geometry();
+        let instances = [vertex_color_shader::Instance {
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+        }];
+        let mesh = vertex_color_shader::Mesh::new(device, &vertices, &colors, &indices, &instances);
+
+        Self {
+            visible: true,
+
+            camera,
+            projection,
+            camera_uniform,
+            camera_uniform_buffer,
+
+            mesh,
+        }
+    }
+
+    /// Line list of the axes, the negative ones are dimmed and the positive ones end in a cross
+    fn geometry() -> (Vec<Vertex>, Vec<Color>, Vec<u32>)
+    {
+        let mut vertices = Vec::new();
+        let mut colors = Vec::new();
+        let mut indices = Vec::new();
+
+        let mut line = |a: glam::Vec3, b: glam::Vec3, color: [f32; 3]| {
+            indices.push(vertices.len() as u32);
+            indices.push(vertices.len() as u32 + 1);
+            vertices.push(Vertex { position: a.into() });
+            vertices.push(Vertex { position: b.into() });
+            colors.push(Color { color });
+            colors.push(Color { color });
+        };
+
+        let axes = [
+            (glam::Vec3::X, [1.0, 0.2, 0.2]),
+            (glam::Vec3::Y, [0.2, 1.0, 0.2]),
+            (glam::Vec3::Z, [0.3, 0.4, 1.0]),
+        ];
+
+        for (axis, color) in axes {
+            let dimmed = color.map(|channel| channel * 0.35);
+            line(glam::Vec3::ZERO, axis, color);
+            line(glam::Vec3::ZERO, -axis, dimmed);
+
+            for (cross_axis, _) in axes {
+                if cross_axis != axis {
+                    line(axis - cross_axis * 0.1, axis + cross_axis * 0.1, color);
+                }
+            }
+        }
+
+        (vertices, colors, indices)
+    }
+
+    /// Follows the orientation of the main camera
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &renderer::camera::Camera)
+    {
+        let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
+        let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
+        let forward = cgmath::Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin);
+
+        self.camera.position = cgmath::Point3::new(0.0, 0.0, 0.0) - forward * CAMERA_DISTANCE;
+        self.camera.yaw = camera.yaw;
+        self.camera.pitch = camera.pitch;
+
+        self.camera_uniform.update_view_proj(&self.camera, &self.projection);
+        self.camera_uniform_buffer.update(queue, self.camera_uniform);
+    }
+
+    /// x, y and size of the gizmo in the top right corner of the area, None if the area is too small
+    fn viewport(area: ViewportRect) -> Option<(u32, u32, u32)>
+    {
+        let extent = GIZMO_SIZE + GIZMO_MARGIN;
+        if area.width < extent || area.height < extent {
+            return None;
+        }
+
+        Some((area.x + area.width - extent, area.y + GIZMO_MARGIN, GIZMO_SIZE))
+    }
+
+    /// The axis whose end is under the position, in physical pixels of the frame
+    pub fn hit_test(&self, area: ViewportRect, x: f32, y: f32) -> Option<GizmoAxis>
+    {
+        if !self.visible {
+            return None;
+        }
+
+        let (viewport_x, viewport_y, size) = Self::viewport(area)?;
+        let view_proj: [[f32; 4]; 4] = (self.projection.calc_matrix() * self.camera.calc_matrix()).into();
+        let view_proj = glam::Mat4::from_cols_array_2d(&view_proj);
+
+        GizmoAxis::ALL.iter()
+            .filter_map(|axis| {
+                let ndc = view_proj.project_point3(axis.direction());
+                let screen_x = viewport_x as f32 + (ndc.x * 0.5 + 0.5) * size as f32;
+                let screen_y = viewport_y as f32 + (0.5 - ndc.y * 0.5) * size as f32;
+                let distance = glam::Vec2::new(screen_x - x, screen_y - y).length();
+
+                // the end nearest to the camera wins if ends overlap
+                (distance <= HIT_RADIUS).then_some((*axis, ndc.z))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(axis, _)| axis)
+    }
+
+    /// Expects a line list pipeline of the vertex color shader, drawn in the top right corner of the area
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, area: ViewportRect)
+    {
+        let Some((x, y, size)) = Self::viewport(area) else {
+            return;
+        };
+
+        render_pass.set_viewport(x as f32, y as f32, size as f32, size as f32, 0.0, 1.0);
+        self.camera_uniform_buffer.bind(render_pass);
+        self.mesh.draw(render_pass);
+    }
+}
@@ -0,0 +1,157 @@
+//! Infinite ground grid on the z = 0 plane
+//!
+//! Drawn as a fullscreen triangle after the opaque meshes, the shader intersects
+//! the view rays with the plane and writes the depth of the hit, so the grid is
+//! hidden behind and blended over the scene.
+
+use wgpu::util::DeviceExt;
+
+use super::pipeline::Pipeline;
+
+#[derive(Copy, Clone, Debug)]
+pub struct GridSettings {
+    /// Distance between the minor lines
+    pub minor_spacing: f32,
+    /// Distance between the major lines, a multiple of the minor spacing
+    pub major_spacing: f32,
+    /// The grid fades out between half of this distance to the camera and the distance
+    pub fade_distance: f32,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            minor_spacing: 1.0,
+            major_spacing: 10.0,
+            fade_distance: 60.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridUniform {
+    view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+    params: [f32; 4],
+}
+
+pub struct Grid {
+    pub settings: GridSettings,
+    pub visible: bool,
+
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: Pipeline,
+    /// One uniform buffer and bind group per viewport
+    uniforms: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+}
+
+impl Grid {
+    /// The pipeline draws into the scene
+    pub fn new(device: &wgpu::Device, scene_format: wgpu::TextureFormat, sample_count: u32) -> Self
+    {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Grid Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline = Pipeline::new_grid(device, &bind_group_layout, scene_format, sample_count);
+
+        Self {
+            settings: GridSettings::default(),
+            visible: true,
+
+            bind_group_layout,
+            pipeline,
+            uniforms: Vec::new(),
+        }
+    }
+
+    fn create_uniform(&self, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::BindGroup)
+    {
+        let uniform = GridUniform {
+            view_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            inv_view_proj: glam::Mat4::IDENTITY.to_cols_array_2d(),
+            camera_position: [0.0; 4],
+            params: [0.0; 4],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grid Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (uniform_buffer, bind_group)
+    }
+
+    /// Creates the uniforms of new viewports
+    pub fn prepare(&mut self, device: &wgpu::Device, viewports: usize)
+    {
+        while self.uniforms.len() < viewports {
+            let uniform = self.create_uniform(device);
+            self.uniforms.push(uniform);
+        }
+    }
+
+    /// Writes the camera of the viewport, call [Grid::prepare] before
+    pub fn update(&self,
+        queue: &wgpu::Queue,
+        viewport: usize,
+        view_proj: glam::Mat4,
+        camera_position: glam::Vec3,
+    )
+    {
+        let Some((uniform_buffer, _)) = self.uniforms.get(viewport) else {
+            return;
+        };
+
+        let uniform = GridUniform {
+            view_proj: view_proj.to_cols_array_2d(),
+            inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+            camera_position: camera_position.extend(1.0).into(),
+            params: [
+                self.settings.minor_spacing.max(f32::EPSILON),
+                self.settings.major_spacing.max(f32::EPSILON),
+                self.settings.fade_distance.max(f32::EPSILON),
+                0.0,
+            ],
+        };
+
+        queue.write_buffer(uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Expects the rect of the viewport to be set
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, viewport: usize)
+    {
+        let Some((_, bind_group)) = self.uniforms.get(viewport) else {
+            return;
+        };
+
+        self.pipeline.bind(render_pass);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,636 @@
+//! Materials registered by the app and the built in ones, each with its own shader and pipeline state
+//!
+//! A material describes everything a pipeline needs except the formats and the sample
+//! count. Its source is validated with naga when it is registered. Its pipeline is created
+//! before the first frame it is drawn in and rebuilt when one of them changes. Meshes refer
+//! to their material by a handle. Only host data is kept in the description, so everything
+//! can be created again on a new device.
+//!
+//! Materials reading the position at location 0 and the instance transform at locations 5
+//! to 8 can be drawn into the picking target and the shadow map as well. Those pipelines
+//! mirror the bind groups of the material, so meshes bind their groups the same way in
+//! every pass, the renderer binds empty groups in place of the lights and shadows.
+
+use wgpu_renderer::{vertex_color_shader, vertex_texture_shader};
+
+use crate::lit_shader;
+use super::pipeline::Pipeline;
+use super::render_queue::QueuePipeline;
+use super::shader_validation::{self, ShaderDiagnostic};
+
+const LIT_SHADER: &str = include_str!("shaders/lit_shader.wgsl");
+
+/// Bind groups of a material after the camera, which is always group 0
+pub enum MaterialBindGroup {
+    /// Lights of the lit shader, bound by the renderer
+    Lights,
+    /// Shadow map and cascades, bound by the renderer
+    Shadows,
+    /// Layout of the vertex_texture_shader, the mesh binds its texture
+    Texture,
+    /// Bound by the mesh, with bind groups created by [super::Renderer::create_material_bind_group]
+    Custom(Vec<wgpu::BindGroupLayoutEntry>),
+}
+
+/// The texture a material draws into
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterialTarget {
+    /// The scene in the scene format, before post processing
+    Scene,
+    /// The frame in the surface format, after post processing
+    Frame,
+}
+
+pub struct MaterialDesc {
+    pub label: String,
+    /// WGSL with the entry points `vs_main` and `fs_main`
+    pub source: String,
+    pub vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>,
+    /// Groups 1 and up
+    pub bind_groups: Vec<MaterialBindGroup>,
+    pub topology: wgpu::PrimitiveTopology,
+    /// Materials which blend are drawn back to front after the opaque ones
+    pub blend: Option<wgpu::BlendState>,
+    pub depth_compare: wgpu::CompareFunction,
+    pub depth_write_enabled: bool,
+    pub target: MaterialTarget,
+    /// Drawn into the picking target, needs the vertex layouts of [MaterialDesc::has_depth_pass_layouts]
+    pub pickable: bool,
+    /// Drawn into the shadow map, needs the same vertex layouts as picking
+    pub casts_shadows: bool,
+}
+
+impl MaterialDesc {
+    /// Opaque triangles into the scene with depth test and write, without further bind groups,
+    /// pickable and casting shadows if the vertex layouts allow it
+    pub fn new(label: &str, source: &str, vertex_layouts: Vec<wgpu::VertexBufferLayout<'static>>) -> Self
+    {
+        let depth_pass = Self::has_depth_pass_layouts(&vertex_layouts);
+
+        Self {
+            label: label.to_string(),
+            source: source.to_string(),
+            vertex_layouts,
+            bind_groups: Vec::new(),
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            blend: Some(wgpu::BlendState::REPLACE),
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: true,
+            target: MaterialTarget::Scene,
+            pickable: depth_pass,
+            casts_shadows: depth_pass,
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.blend.is_some_and(|blend| blend != wgpu::BlendState::REPLACE)
+    }
+
+    /// The picking and shadow shaders read the position at location 0 and the instance transform at locations 5 to 8
+    pub fn has_depth_pass_layouts(vertex_layouts: &[wgpu::VertexBufferLayout]) -> bool
+    {
+        let attribute = |location: u32| vertex_layouts.iter()
+            .flat_map(|layout| layout.attributes.iter().map(move |attribute| (layout.step_mode, attribute.format, attribute.shader_location)))
+            .find(|(_, _, shader_location)| *shader_location == location)
+            .map(|(step_mode, format, _)| (step_mode, format));
+
+        attribute(0) == Some((wgpu::VertexStepMode::Vertex, wgpu::VertexFormat::Float32x3))
+            && (5..=8).all(|location| attribute(location) == Some((wgpu::VertexStepMode::Instance, wgpu::VertexFormat::Float32x4)))
+    }
+
+    /// Validates the source with naga and checks the entry points and the vertex layouts of the depth passes
+    pub fn validate(&self) -> Result<(), ShaderDiagnostic>
+    {
+        let module = shader_validation::validate_wgsl(&self.label, &self.source)?;
+
+        for (name, stage) in [("vs_main", naga::ShaderStage::Vertex), ("fs_main", naga::ShaderStage::Fragment)] {
+            if !shader_validation::has_entry_point(&module, name, stage) {
+                return Err(ShaderDiagnostic::new(&self.label, None, format!("missing {stage:?} entry point {name}")));
+            }
+        }
+
+        if (self.pickable || self.casts_shadows) && !Self::has_depth_pass_layouts(&self.vertex_layouts) {
+            return Err(ShaderDiagnostic::new(&self.label, None,
+                "picking and shadows need the position at location 0 and the instance transform at locations 5 to 8".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Refers to a material of the [Materials] it was registered with
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialHandle(u32);
+
+impl MaterialHandle {
+    #[cfg(test)]
+    pub fn new(index: u32) -> Self {
+        Self(index)
+    }
+
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Meshes drawn with a registered material, they bind their buffers and the custom bind groups
+pub trait MaterialShaderDraw {
+    fn material(&self) -> MaterialHandle;
+
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+/// Layouts of the bind groups the renderer owns
+pub struct RendererLayouts<'a> {
+    pub camera: &'a wgpu::BindGroupLayout,
+    pub lights: &'a wgpu::BindGroupLayout,
+    pub shadows: &'a wgpu::BindGroupLayout,
+    pub texture: &'a wgpu::BindGroupLayout,
+    /// Group 0 of the shadow pass
+    pub cascade: &'a wgpu::BindGroupLayout,
+    /// The last group of the picking pass
+    pub pick_object: &'a wgpu::BindGroupLayout,
+}
+
+/// Formats of the targets the pipelines are built for
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MaterialFormats {
+    pub scene: wgpu::TextureFormat,
+    pub frame: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+impl MaterialFormats {
+    fn format(&self, target: MaterialTarget) -> wgpu::TextureFormat {
+        match target {
+            MaterialTarget::Scene => self.scene,
+            MaterialTarget::Frame => self.frame,
+        }
+    }
+}
+
+/// Pipelines of the picking and shadow passes, they do not depend on the formats
+struct DepthPasses {
+    picking: Option<Pipeline>,
+    shadow: Option<Pipeline>,
+}
+
+struct Material {
+    desc: MaterialDesc,
+    /// Layouts of the custom bind groups, in the order of the bind groups of the description
+    custom_layouts: Vec<Option<wgpu::BindGroupLayout>>,
+
+    /// Pipeline with the formats it was built for
+    pipeline: Option<(Pipeline, MaterialFormats)>,
+    depth_passes: Option<DepthPasses>,
+}
+
+impl Material {
+    fn create_shader(&self, device: &wgpu::Device) -> wgpu::ShaderModule {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&self.desc.label),
+            source: wgpu::ShaderSource::Wgsl(self.desc.source.as_str().into()),
+        })
+    }
+
+    fn create_custom_layouts(device: &wgpu::Device, desc: &MaterialDesc) -> Vec<Option<wgpu::BindGroupLayout>>
+    {
+        desc.bind_groups.iter()
+            .map(|bind_group| match bind_group {
+                MaterialBindGroup::Custom(entries) => Some(device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some(&desc.label),
+                    entries,
+                })),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Layouts of the groups after group 0, the lights and shadows are replaced by `empty` if given
+    fn group_layouts<'l>(&'l self,
+        layouts: &RendererLayouts<'l>,
+        empty: Option<&'l wgpu::BindGroupLayout>,
+    ) -> Vec<&'l wgpu::BindGroupLayout>
+    {
+        self.desc.bind_groups.iter().zip(&self.custom_layouts)
+            .map(|(bind_group, custom)| match bind_group {
+                MaterialBindGroup::Lights => empty.unwrap_or(layouts.lights),
+                MaterialBindGroup::Shadows => empty.unwrap_or(layouts.shadows),
+                MaterialBindGroup::Texture => layouts.texture,
+                MaterialBindGroup::Custom(_) => custom.as_ref().expect("created with the material"),
+            })
+            .collect()
+    }
+
+    fn create_pipeline(&self,
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layouts: &RendererLayouts,
+        formats: MaterialFormats,
+    ) -> Pipeline
+    {
+        let bind_group_layouts: Vec<&wgpu::BindGroupLayout> = std::iter::once(layouts.camera)
+            .chain(self.group_layouts(layouts, None))
+            .collect();
+
+        Pipeline::new_material(device,
+            shader,
+            &self.desc,
+            &bind_group_layouts,
+            formats.format(self.desc.target),
+            formats.sample_count)
+    }
+
+    fn create_depth_passes(&self,
+        device: &wgpu::Device,
+        layouts: &RendererLayouts,
+        empty_layout: &wgpu::BindGroupLayout,
+    ) -> DepthPasses
+    {
+        let group_layouts = self.group_layouts(layouts, Some(empty_layout));
+
+        // the object group comes after the groups of the material
+        let picking_layouts: Vec<&wgpu::BindGroupLayout> = std::iter::once(layouts.camera)
+            .chain(group_layouts.iter().copied())
+            .chain(std::iter::once(layouts.pick_object))
+            .collect();
+        let fits = picking_layouts.len() as u32 <= device.limits().max_bind_groups;
+        if self.desc.pickable && !fits {
+            log::warn!("material {}: too many bind groups for picking", self.desc.label);
+        }
+        let picking = (self.desc.pickable && fits).then(|| Pipeline::new_picking(device,
+            &format!("{} Picking", self.desc.label),
+            &picking_layouts,
+            &self.desc.vertex_layouts,
+            self.desc.topology));
+
+        let shadow_layouts: Vec<&wgpu::BindGroupLayout> = std::iter::once(layouts.cascade)
+            .chain(group_layouts.iter().copied())
+            .collect();
+        let shadow = self.desc.casts_shadows.then(|| Pipeline::new_shadow(device,
+            &format!("{} Shadow", self.desc.label),
+            &shadow_layouts,
+            &self.desc.vertex_layouts,
+            self.desc.topology));
+
+        DepthPasses {
+            picking,
+            shadow,
+        }
+    }
+}
+
+pub struct Materials {
+    materials: Vec<Material>,
+
+    // bound in place of the lights and shadows in the picking and shadow passes
+    empty_layout: wgpu::BindGroupLayout,
+    empty_bind_group: wgpu::BindGroup,
+}
+
+impl Materials {
+    pub fn new(device: &wgpu::Device) -> Self
+    {
+        let (empty_layout, empty_bind_group) = Self::create_empty_group(device);
+
+        Self {
+            materials: Vec::new(),
+
+            empty_layout,
+            empty_bind_group,
+        }
+    }
+
+    fn create_empty_group(device: &wgpu::Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup)
+    {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Empty Bind Group Layout"),
+            entries: &[],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material Empty Bind Group"),
+            layout: &layout,
+            entries: &[],
+        });
+
+        (layout, bind_group)
+    }
+
+    /// Returns the diagnostic of naga if the material is not valid, see [MaterialDesc::validate]
+    pub fn register(&mut self, device: &wgpu::Device, desc: MaterialDesc) -> Result<MaterialHandle, ShaderDiagnostic>
+    {
+        desc.validate()?;
+
+        let custom_layouts = Material::create_custom_layouts(device, &desc);
+        self.materials.push(Material {
+            desc,
+            custom_layouts,
+            pipeline: None,
+            depth_passes: None,
+        });
+
+        Ok(MaterialHandle(self.materials.len() as u32 - 1))
+    }
+
+    /// Creates the layouts on a new device, the pipelines are built by the next [Materials::prepare]
+    pub fn recreate(&mut self, device: &wgpu::Device)
+    {
+        (self.empty_layout, self.empty_bind_group) = Self::create_empty_group(device);
+
+        for material in &mut self.materials {
+            material.custom_layouts = Material::create_custom_layouts(device, &material.desc);
+            material.pipeline = None;
+            material.depth_passes = None;
+        }
+    }
+
+    /// Builds the pipelines which are missing or were built for other formats
+    pub fn prepare(&mut self, device: &wgpu::Device, layouts: &RendererLayouts, formats: MaterialFormats)
+    {
+        for material in &mut self.materials {
+            let up_to_date = matches!(material.pipeline, Some((_, built)) if built == formats);
+            if !up_to_date {
+                let shader = material.create_shader(device);
+                let pipeline = material.create_pipeline(device, &shader, layouts, formats);
+                material.pipeline = Some((pipeline, formats));
+            }
+
+            if material.depth_passes.is_none() {
+                material.depth_passes = Some(material.create_depth_passes(device, layouts, &self.empty_layout));
+            }
+        }
+    }
+
+    /// Replaces the source of the materials, keeps the old pipelines if wgpu reports an error
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn reload(&mut self,
+        device: &wgpu::Device,
+        layouts: &RendererLayouts,
+        formats: MaterialFormats,
+        handles: &[MaterialHandle],
+        file: &str,
+        source: &str,
+    ) -> Result<(), ShaderDiagnostic>
+    {
+        // wgpu reports errors like a mismatch with the vertex layouts here, instead of panicking
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(file),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipelines: Vec<(usize, Pipeline)> = handles.iter()
+            .filter_map(|handle| {
+                let index = handle.0 as usize;
+                let material = self.materials.get(index)?;
+                Some((index, material.create_pipeline(device, &shader, layouts, formats)))
+            })
+            .collect();
+
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(ShaderDiagnostic::from_wgpu(file, &error));
+        }
+
+        for (index, pipeline) in pipelines {
+            let material = &mut self.materials[index];
+            material.desc.source = source.to_string();
+            material.pipeline = Some((pipeline, formats));
+        }
+
+        Ok(())
+    }
+
+    /// None if the material was registered after the last call to [Materials::prepare]
+    pub fn pipeline(&self, handle: MaterialHandle) -> Option<&Pipeline> {
+        self.materials.get(handle.0 as usize)
+            .and_then(|material| material.pipeline.as_ref())
+            .map(|(pipeline, _)| pipeline)
+    }
+
+    /// None if the material is not pickable, see [Materials::bind_empty_groups]
+    pub fn picking_pipeline(&self, handle: MaterialHandle) -> Option<&Pipeline> {
+        self.materials.get(handle.0 as usize)
+            .and_then(|material| material.depth_passes.as_ref())
+            .and_then(|depth_passes| depth_passes.picking.as_ref())
+    }
+
+    /// None if the material casts no shadows, see [Materials::bind_empty_groups]
+    pub fn shadow_pipeline(&self, handle: MaterialHandle) -> Option<&Pipeline> {
+        self.materials.get(handle.0 as usize)
+            .and_then(|material| material.depth_passes.as_ref())
+            .and_then(|depth_passes| depth_passes.shadow.as_ref())
+    }
+
+    /// Binds the empty group in place of the lights and shadows for the picking and shadow pipelines,
+    /// returns the index of the group after the ones of the material
+    pub fn bind_empty_groups<'a>(&'a self, handle: MaterialHandle, render_pass: &mut wgpu::RenderPass<'a>) -> u32
+    {
+        let bind_groups = self.bind_groups(handle);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            if matches!(bind_group, MaterialBindGroup::Lights | MaterialBindGroup::Shadows) {
+                render_pass.set_bind_group(index as u32 + 1, &self.empty_bind_group, &[]);
+            }
+        }
+
+        bind_groups.len() as u32 + 1
+    }
+
+    pub fn is_transparent(&self, handle: MaterialHandle) -> bool {
+        self.materials.get(handle.0 as usize)
+            .is_some_and(|material| material.desc.is_transparent())
+    }
+
+    /// Layout of a custom bind group of the material, to create the bind groups of its meshes
+    pub fn bind_group_layout(&self, handle: MaterialHandle, group: u32) -> Option<&wgpu::BindGroupLayout> {
+        let material = self.materials.get(handle.0 as usize)?;
+        let index = (group as usize).checked_sub(1)?;
+
+        material.custom_layouts.get(index)?.as_ref()
+    }
+
+    /// The bind groups after the camera, group 1 first
+    pub fn bind_groups(&self, handle: MaterialHandle) -> &[MaterialBindGroup] {
+        self.materials.get(handle.0 as usize)
+            .map_or(&[], |material| material.desc.bind_groups.as_slice())
+    }
+}
+
+/// Materials of the meshes of wgpu_renderer, the lit meshes and the overlays of the renderer
+pub struct BuiltinMaterials {
+    pub color: MaterialHandle,
+    pub lines: MaterialHandle,
+    /// Lines on top of everything, they neither test nor write depth
+    pub lines_overlay: MaterialHandle,
+    pub frame_lines: MaterialHandle,
+    pub frame_lines_overlay: MaterialHandle,
+    pub texture: MaterialHandle,
+    /// Alpha blended, tests against the depth of the opaque meshes without writing it
+    pub texture_transparent: MaterialHandle,
+    /// Draws into the frame on top of everything, for the orthographic gui camera
+    pub gui: MaterialHandle,
+    pub lit: MaterialHandle,
+}
+
+impl BuiltinMaterials {
+    pub fn register(device: &wgpu::Device, materials: &mut Materials) -> Self
+    {
+        let color_layouts = || vec![
+            vertex_color_shader::Vertex::desc(),
+            vertex_color_shader::Color::desc(),
+            vertex_color_shader::InstanceRaw::desc(),
+        ];
+        let texture_layouts = || vec![
+            vertex_texture_shader::Vertex::desc(),
+            vertex_texture_shader::InstanceRaw::desc(),
+        ];
+        let mut register = |desc| materials.register(device, desc)
+            .unwrap_or_else(|diagnostic| panic!("built in material: {diagnostic}"));
+
+        let color = register(MaterialDesc {
+            casts_shadows: false,
+            ..MaterialDesc::new("Color Material", vertex_color_shader::SHADER_SOURCE, color_layouts())
+        });
+
+        let line_desc = |label: &str, depth_compare, target| MaterialDesc {
+            topology: wgpu::PrimitiveTopology::LineList,
+            depth_compare,
+            depth_write_enabled: depth_compare != wgpu::CompareFunction::Always,
+            target,
+            pickable: false,
+            casts_shadows: false,
+            ..MaterialDesc::new(label, vertex_color_shader::SHADER_SOURCE, color_layouts())
+        };
+        let lines_overlay = register(line_desc("Lines Overlay Material", wgpu::CompareFunction::Always, MaterialTarget::Scene));
+        let frame_lines = register(line_desc("Frame Lines Material", wgpu::CompareFunction::Less, MaterialTarget::Frame));
+        let frame_lines_overlay = register(line_desc("Frame Lines Overlay Material", wgpu::CompareFunction::Always, MaterialTarget::Frame));
+        let lines = register(line_desc("Lines Material", wgpu::CompareFunction::Less, MaterialTarget::Scene));
+
+        // the textured meshes of wgpu_renderer blend, but are drawn with the opaque ones
+        let texture_desc = |label: &str| MaterialDesc {
+            bind_groups: vec![MaterialBindGroup::Texture],
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            casts_shadows: false,
+            ..MaterialDesc::new(label, vertex_texture_shader::SHADER_SOURCE, texture_layouts())
+        };
+        let texture_transparent = register(MaterialDesc {
+            depth_write_enabled: false,
+            pickable: false,
+            ..texture_desc("Texture Transparent Material")
+        });
+        let gui = register(MaterialDesc {
+            depth_compare: wgpu::CompareFunction::Always,
+            depth_write_enabled: false,
+            target: MaterialTarget::Frame,
+            pickable: false,
+            ..texture_desc("Gui Material")
+        });
+        let texture = register(texture_desc("Texture Material"));
+
+        let lit = register(MaterialDesc {
+            bind_groups: vec![MaterialBindGroup::Lights, MaterialBindGroup::Shadows],
+            ..MaterialDesc::new("Lit Material", LIT_SHADER, vec![
+                lit_shader::Vertex::desc(),
+                vertex_color_shader::InstanceRaw::desc(),
+            ])
+        });
+
+        Self {
+            color,
+            lines,
+            lines_overlay,
+            frame_lines,
+            frame_lines_overlay,
+            texture,
+            texture_transparent,
+            gui,
+            lit,
+        }
+    }
+
+    /// The material the items of the pipeline are drawn with
+    pub fn handle(&self, pipeline: QueuePipeline) -> MaterialHandle {
+        match pipeline {
+            QueuePipeline::Color => self.color,
+            QueuePipeline::Lit => self.lit,
+            QueuePipeline::Texture => self.texture,
+            QueuePipeline::Material(handle) => handle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(position, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+";
+
+    fn color_layouts() -> Vec<wgpu::VertexBufferLayout<'static>> {
+        vec![
+            vertex_color_shader::Vertex::desc(),
+            vertex_color_shader::Color::desc(),
+            vertex_color_shader::InstanceRaw::desc(),
+        ]
+    }
+
+    #[test]
+    fn valid_material_passes()
+    {
+        assert_eq!(MaterialDesc::new("valid", SHADER, color_layouts()).validate(), Ok(()));
+    }
+
+    #[test]
+    fn broken_source_is_reported()
+    {
+        let desc = MaterialDesc::new("broken", &SHADER.replace("vec4<f32>(1.0)", "vec4<f32>(1.0"), color_layouts());
+
+        let diagnostic = desc.validate().unwrap_err();
+        assert_eq!(diagnostic.file, "broken");
+        assert!(diagnostic.line > 0);
+    }
+
+    #[test]
+    fn missing_entry_point_is_reported()
+    {
+        let desc = MaterialDesc::new("missing", &SHADER.replace("fs_main", "fragment_main"), color_layouts());
+
+        assert!(desc.validate().unwrap_err().message.contains("fs_main"));
+    }
+
+    #[test]
+    fn depth_passes_need_the_position_and_the_instance_transform()
+    {
+        assert!(MaterialDesc::has_depth_pass_layouts(&color_layouts()));
+        assert!(MaterialDesc::has_depth_pass_layouts(&[lit_shader::Vertex::desc(), vertex_color_shader::InstanceRaw::desc()]));
+        assert!(!MaterialDesc::has_depth_pass_layouts(&[vertex_color_shader::Vertex::desc()]));
+
+        let desc = MaterialDesc::new("no instances", SHADER, vec![vertex_color_shader::Vertex::desc()]);
+        assert!(!desc.pickable && !desc.casts_shadows);
+        assert_eq!(desc.validate(), Ok(()));
+
+        let desc = MaterialDesc { pickable: true, ..desc };
+        assert!(desc.validate().is_err());
+    }
+
+    #[test]
+    fn blending_materials_are_transparent()
+    {
+        let desc = MaterialDesc::new("opaque", SHADER, color_layouts());
+        assert!(!desc.is_transparent());
+
+        let desc = MaterialDesc { blend: None, ..desc };
+        assert!(!desc.is_transparent());
+
+        let desc = MaterialDesc { blend: Some(wgpu::BlendState::ALPHA_BLENDING), ..desc };
+        assert!(desc.is_transparent());
+    }
+}
@@ -0,0 +1,356 @@
+//! Picture in picture minimap, the scene seen from above
+//!
+//! The opaque meshes are drawn into a texture with an orthographic camera looking down
+//! onto the ground, together with a marker for the position and view direction of the
+//! main camera. The texture is drawn as a gui quad into the bottom right corner of the
+//! frame, clicking onto it moves the main camera above the clicked point.
+
+use wgpu_renderer::renderer;
+use wgpu_renderer::vertex_color_shader::{self, VertexColorShaderDraw};
+use wgpu_renderer::vertex_texture_shader;
+
+use super::culling::Frustum;
+use super::multisample::MultisampleTargets;
+use super::viewport::{ViewportRect, CAMERA_ZFAR};
+use crate::math::Ray;
+
+/// Width and height of the minimap in physical pixels
+pub const MINIMAP_SIZE: u32 = 200;
+const MINIMAP_MARGIN: u32 = 10;
+
+/// Half of the width of the ground shown by default
+const HALF_EXTENT: f32 = 10.0;
+
+const MARKER_COLOR: [f32; 3] = [1.0, 0.8, 0.1];
+
+pub struct Minimap {
+    pub visible: bool,
+
+    // camera
+    camera: renderer::camera::Camera,
+    half_extent: f32,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    frustum: Frustum,
+
+    // target
+    targets: MultisampleTargets,
+    color_view: wgpu::TextureView,
+    texture_bind_group: wgpu::BindGroup,
+
+    // marker
+    marker: vertex_color_shader::Mesh,
+
+    // quad
+    vertex_buffer: vertex_texture_shader::VertexBuffer,
+    index_buffer: vertex_texture_shader::IndexBuffer,
+    instance_buffer: vertex_texture_shader::InstanceBuffer,
+    rect: Option<ViewportRect>,
+}
+
+impl Minimap {
+    /// The camera looks straight down, its position is the center of the minimap
+    pub fn new(device: &wgpu::Device,
+        camera_bind_group_layout: &vertex_color_shader::CameraBindGroupLayout,
+        texture_bind_group_layout: &vertex_texture_shader::TextureBindGroupLayout,
+        camera: renderer::camera::Camera,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+        frame_size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self
+    {
+        // camera
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Minimap Camera Buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Minimap Camera Bind Group"),
+            layout: camera_bind_group_layout.get(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // target
+        let size = winit::dpi::PhysicalSize::new(MINIMAP_SIZE, MINIMAP_SIZE);
+        let targets = MultisampleTargets::new(device, surface_format, size, sample_count);
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Minimap Color Texture"),
+            size: wgpu::Extent3d {
+                width: MINIMAP_SIZE,
+                height: MINIMAP_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: surface_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Minimap Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Minimap Texture Bind Group"),
+            layout: texture_bind_group_layout.get(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        // marker
+        let colors = [vertex_color_shader::Color { color: MARKER_COLOR }; 4];
+        let indices = [0, 1, 1, 2, 1, 3];
+        let instances = [vertex_color_shader::Instance {
+            position: glam::Vec3::ZERO,
+            rotation: glam::Quat::IDENTITY,
+        }];
+        let marker = vertex_color_shader::Mesh::new(device, &[vertex_color_shader::Vertex { position: [0.0; 3] }; 4], &colors, &indices, &instances);
+
+        // quad
+        let rect = Self::rect(frame_size);
+        let vertex_buffer = vertex_texture_shader::VertexBuffer::new(device, &Self::quad_vertices(rect, frame_size));
+        let index_buffer = vertex_texture_shader::IndexBuffer::new(device, &[0, 1, 2, 2, 3, 0]);
+        let instance_buffer = vertex_texture_shader::InstanceBuffer::new(device, &[vertex_texture_shader::Instance::zero().to_raw()]);
+
+        Self {
+            visible: true,
+
+            camera,
+            half_extent: HALF_EXTENT,
+            camera_buffer,
+            camera_bind_group,
+            frustum: Frustum::from_view_proj(glam::Mat4::IDENTITY),
+
+            targets,
+            color_view,
+            texture_bind_group,
+
+            marker,
+
+            vertex_buffer,
+            index_buffer,
+            instance_buffer,
+            rect,
+        }
+    }
+
+    /// Area of the quad in the bottom right corner of the frame, None if the frame is too small
+    pub fn rect(frame_size: winit::dpi::PhysicalSize<u32>) -> Option<ViewportRect>
+    {
+        let extent = MINIMAP_SIZE + MINIMAP_MARGIN;
+        if frame_size.width < extent || frame_size.height < extent {
+            return None;
+        }
+
+        Some(ViewportRect {
+            x: frame_size.width - extent,
+            y: frame_size.height - extent,
+            width: MINIMAP_SIZE,
+            height: MINIMAP_SIZE,
+        })
+    }
+
+    /// The orthographic gui camera has its origin in the bottom left corner of the frame
+    fn quad_vertices(rect: Option<ViewportRect>, frame_size: winit::dpi::PhysicalSize<u32>) -> [vertex_texture_shader::Vertex; 4]
+    {
+        let (left, bottom, right, top) = rect.map_or((0.0, 0.0, 0.0, 0.0), |rect| {
+            let bottom = (frame_size.height - rect.y - rect.height) as f32;
+            (rect.x as f32, bottom, (rect.x + rect.width) as f32, bottom + rect.height as f32)
+        });
+
+        [
+            vertex_texture_shader::Vertex { position: [left, bottom, 0.0], tex_coords: [0.0, 1.0] },
+            vertex_texture_shader::Vertex { position: [right, bottom, 0.0], tex_coords: [1.0, 1.0] },
+            vertex_texture_shader::Vertex { position: [right, top, 0.0], tex_coords: [1.0, 0.0] },
+            vertex_texture_shader::Vertex { position: [left, top, 0.0], tex_coords: [0.0, 0.0] },
+        ]
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, frame_size: winit::dpi::PhysicalSize<u32>)
+    {
+        self.rect = Self::rect(frame_size);
+        self.vertex_buffer = vertex_texture_shader::VertexBuffer::new(device, &Self::quad_vertices(self.rect, frame_size));
+    }
+
+    /// Orthographic projection of the ground around the camera
+    pub fn view_proj(&self) -> glam::Mat4
+    {
+        let view: [[f32; 4]; 4] = self.camera.calc_matrix().into();
+        let projection = glam::Mat4::orthographic_rh(
+            -self.half_extent,
+            self.half_extent,
+            -self.half_extent,
+            self.half_extent,
+            0.0,
+            CAMERA_ZFAR);
+
+        projection * glam::Mat4::from_cols_array_2d(&view)
+    }
+
+    pub fn frustum(&self) -> &Frustum {
+        &self.frustum
+    }
+
+    /// Point on the ground under the position in physical pixels of the frame, None outside of the minimap
+    pub fn ground_point(&self, x: f32, y: f32) -> Option<glam::Vec2>
+    {
+        let rect = self.rect.filter(|rect| self.visible && rect.contains(x, y))?;
+        let ray = Ray::from_screen(self.view_proj(), x - rect.x as f32, y - rect.y as f32, rect.width, rect.height);
+
+        // the camera looks straight down, every ray hits the ground below its origin
+        Some(ray.origin.truncate())
+    }
+
+    /// Writes the camera and moves the marker to the main camera
+    pub fn update(&mut self, queue: &wgpu::Queue, main_camera: &renderer::camera::Camera)
+    {
+        let view_proj = self.view_proj();
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&view_proj.to_cols_array_2d()));
+        self.frustum = Frustum::from_view_proj(view_proj);
+
+        let vertices = Self::marker_vertices(main_camera, self.half_extent * 0.1);
+        self.marker.update_vertex_buffer(queue, &vertices);
+    }
+
+    /// Arrow from the camera position along the view direction projected onto the ground
+    fn marker_vertices(camera: &renderer::camera::Camera, length: f32) -> [vertex_color_shader::Vertex; 4]
+    {
+        let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
+        let pitch_cos = camera.pitch.0.cos();
+
+        // looking straight down the arrow points up in the minimap
+        let direction = glam::Vec2::new(pitch_cos * yaw_cos, pitch_cos * yaw_sin)
+            .try_normalize()
+            .unwrap_or(glam::Vec2::Y);
+        let side = direction.perp();
+
+        let position = glam::Vec2::new(camera.position.x, camera.position.y);
+        let tip = position + direction * length * 2.0;
+        let left = tip - direction * length * 0.7 + side * length * 0.5;
+        let right = tip - direction * length * 0.7 - side * length * 0.5;
+
+        [position, tip, left, right].map(|point| vertex_color_shader::Vertex {
+            position: [point.x, point.y, 0.0],
+        })
+    }
+
+    /// Render pass into the minimap texture, cleared to the background
+    pub fn begin_pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a>
+    {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Minimap Render Pass"),
+            color_attachments: &[Some(self.targets.color_attachment(
+                &self.color_view,
+                wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.05,
+                    g: 0.07,
+                    b: 0.05,
+                    a: 1.0,
+                }),
+            ))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.targets.depth_view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        })
+    }
+
+    pub fn bind_camera<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>)
+    {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+    }
+
+    /// Expects a line list pipeline of the vertex color shader and the minimap camera
+    pub fn draw_marker<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>)
+    {
+        self.marker.draw(render_pass);
+    }
+
+    /// Expects the gui pipeline and the orthographic camera
+    pub fn draw_quad<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>)
+    {
+        if !self.visible || self.rect.is_none() {
+            return;
+        }
+
+        self.vertex_buffer.bind(render_pass);
+        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+        self.index_buffer.bind(render_pass);
+        self.instance_buffer.bind_slot(render_pass, 1);
+
+        render_pass.draw_indexed(0..self.index_buffer.size(), 0, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn top_view_proj(center: glam::Vec2) -> glam::Mat4
+    {
+        let view = glam::Mat4::look_at_rh(center.extend(10.0), center.extend(0.0), glam::Vec3::Y);
+        glam::Mat4::orthographic_rh(-HALF_EXTENT, HALF_EXTENT, -HALF_EXTENT, HALF_EXTENT, 0.0, CAMERA_ZFAR) * view
+    }
+
+    #[test]
+    fn rect_is_in_the_bottom_right_corner()
+    {
+        let rect = Minimap::rect(winit::dpi::PhysicalSize::new(800, 600)).unwrap();
+
+        assert_eq!(rect, ViewportRect { x: 590, y: 390, width: MINIMAP_SIZE, height: MINIMAP_SIZE });
+        assert!(Minimap::rect(winit::dpi::PhysicalSize::new(100, 600)).is_none());
+    }
+
+    #[test]
+    fn corners_of_the_minimap_map_to_the_shown_ground()
+    {
+        let view_proj = top_view_proj(glam::Vec2::new(3.0, -2.0));
+        let size = MINIMAP_SIZE as f32;
+
+        let top_left = Ray::from_screen(view_proj, 0.0, 0.0, MINIMAP_SIZE, MINIMAP_SIZE).origin;
+        let bottom_right = Ray::from_screen(view_proj, size, size, MINIMAP_SIZE, MINIMAP_SIZE).origin;
+
+        assert!(top_left.truncate().abs_diff_eq(glam::Vec2::new(-7.0, 8.0), 1e-4), "{top_left}");
+        assert!(bottom_right.truncate().abs_diff_eq(glam::Vec2::new(13.0, -12.0), 1e-4), "{bottom_right}");
+    }
+
+    #[test]
+    fn marker_points_along_the_view_direction()
+    {
+        let camera = renderer::camera::Camera::new(
+            cgmath::Point3::new(1.0, 2.0, 5.0),
+            cgmath::Deg(0.0),
+            cgmath::Deg(-30.0));
+
+        let vertices = Minimap::marker_vertices(&camera, 1.0);
+
+        assert_eq!(vertices[0].position, [1.0, 2.0, 0.0]);
+        assert_eq!(vertices[1].position, [3.0, 2.0, 0.0]);
+    }
+}
@@ -1,6 +1,47 @@
 
-
 mod renderer;
 mod camera_controller;
+mod render_target;
+#[cfg(test)]
+mod offscreen;
+mod window_target;
+mod frame_capture;
+mod pipeline;
+mod multisample;
+mod post_processing;
+mod render_graph;
+mod shadow;
+mod culling;
+mod render_queue;
+mod debug_draw;
+mod grid;
+mod gizmo;
+mod picking;
+mod material;
+mod viewport;
+mod minimap;
+mod resources;
+mod shader_validation;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+mod shader_reload;
+
+pub use renderer::Renderer;
+pub use render_target::RenderTarget;
+#[cfg(test)]
+pub use render_target::FrameStatus;
+#[cfg(test)]
+pub use offscreen::OffscreenRenderer;
+pub use window_target::WindowTarget;
+pub use frame_capture::CaptureError;
 
-pub use renderer::Renderer;
\ No newline at end of file
+pub use post_processing::{AutoExposure, PostEffect, PostEffectKind};
+pub use render_graph::{PassContext, RenderGraphNode, RenderGraphPass};
+#[cfg(test)]
+pub use render_graph::AttachmentDesc;
+pub use debug_draw::TEXT_HEIGHT;
+pub use culling::{Aabb, Bounded, DrawInstances, CullingStats};
+#[cfg(test)]
+pub use culling::{TextureDrawable, ColorDrawable, LitDrawable};
+pub use render_queue::{Batched, DrawId, RenderQueue};
+pub use material::{MaterialBindGroup, MaterialDesc, MaterialHandle, MaterialShaderDraw};
+pub use resources::{MeshHandle, ResourceManager, TextureHandle};
@@ -0,0 +1,126 @@
+//! Multisampled color and depth targets of the forward pass
+//!
+
+use super::pipeline::{DEPTH_FORMAT, HDR_FORMAT};
+use super::render_graph::AttachmentView;
+use super::render_target::RenderTarget;
+
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+/// Returns the highest sample count not above the requested one which is
+/// supported for both the surface format and the depth format
+pub fn clamp_sample_count(target: &impl RenderTarget, requested: u32) -> u32
+{
+    let surface_format = target.surface_format();
+
+    SAMPLE_COUNTS.iter()
+        .rev()
+        .copied()
+        .filter(|&count| count <= requested)
+        .find(|&count| target.sample_count_supported(surface_format, count)
+            && target.sample_count_supported(DEPTH_FORMAT, count))
+        .unwrap_or(1)
+}
+
+/// The HDR format if it supports the sample count, otherwise the surface format
+/// without HDR, which is logged
+pub fn scene_format(target: &impl RenderTarget, sample_count: u32) -> wgpu::TextureFormat
+{
+    if target.sample_count_supported(HDR_FORMAT, sample_count) {
+        HDR_FORMAT
+    } else {
+        let surface_format = target.surface_format();
+        log::warn!("{HDR_FORMAT:?} does not support {sample_count} samples, the scene is rendered in {surface_format:?} without HDR");
+        surface_format
+    }
+}
+
+pub struct MultisampleTargets {
+    sample_count: u32,
+
+    // only exists for a sample count above 1, resolves into the frame
+    color_view: Option<wgpu::TextureView>,
+    depth_view: wgpu::TextureView,
+}
+
+impl MultisampleTargets {
+    pub fn new(device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        sample_count: u32,
+    ) -> Self
+    {
+        let color_view = if sample_count > 1 {
+            Some(Self::create_view(device, "Multisample Color Texture", surface_format, size, sample_count))
+        } else {
+            None
+        };
+
+        let depth_view = Self::create_view(device, "Depth Texture", DEPTH_FORMAT, size, sample_count);
+
+        Self {
+            sample_count,
+
+            color_view,
+            depth_view,
+        }
+    }
+
+    fn create_view(device: &wgpu::Device,
+        label: &str,
+        format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        sample_count: u32,
+    ) -> wgpu::TextureView
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.width.max(1),
+                height: size.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// The multisampled texture which resolves into the view,
+    /// or the view itself if multisampling is disabled
+    pub fn color_view<'a>(&'a self, view: &'a wgpu::TextureView) -> AttachmentView<'a>
+    {
+        match &self.color_view {
+            Some(color_view) => AttachmentView {
+                view: color_view,
+                resolve_target: Some(view),
+            },
+            None => AttachmentView {
+                view,
+                resolve_target: None,
+            },
+        }
+    }
+
+    /// Draws into the [MultisampleTargets::color_view] of the view
+    ///
+    /// The multisampled texture is kept, so a following pass can load it again.
+    pub fn color_attachment<'a>(&'a self, view: &'a wgpu::TextureView, load: wgpu::LoadOp<wgpu::Color>)
+    -> wgpu::RenderPassColorAttachment<'a>
+    {
+        self.color_view(view).color_attachment(load)
+    }
+
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+}
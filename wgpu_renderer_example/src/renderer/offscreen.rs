@@ -0,0 +1,191 @@
+//! Renders into an owned texture instead of a window surface
+//!
+//! Used on machines without a display, e.g. CI runners with a software adapter
+//! like llvmpipe or lavapipe. Select the adapter with WGPU_ADAPTER_NAME and WGPU_BACKEND.
+//! Errors of the surface and the loss of the device can be injected to test the recovery.
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use super::frame_capture::FrameCapture;
+use super::render_target::{self, DeviceError, DeviceLost, Frame, RenderTarget};
+
+pub struct OffscreenRenderer {
+    instance: wgpu::Instance,
+    adapter: wgpu::Adapter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    device_lost: DeviceLost,
+
+    size: winit::dpi::PhysicalSize<u32>,
+    color_texture: wgpu::Texture,
+
+    /// Returned by the next call to get_current_frame
+    injected_error: Option<wgpu::SurfaceError>,
+    /// Number of times the surface was configured again
+    reconfigured: u32,
+}
+
+impl OffscreenRenderer {
+    pub const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Returns None if no adapter is available
+    pub async fn new(width: u32, height: u32) -> Option<Self>
+    {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+            ..Default::default()
+        });
+
+        let (adapter, device, queue) = Self::request_device(&instance).await.ok()?;
+
+        let size = winit::dpi::PhysicalSize::new(width.max(1), height.max(1));
+        let color_texture = Self::create_color_texture(&device, size);
+
+        Some(Self {
+            instance,
+            adapter,
+            device_lost: DeviceLost::watch(&device),
+            device,
+            queue,
+
+            size,
+            color_texture,
+
+            injected_error: None,
+            reconfigured: 0,
+        })
+    }
+
+    async fn request_device(instance: &wgpu::Instance) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), DeviceError>
+    {
+        let adapter = wgpu::util::initialize_adapter_from_env_or_default(instance, None).await
+            .ok_or(DeviceError::NoAdapter)?;
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("Offscreen Device"),
+                required_features: Self::format_features(&adapter),
+                required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+            },
+            None,
+        ).await.map_err(DeviceError::RequestDevice)?;
+
+        Ok((adapter, device, queue))
+    }
+
+    /// The features the device requests so the adapter specific sample counts can be used
+    fn format_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+    }
+
+    /// The next frame fails with the error
+    pub fn inject_surface_error(&mut self, error: wgpu::SurfaceError) {
+        self.injected_error = Some(error);
+    }
+
+    /// Destroys the device, as if the driver had lost it
+    pub fn lose_device(&mut self)
+    {
+        self.device.destroy();
+        // the callback may run later, the renderer has to notice the loss before the next frame
+        self.device_lost.set();
+    }
+
+    pub fn reconfigured(&self) -> u32 {
+        self.reconfigured
+    }
+
+    fn create_color_texture(device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) -> wgpu::Texture
+    {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Color Texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::COLOR_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    /// Copies the last rendered frame back to the host
+    pub fn read_frame(&mut self) -> image::RgbaImage
+    {
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Offscreen Readback Encoder"),
+        });
+
+        let capture = FrameCapture::new(&self.device, &mut encoder, &self.color_texture);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        capture.read(&self.device).expect("reading the offscreen frame failed")
+    }
+}
+
+impl WgpuRendererInterface for OffscreenRenderer {
+    fn device(&mut self) -> &mut wgpu::Device {
+        &mut self.device
+    }
+
+    fn queue(&mut self) -> &mut wgpu::Queue {
+        &mut self.queue
+    }
+}
+
+impl RenderTarget for OffscreenRenderer {
+    fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.size = new_size;
+            self.color_texture = Self::create_color_texture(&self.device, new_size);
+        }
+    }
+
+    fn surface_format(&self) -> wgpu::TextureFormat {
+        Self::COLOR_FORMAT
+    }
+
+    fn get_current_frame(&mut self) -> Result<Frame, wgpu::SurfaceError> {
+        if let Some(error) = self.injected_error.take() {
+            return Err(error);
+        }
+
+        let view = self.color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Frame::new(None, view))
+    }
+
+    fn reconfigure(&mut self) {
+        self.reconfigured += 1;
+    }
+
+    fn device_lost(&self) -> bool {
+        self.device_lost.get()
+    }
+
+    fn recreate_device(&mut self) -> Result<(), DeviceError>
+    {
+        let (adapter, device, queue) = render_target::block_on_device(Self::request_device(&self.instance))?;
+
+        self.device_lost = DeviceLost::watch(&device);
+        self.color_texture = Self::create_color_texture(&device, self.size);
+        self.adapter = adapter;
+        self.device = device;
+        self.queue = queue;
+
+        Ok(())
+    }
+
+    fn sample_count_supported(&self, format: wgpu::TextureFormat, sample_count: u32) -> bool {
+        render_target::sample_count_supported(&self.adapter, self.device.features(), format, sample_count)
+    }
+}
@@ -0,0 +1,354 @@
+//! Object picking with an id buffer
+//!
+//! On request the scene is drawn a second time into an R32Uint target, each pixel
+//! holds the draw id and the instance of the mesh in front. The texel under the
+//! cursor is copied into a buffer and read back asynchronously, so the result
+//! arrives a frame or more later without stalling the renderer. The pass only runs
+//! for frames with a pending request.
+
+use std::sync::mpsc;
+
+use wgpu::util::DeviceExt;
+
+use super::culling::CullingStats;
+use super::material::{BuiltinMaterials, Materials};
+use super::render_queue::{DrawId, RenderQueue, TextureBinds};
+use super::viewport::Viewport;
+
+pub const PICKING_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+/// Meshes with a higher draw id can not be picked, ids are stored in the upper 16 bits
+pub const MAX_PICK_OBJECTS: u32 = 4096;
+
+/// Instances above this index are reported modulo it
+pub const MAX_PICK_INSTANCES: u32 = 1 << 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PickHit {
+    pub draw_id: DrawId,
+    pub instance: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PickResult {
+    /// Position in physical pixels of the frame
+    pub x: u32,
+    pub y: u32,
+    /// None if no mesh is under the position
+    pub hit: Option<PickHit>,
+}
+
+impl PickResult {
+    fn decode(x: u32, y: u32, value: u32) -> Self
+    {
+        let hit = (value != 0).then(|| PickHit {
+            draw_id: DrawId((value >> 16) - 1),
+            instance: value & (MAX_PICK_INSTANCES - 1),
+        });
+
+        Self { x, y, hit }
+    }
+}
+
+enum PickState {
+    /// Waiting for the picking pass of the next frame
+    Recording,
+    Mapping(mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>),
+}
+
+struct PendingPick {
+    x: u32,
+    y: u32,
+    buffer: wgpu::Buffer,
+    state: PickState,
+}
+
+/// The draw ids and their depth, transient textures of the render graph
+pub struct PickTarget<'a> {
+    pub texture: &'a wgpu::Texture,
+    pub view: &'a wgpu::TextureView,
+    pub depth_view: &'a wgpu::TextureView,
+}
+
+pub struct Picking {
+    // one uniform per draw id, selected with a dynamic offset, the pipelines are variants of the materials
+    object_bind_group_layout: wgpu::BindGroupLayout,
+    object_bind_group: wgpu::BindGroup,
+    object_stride: u32,
+
+    requested: Option<(u32, u32)>,
+    pending: Option<PendingPick>,
+}
+
+impl Picking {
+    pub fn new(device: &wgpu::Device) -> Self
+    {
+        let object_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Picking Object Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(4),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // the ids never change, so they are written once
+        let object_stride = device.limits().min_uniform_buffer_offset_alignment.max(4);
+        let mut object_data = vec![0u8; (MAX_PICK_OBJECTS * object_stride) as usize];
+        for object in 0..MAX_PICK_OBJECTS {
+            let offset = (object * object_stride) as usize;
+            object_data[offset..offset + 4].copy_from_slice(&((object + 1) << 16).to_le_bytes());
+        }
+        let object_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Picking Object Buffer"),
+            contents: &object_data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let object_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Picking Object Bind Group"),
+            layout: &object_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &object_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(4),
+                    }),
+                },
+            ],
+        });
+
+        Self {
+            object_bind_group_layout,
+            object_bind_group,
+            object_stride,
+
+            requested: None,
+            pending: None,
+        }
+    }
+
+    /// The last group of the picking pipelines of the materials
+    pub fn object_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.object_bind_group_layout
+    }
+
+    /// Picks at the position in physical pixels of the frame with the next rendered frame,
+    /// replaces an earlier request which was not rendered yet
+    pub fn request(&mut self, x: u32, y: u32) {
+        self.requested = Some((x, y));
+    }
+
+    /// Starts the requested pick if no other one is in flight, call before recording the frame
+    pub fn prepare(&mut self, device: &wgpu::Device, frame_size: winit::dpi::PhysicalSize<u32>)
+    {
+        if self.pending.is_some() || frame_size.width == 0 || frame_size.height == 0 {
+            return;
+        }
+        let Some((x, y)) = self.requested.take() else {
+            return;
+        };
+        if x >= frame_size.width || y >= frame_size.height {
+            return;
+        }
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picking Readback Buffer"),
+            size: wgpu::COPY_BUFFER_ALIGNMENT,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        self.pending = Some(PendingPick {
+            x,
+            y,
+            buffer,
+            state: PickState::Recording,
+        });
+    }
+
+    /// Draws the ids of the opaque and transparent meshes of pickable materials seen by the camera
+    /// of the viewport under the cursor and copies the texel under the cursor
+    pub fn draw(&self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: PickTarget,
+        queue: &RenderQueue,
+        materials: &Materials,
+        builtin_materials: &BuiltinMaterials,
+        viewport: &Viewport,
+    )
+    {
+        let Some(pending) = &self.pending else {
+            return;
+        };
+        if !matches!(pending.state, PickState::Recording) {
+            return;
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Picking Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: target.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: Default::default(),
+                occlusion_query_set: Default::default(),
+            });
+
+            viewport.rect().apply(&mut render_pass);
+
+            let mut stats = CullingStats::default();
+            let mut current_material = None;
+            let mut object_group = 0;
+            let mut textures = TextureBinds::default();
+            for item in queue.scene_items() {
+                if item.id.0 >= MAX_PICK_OBJECTS {
+                    continue;
+                }
+
+                let material = builtin_materials.handle(item.mesh.pipeline());
+                let Some(pipeline) = materials.picking_pipeline(material) else {
+                    continue;
+                };
+                if current_material != Some(material) {
+                    current_material = Some(material);
+                    pipeline.bind(&mut render_pass);
+                    viewport.bind_camera(&mut render_pass);
+                    object_group = materials.bind_empty_groups(material, &mut render_pass);
+                    textures.reset();
+                }
+
+                textures.bind(&mut render_pass, item.mesh);
+                render_pass.set_bind_group(object_group, &self.object_bind_group, &[item.id.0 * self.object_stride]);
+                item.mesh.draw_culled(viewport.frustum(), &mut stats, &mut render_pass, item.instances.clone());
+            }
+        }
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: target.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: pending.x,
+                    y: pending.y,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &pending.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Maps the readback buffer of a recorded pick, call after the frame was submitted
+    pub fn submitted(&mut self)
+    {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+        if !matches!(pending.state, PickState::Recording) {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        pending.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        pending.state = PickState::Mapping(receiver);
+    }
+
+    /// The result of the last pick once it is read back, never blocks
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<PickResult>
+    {
+        let pending = self.pending.as_ref()?;
+        let PickState::Mapping(receiver) = &pending.state else {
+            return None;
+        };
+
+        device.poll(wgpu::Maintain::Poll);
+
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.pending = None;
+                return None;
+            }
+        };
+
+        let pending = self.pending.take()?;
+        if let Err(err) = result {
+            log::error!("picking: readback failed: {err}");
+            return None;
+        }
+
+        let value = {
+            let data = pending.buffer.slice(..).get_mapped_range();
+            u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+        };
+        pending.buffer.unmap();
+
+        Some(PickResult::decode(pending.x, pending.y, value))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_background()
+    {
+        assert_eq!(PickResult::decode(3, 4, 0), PickResult { x: 3, y: 4, hit: None });
+    }
+
+    #[test]
+    fn decode_draw_id_in_the_upper_bits()
+    {
+        // draw ids are stored plus one, 0 is the background
+        let hit = PickResult::decode(0, 0, (1 << 16) | 7).hit;
+        assert_eq!(hit, Some(PickHit { draw_id: DrawId(0), instance: 7 }));
+
+        let hit = PickResult::decode(0, 0, (MAX_PICK_OBJECTS << 16) | (MAX_PICK_INSTANCES - 1)).hit;
+        assert_eq!(hit, Some(PickHit { draw_id: DrawId(MAX_PICK_OBJECTS - 1), instance: MAX_PICK_INSTANCES - 1 }));
+    }
+
+    #[test]
+    fn draw_ids_fit_in_the_upper_bits()
+    {
+        assert!((MAX_PICK_OBJECTS as u64) << 16 <= u32::MAX as u64);
+    }
+}
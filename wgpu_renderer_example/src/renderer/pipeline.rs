@@ -0,0 +1,215 @@
+//! Pipelines of the materials, their picking and shadow variants and the grid, with a configurable sample count
+//!
+//! The built in pipelines of the vertex color, vertex texture and lit shader are
+//! materials as well, see [super::material::BuiltinMaterials].
+
+use super::material::MaterialDesc;
+use super::shadow::SHADOW_FORMAT;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// The scene is rendered in this format if the adapter supports it, and tonemapped into the frame
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+struct PipelineDescriptor<'a> {
+    label: &'a str,
+    shader: &'a wgpu::ShaderModule,
+    bind_group_layouts: &'a [&'a wgpu::BindGroupLayout],
+    buffers: &'a [wgpu::VertexBufferLayout<'a>],
+    topology: wgpu::PrimitiveTopology,
+    depth_compare: wgpu::CompareFunction,
+    depth_write_enabled: bool,
+    blend: Option<wgpu::BlendState>,
+    surface_format: wgpu::TextureFormat,
+    sample_count: u32,
+}
+
+pub struct Pipeline {
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl Pipeline {
+    /// Fullscreen triangle of the infinite grid, blended over the scene without writing depth
+    pub fn new_grid(device: &wgpu::Device,
+        grid_bind_group_layout: &wgpu::BindGroupLayout,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self
+    {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/grid.wgsl"));
+
+        Self::create(device, &PipelineDescriptor {
+            label: "Grid Pipeline",
+            shader: &shader,
+            bind_group_layouts: &[grid_bind_group_layout],
+            buffers: &[],
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: false,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            surface_format,
+            sample_count,
+        })
+    }
+
+    /// Writes draw ids into the picking target, the bind groups and buffers have to match the meshes,
+    /// the object group is the last one
+    pub fn new_picking(device: &wgpu::Device,
+        label: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        buffers: &[wgpu::VertexBufferLayout],
+        topology: wgpu::PrimitiveTopology,
+    ) -> Self
+    {
+        let object_group = bind_group_layouts.len() - 1;
+        let source = include_str!("shaders/picking.wgsl").replace("@group(2)", &format!("@group({object_group})"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("picking.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        Self::create(device, &PipelineDescriptor {
+            label,
+            shader: &shader,
+            bind_group_layouts,
+            buffers,
+            topology,
+            depth_compare: wgpu::CompareFunction::Less,
+            depth_write_enabled: true,
+            blend: None,
+            surface_format: super::picking::PICKING_FORMAT,
+            sample_count: 1,
+        })
+    }
+
+    /// Depth only from the light's point of view, the cascade is bound at group 0 and the bind groups
+    /// and buffers after it have to match the meshes
+    pub fn new_shadow(device: &wgpu::Device,
+        label: &str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        buffers: &[wgpu::VertexBufferLayout],
+        topology: wgpu::PrimitiveTopology,
+    ) -> Self
+    {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/shadow.wgsl"));
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_shadow",
+                buffers,
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // slope scaled bias against shadow acne on surfaces at a grazing angle to the light
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            render_pipeline,
+        }
+    }
+
+    /// The bind group layouts start with the camera, followed by the ones of the material
+    pub fn new_material(device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        material: &MaterialDesc,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self
+    {
+        Self::create(device, &PipelineDescriptor {
+            label: &material.label,
+            shader,
+            bind_group_layouts,
+            buffers: &material.vertex_layouts,
+            topology: material.topology,
+            depth_compare: material.depth_compare,
+            depth_write_enabled: material.depth_write_enabled,
+            blend: material.blend,
+            surface_format,
+            sample_count,
+        })
+    }
+
+    fn create(device: &wgpu::Device, desc: &PipelineDescriptor) -> Self
+    {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(desc.label),
+            bind_group_layouts: desc.bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(desc.label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: desc.shader,
+                entry_point: "vs_main",
+                buffers: desc.buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: desc.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: desc.surface_format,
+                    blend: desc.blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: desc.topology,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: desc.depth_write_enabled,
+                depth_compare: desc.depth_compare,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: desc.sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            render_pipeline,
+        }
+    }
+
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.render_pipeline);
+    }
+}
@@ -0,0 +1,1059 @@
+//! Chain of fullscreen effects between the scene and the overlay
+//!
+//! The scene is rendered into an intermediate texture, every enabled effect reads
+//! the output of the previous one. The result is tonemapped into the overlay pass,
+//! so the gui and the performance monitor are drawn on top without post processing.
+//! With auto exposure the average luminance of the scene is reduced over a mip chain
+//! on the GPU and adapted over time, the tonemapper reads it without a readback.
+
+use wgpu::util::DeviceExt;
+
+use super::pipeline::DEPTH_FORMAT;
+
+const LUT_SIZE: u32 = 16;
+
+/// The scene is reduced to this size before its luminance is averaged over the mips
+const LUMINANCE_SIZE: u32 = 256;
+const LUMINANCE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PostEffectKind {
+    Bloom,
+    Tonemapping,
+    ColorGrading,
+    Vignette,
+    Fxaa,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PostEffect {
+    pub kind: PostEffectKind,
+    pub enabled: bool,
+}
+
+impl PostEffect {
+    /// All effects in their default order, disabled
+    pub fn default_chain() -> Vec<PostEffect> {
+        [
+            PostEffectKind::Bloom,
+            PostEffectKind::Tonemapping,
+            PostEffectKind::ColorGrading,
+            PostEffectKind::Vignette,
+            PostEffectKind::Fxaa,
+        ].into_iter()
+        .map(|kind| PostEffect { kind, enabled: false })
+        .collect()
+    }
+}
+
+/// One of the two textures the effects alternate between
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PingPong {
+    Ping,
+    Pong,
+}
+
+/// The enabled effects in the order of the chain with the texture each one renders into,
+/// the first one reads the scene and every other one the output of the effect before it
+fn chain(effects: &[PostEffect]) -> impl Iterator<Item = (PostEffectKind, PingPong)> + '_
+{
+    effects.iter()
+        .filter(|effect| effect.enabled)
+        .map(|effect| effect.kind)
+        .zip([PingPong::Ping, PingPong::Pong].into_iter().cycle())
+}
+
+/// Maps the scene colors into the displayable range
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tonemapper {
+    /// Clamps, bright colors clip to white
+    None,
+    Reinhard,
+    /// Filmic curve fitted by Krzysztof Narkowicz
+    Aces,
+    /// Minimal AgX fitted by Benjamin Wrensch, desaturates bright colors
+    AgX,
+}
+
+impl Tonemapper {
+    /// Each tonemapper after the other, back to the first
+    pub fn next(&self) -> Self {
+        match self {
+            Tonemapper::None => Tonemapper::Reinhard,
+            Tonemapper::Reinhard => Tonemapper::Aces,
+            Tonemapper::Aces => Tonemapper::AgX,
+            Tonemapper::AgX => Tonemapper::None,
+        }
+    }
+
+    /// Selects the curve in the shader
+    fn index(&self) -> u32 {
+        match self {
+            Tonemapper::None => 0,
+            Tonemapper::Reinhard => 1,
+            Tonemapper::Aces => 2,
+            Tonemapper::AgX => 3,
+        }
+    }
+}
+
+/// Exposure derived from the average luminance of the scene
+#[derive(Copy, Clone, Debug)]
+pub struct AutoExposure {
+    /// The average luminance is exposed to this value
+    pub key: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// How fast the exposure follows a change of the luminance, per second
+    pub adaptation_speed: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            key: 0.18,
+            min_exposure: 0.05,
+            max_exposure: 8.0,
+            adaptation_speed: 2.0,
+        }
+    }
+}
+
+impl AutoExposure {
+    /// Share of the difference to the measured luminance which is adapted in a frame
+    fn adaptation(&self, dt: instant::Duration) -> f32 {
+        1.0 - (-dt.as_secs_f32() * self.adaptation_speed).exp()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct PostSettings {
+    /// Scales the scene before tonemapping, with auto exposure it compensates the measured exposure
+    pub exposure: f32,
+    /// None for a manual exposure
+    pub auto_exposure: Option<AutoExposure>,
+    pub tonemapper: Tonemapper,
+    pub vignette_intensity: f32,
+    pub vignette_radius: f32,
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            auto_exposure: None,
+            tonemapper: Tonemapper::Aces,
+            vignette_intensity: 0.6,
+            vignette_radius: 0.45,
+            bloom_threshold: 0.8,
+            bloom_intensity: 0.7,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PostParams {
+    exposure: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    tonemapper: u32,
+    auto_exposure: u32,
+    exposure_key: f32,
+    exposure_min: f32,
+    exposure_max: f32,
+    adaptation: f32,
+    _padding: f32,
+}
+
+impl PostParams {
+    fn new(settings: PostSettings, adaptation: f32) -> Self {
+        let auto_exposure = settings.auto_exposure.unwrap_or_default();
+
+        Self {
+            exposure: settings.exposure,
+            vignette_intensity: settings.vignette_intensity,
+            vignette_radius: settings.vignette_radius,
+            bloom_threshold: settings.bloom_threshold,
+            bloom_intensity: settings.bloom_intensity,
+            tonemapper: settings.tonemapper.index(),
+            auto_exposure: settings.auto_exposure.is_some() as u32,
+            exposure_key: auto_exposure.key,
+            exposure_min: auto_exposure.min_exposure,
+            exposure_max: auto_exposure.max_exposure,
+            adaptation,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Parameters of the generated color grading lookup table
+#[derive(Copy, Clone, Debug)]
+pub struct ColorGrading {
+    pub saturation: f32,
+    pub contrast: f32,
+    pub warmth: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            saturation: 1.15,
+            contrast: 1.05,
+            warmth: 0.04,
+        }
+    }
+}
+
+impl ColorGrading {
+    fn grade(&self, color: [f32; 3]) -> [f32; 3] {
+        let luma = color[0] * 0.299 + color[1] * 0.587 + color[2] * 0.114;
+
+        let mut graded = color.map(|c| luma + (c - luma) * self.saturation);
+        graded = graded.map(|c| (c - 0.5) * self.contrast + 0.5);
+        graded[0] += self.warmth;
+        graded[2] -= self.warmth;
+
+        graded.map(|c| c.clamp(0.0, 1.0))
+    }
+
+    fn lut(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity((LUT_SIZE * LUT_SIZE * LUT_SIZE * 4) as usize);
+        let scale = 1.0 / (LUT_SIZE - 1) as f32;
+
+        for b in 0..LUT_SIZE {
+            for g in 0..LUT_SIZE {
+                for r in 0..LUT_SIZE {
+                    let graded = self.grade([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+                    data.extend(graded.map(|c| (c * 255.0).round() as u8));
+                    data.push(255);
+                }
+            }
+        }
+
+        data
+    }
+}
+
+/// A texture which is rendered to by one effect and sampled by the next
+struct PostTexture {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl PostTexture {
+    fn new(device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+        label: &str,
+    ) -> Self
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self::from_view(device, layout, sampler, view, label)
+    }
+
+    fn from_view(device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        view: wgpu::TextureView,
+        label: &str,
+    ) -> Self
+    {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        Self {
+            view,
+            bind_group,
+        }
+    }
+}
+
+struct PostTextures {
+    scene: PostTexture,
+    ping: PostTexture,
+    pong: PostTexture,
+    bloom_a: PostTexture,
+    bloom_b: PostTexture,
+}
+
+impl PostTextures {
+    fn new(device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Self
+    {
+        let width = size.width;
+        let height = size.height;
+
+        Self {
+            scene: PostTexture::new(device, layout, sampler, format, width, height, "Post Scene Texture"),
+            ping: PostTexture::new(device, layout, sampler, format, width, height, "Post Ping Texture"),
+            pong: PostTexture::new(device, layout, sampler, format, width, height, "Post Pong Texture"),
+            bloom_a: PostTexture::new(device, layout, sampler, format, width / 2, height / 2, "Post Bloom Texture A"),
+            bloom_b: PostTexture::new(device, layout, sampler, format, width / 2, height / 2, "Post Bloom Texture B"),
+        }
+    }
+}
+
+/// Average luminance of the scene for the auto exposure
+struct Luminance {
+    /// log2 of the luminance, every mip is the average of the one above down to 1x1
+    mips: Vec<PostTexture>,
+    /// The adapted luminance of the last frame, read by the tonemapper
+    adapted: wgpu::Texture,
+    adapted_bind_group: wgpu::BindGroup,
+    /// Written by the adaptation and then copied into adapted, which is read by it
+    target: wgpu::Texture,
+    target_view: wgpu::TextureView,
+}
+
+impl Luminance {
+    fn new(device: &wgpu::Device,
+        texture_bind_group_layout: &wgpu::BindGroupLayout,
+        luminance_bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> Self
+    {
+        let mip_level_count = LUMINANCE_SIZE.ilog2() + 1;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Post Luminance Texture"),
+            size: wgpu::Extent3d {
+                width: LUMINANCE_SIZE,
+                height: LUMINANCE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: LUMINANCE_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let mips = (0..mip_level_count)
+            .map(|mip_level| {
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: mip_level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                });
+                PostTexture::from_view(device, texture_bind_group_layout, sampler, view, "Post Luminance Mip")
+            })
+            .collect();
+
+        let create_texture = |label: &str, usage: wgpu::TextureUsages| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: LUMINANCE_FORMAT,
+            usage,
+            view_formats: &[],
+        });
+
+        let adapted = create_texture("Post Adapted Luminance Texture",
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST);
+        let adapted_view = adapted.create_view(&wgpu::TextureViewDescriptor::default());
+        let adapted_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Adapted Luminance Bind Group"),
+            layout: luminance_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&adapted_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+
+        let target = create_texture("Post Luminance Adaptation Texture",
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC);
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            mips,
+            adapted,
+            adapted_bind_group,
+            target,
+            target_view,
+        }
+    }
+}
+
+/// Output of the effect chain, drawn into the frame by [PostProcessing::blit]
+#[derive(Copy, Clone)]
+pub struct PostOutput<'a> {
+    bind_group: &'a wgpu::BindGroup,
+    /// A tonemapping effect in the chain already mapped it into the displayable range
+    tonemapped: bool,
+}
+
+pub struct PostProcessing {
+    effects: Vec<PostEffect>,
+    settings: PostSettings,
+    /// Adaptation of the auto exposure in the next frame, 1 to start from the measured luminance
+    adaptation: f32,
+
+    format: wgpu::TextureFormat,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+
+    params_buffer: wgpu::Buffer,
+    params_bind_group: wgpu::BindGroup,
+    lut_texture: wgpu::Texture,
+    lut_bind_group: wgpu::BindGroup,
+
+    textures: PostTextures,
+    luminance: Luminance,
+
+    pipeline_tonemapping: wgpu::RenderPipeline,
+    pipeline_fxaa: wgpu::RenderPipeline,
+    pipeline_vignette: wgpu::RenderPipeline,
+    pipeline_color_grading: wgpu::RenderPipeline,
+    pipeline_bloom_threshold: wgpu::RenderPipeline,
+    pipeline_blur_horizontal: wgpu::RenderPipeline,
+    pipeline_blur_vertical: wgpu::RenderPipeline,
+    pipeline_bloom_composite: wgpu::RenderPipeline,
+    pipeline_log_luminance: wgpu::RenderPipeline,
+    pipeline_luminance_downsample: wgpu::RenderPipeline,
+    pipeline_adapt_luminance: wgpu::RenderPipeline,
+    pipeline_blit: wgpu::RenderPipeline,
+    pipeline_resolve: wgpu::RenderPipeline,
+    pipeline_copy: wgpu::RenderPipeline,
+}
+
+impl PostProcessing {
+    /// The effects run in the format of the scene, the blit into the overlay pass is done with
+    /// the surface format and the sample count of the overlay pass,
+    /// upload a lookup table with [PostProcessing::set_color_grading] before using color grading
+    pub fn new(device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        surface_format: wgpu::TextureFormat,
+        size: winit::dpi::PhysicalSize<u32>,
+        sample_count: u32,
+    ) -> Self
+    {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/post_processing.wgsl"));
+
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let params_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Params Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let lut_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Lut Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let luminance_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Luminance Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        // params
+        let settings = PostSettings::default();
+        let adaptation = 1.0;
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Post Params Buffer"),
+            contents: bytemuck::cast_slice(&[PostParams::new(settings, adaptation)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Params Bind Group"),
+            layout: &params_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // color grading lut
+        let lut_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Post Lut Texture"),
+            size: wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: LUT_SIZE,
+                depth_or_array_layers: LUT_SIZE,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let lut_view = lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let lut_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Lut Bind Group"),
+            layout: &lut_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let textures = PostTextures::new(device, &texture_bind_group_layout, &sampler, format, size);
+        let luminance = Luminance::new(device, &texture_bind_group_layout, &luminance_bind_group_layout, &sampler);
+
+        // pipelines
+        let layout_effect = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Effect Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout, &params_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let layout_bloom = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Bloom Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout, &params_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let layout_lut = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Lut Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout, &params_bind_group_layout, &lut_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let layout_tonemap = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Tonemap Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout, &params_bind_group_layout, &luminance_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let layout_blit = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Blit Pipeline Layout"),
+            bind_group_layouts: &[&texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let effect = |layout: &wgpu::PipelineLayout, entry_point: &str| {
+            Self::create_pipeline(device, &shader, layout, entry_point, format, 1, false)
+        };
+
+        let pipeline_tonemapping = effect(&layout_tonemap, "fs_tonemapping");
+        let pipeline_fxaa = effect(&layout_effect, "fs_fxaa");
+        let pipeline_vignette = effect(&layout_effect, "fs_vignette");
+        let pipeline_color_grading = effect(&layout_lut, "fs_color_grading");
+        let pipeline_bloom_threshold = effect(&layout_effect, "fs_bloom_threshold");
+        let pipeline_blur_horizontal = effect(&layout_effect, "fs_blur_horizontal");
+        let pipeline_blur_vertical = effect(&layout_effect, "fs_blur_vertical");
+        let pipeline_bloom_composite = effect(&layout_bloom, "fs_bloom_composite");
+        let pipeline_log_luminance = Self::create_pipeline(device, &shader, &layout_effect, "fs_log_luminance", LUMINANCE_FORMAT, 1, false);
+        let pipeline_luminance_downsample = Self::create_pipeline(device, &shader, &layout_blit, "fs_blit", LUMINANCE_FORMAT, 1, false);
+        let pipeline_adapt_luminance = Self::create_pipeline(device, &shader, &layout_tonemap, "fs_adapt_luminance", LUMINANCE_FORMAT, 1, false);
+        let pipeline_blit = Self::create_pipeline(device, &shader, &layout_blit, "fs_blit", surface_format, sample_count, true);
+        let pipeline_resolve = Self::create_pipeline(device, &shader, &layout_tonemap, "fs_tonemapping", surface_format, sample_count, true);
+        let pipeline_copy = Self::create_pipeline(device, &shader, &layout_blit, "fs_copy", surface_format, 1, false);
+
+        Self {
+            effects: PostEffect::default_chain(),
+            settings,
+            adaptation,
+
+            format,
+            texture_bind_group_layout,
+            sampler,
+
+            params_buffer,
+            params_bind_group,
+            lut_texture,
+            lut_bind_group,
+
+            textures,
+            luminance,
+
+            pipeline_tonemapping,
+            pipeline_fxaa,
+            pipeline_vignette,
+            pipeline_color_grading,
+            pipeline_bloom_threshold,
+            pipeline_blur_horizontal,
+            pipeline_blur_vertical,
+            pipeline_bloom_composite,
+            pipeline_log_luminance,
+            pipeline_luminance_downsample,
+            pipeline_adapt_luminance,
+            pipeline_blit,
+            pipeline_resolve,
+            pipeline_copy,
+        }
+    }
+
+    fn create_pipeline(device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::PipelineLayout,
+        entry_point: &str,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        with_depth: bool,
+    ) -> wgpu::RenderPipeline
+    {
+        // the blit is the first draw of the overlay pass, which has a depth attachment
+        let depth_stencil = if with_depth {
+            Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            })
+        } else {
+            None
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) {
+        self.textures = PostTextures::new(device, &self.texture_bind_group_layout, &self.sampler, self.format, size);
+    }
+
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+
+    /// Replaces the chain, effects are applied in the given order
+    pub fn set_effects(&mut self, effects: Vec<PostEffect>) {
+        self.effects = effects;
+    }
+
+    /// Returns the new state, or None if the effect is not part of the chain
+    pub fn toggle(&mut self, kind: PostEffectKind) -> Option<bool> {
+        let effect = self.effects.iter_mut().find(|effect| effect.kind == kind)?;
+        effect.enabled = !effect.enabled;
+
+        Some(effect.enabled)
+    }
+
+    pub fn settings(&self) -> PostSettings {
+        self.settings
+    }
+
+    /// Turning on auto exposure starts from the luminance of the next frame
+    pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: PostSettings) {
+        if self.settings.auto_exposure.is_none() {
+            self.adaptation = 1.0;
+        }
+        self.settings = settings;
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[PostParams::new(settings, self.adaptation)]));
+    }
+
+    /// Adapts the auto exposure to the time the last frame took
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: instant::Duration)
+    {
+        let Some(auto_exposure) = self.settings.auto_exposure else {
+            return;
+        };
+
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[PostParams::new(self.settings, self.adaptation)]));
+        self.adaptation = auto_exposure.adaptation(dt);
+    }
+
+    /// Regenerates the lookup table of the color grading effect
+    pub fn set_color_grading(&mut self, queue: &wgpu::Queue, color_grading: ColorGrading) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.lut_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &color_grading.lut(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(LUT_SIZE * 4),
+                rows_per_image: Some(LUT_SIZE),
+            },
+            wgpu::Extent3d {
+                width: LUT_SIZE,
+                height: LUT_SIZE,
+                depth_or_array_layers: LUT_SIZE,
+            },
+        );
+    }
+
+    /// The forward pass renders the scene into this view
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.textures.scene.view
+    }
+
+    /// The unprocessed scene, used if the effect chain is not run
+    pub fn scene_output(&self) -> PostOutput<'_> {
+        PostOutput {
+            bind_group: &self.textures.scene.bind_group,
+            tonemapped: false,
+        }
+    }
+
+    /// Records all enabled effects, returns the final texture
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder) -> PostOutput<'_>
+    {
+        let mut tonemapped = false;
+        let textures = &self.textures;
+        let mut input = &textures.scene;
+
+        for (kind, target) in chain(&self.effects) {
+            let output = match target {
+                PingPong::Ping => &textures.ping,
+                PingPong::Pong => &textures.pong,
+            };
+
+            match kind {
+                PostEffectKind::Bloom => {
+                    Self::fullscreen_pass(encoder, "Bloom Threshold", &self.pipeline_bloom_threshold,
+                        &[&input.bind_group, &self.params_bind_group], &textures.bloom_a.view);
+                    Self::fullscreen_pass(encoder, "Bloom Blur Horizontal", &self.pipeline_blur_horizontal,
+                        &[&textures.bloom_a.bind_group, &self.params_bind_group], &textures.bloom_b.view);
+                    Self::fullscreen_pass(encoder, "Bloom Blur Vertical", &self.pipeline_blur_vertical,
+                        &[&textures.bloom_b.bind_group, &self.params_bind_group], &textures.bloom_a.view);
+                    Self::fullscreen_pass(encoder, "Bloom Composite", &self.pipeline_bloom_composite,
+                        &[&input.bind_group, &self.params_bind_group, &textures.bloom_a.bind_group], &output.view);
+                }
+                PostEffectKind::Tonemapping => {
+                    self.measure_luminance(encoder, &input.bind_group);
+                    Self::fullscreen_pass(encoder, "Tonemapping", &self.pipeline_tonemapping,
+                        &[&input.bind_group, &self.params_bind_group, &self.luminance.adapted_bind_group], &output.view);
+                    tonemapped = true;
+                }
+                PostEffectKind::ColorGrading => {
+                    Self::fullscreen_pass(encoder, "Color Grading", &self.pipeline_color_grading,
+                        &[&input.bind_group, &self.params_bind_group, &self.lut_bind_group], &output.view);
+                }
+                PostEffectKind::Vignette => {
+                    Self::fullscreen_pass(encoder, "Vignette", &self.pipeline_vignette,
+                        &[&input.bind_group, &self.params_bind_group], &output.view);
+                }
+                PostEffectKind::Fxaa => {
+                    Self::fullscreen_pass(encoder, "Fxaa", &self.pipeline_fxaa,
+                        &[&input.bind_group, &self.params_bind_group], &output.view);
+                }
+            }
+
+            input = output;
+        }
+
+        PostOutput {
+            bind_group: &input.bind_group,
+            tonemapped,
+        }
+    }
+
+    /// Averages the luminance of the input and adapts the exposure to it, only with auto exposure
+    fn measure_luminance(&self, encoder: &mut wgpu::CommandEncoder, input: &wgpu::BindGroup)
+    {
+        if self.settings.auto_exposure.is_none() {
+            return;
+        }
+
+        let luminance = &self.luminance;
+        Self::fullscreen_pass(encoder, "Log Luminance", &self.pipeline_log_luminance,
+            &[input, &self.params_bind_group], &luminance.mips[0].view);
+        for (source, target) in luminance.mips.iter().zip(&luminance.mips[1..]) {
+            Self::fullscreen_pass(encoder, "Luminance Downsample", &self.pipeline_luminance_downsample,
+                &[&source.bind_group], &target.view);
+        }
+
+        let average = luminance.mips.last().unwrap();
+        Self::fullscreen_pass(encoder, "Adapt Luminance", &self.pipeline_adapt_luminance,
+            &[&average.bind_group, &self.params_bind_group, &luminance.adapted_bind_group], &luminance.target_view);
+        encoder.copy_texture_to_texture(
+            luminance.target.as_image_copy(),
+            luminance.adapted.as_image_copy(),
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            });
+    }
+
+    /// Measures the luminance of the output for the auto exposure, before it is tonemapped by [PostProcessing::blit]
+    pub fn prepare_blit(&self, encoder: &mut wgpu::CommandEncoder, output: PostOutput)
+    {
+        if !output.tonemapped {
+            self.measure_luminance(encoder, output.bind_group);
+        }
+    }
+
+    fn fullscreen_pass(encoder: &mut wgpu::CommandEncoder,
+        label: &str,
+        pipeline: &wgpu::RenderPipeline,
+        bind_groups: &[&wgpu::BindGroup],
+        target: &wgpu::TextureView,
+    )
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        });
+
+        render_pass.set_pipeline(pipeline);
+        for (index, bind_group) in bind_groups.iter().enumerate() {
+            render_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Binds a texture in the surface format, so it can be copied with [PostProcessing::copy]
+    pub fn copy_source(&self, device: &wgpu::Device, view: &wgpu::TextureView) -> wgpu::BindGroup
+    {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Copy Source Bind Group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        })
+    }
+
+    /// Copies a texture of the surface format and size pixel by pixel into the target
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::BindGroup, target: &wgpu::TextureView) {
+        Self::fullscreen_pass(encoder, "Copy", &self.pipeline_copy, &[source], target);
+    }
+
+    /// Draws the result of [PostProcessing::run] into the current render pass,
+    /// tonemapped unless the chain already did it
+    pub fn blit<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, output: PostOutput<'a>) {
+        if output.tonemapped {
+            render_pass.set_pipeline(&self.pipeline_blit);
+        } else {
+            render_pass.set_pipeline(&self.pipeline_resolve);
+            render_pass.set_bind_group(1, &self.params_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.luminance.adapted_bind_group, &[]);
+        }
+        render_pass.set_bind_group(0, output.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_match_the_uniform_layout()
+    {
+        // twelve scalars, a multiple of 16 bytes as required for uniforms
+        assert_eq!(std::mem::size_of::<PostParams>(), 48);
+    }
+
+    #[test]
+    fn adaptation_follows_the_frame_time()
+    {
+        let auto_exposure = AutoExposure::default();
+
+        assert_eq!(auto_exposure.adaptation(instant::Duration::ZERO), 0.0);
+        assert!(auto_exposure.adaptation(instant::Duration::from_millis(16)) < auto_exposure.adaptation(instant::Duration::from_millis(100)));
+        assert!(auto_exposure.adaptation(instant::Duration::from_secs(60)) > 0.999);
+    }
+
+    #[test]
+    fn chain_keeps_the_order_of_the_enabled_effects()
+    {
+        let effects = vec![
+            PostEffect { kind: PostEffectKind::Fxaa, enabled: true },
+            PostEffect { kind: PostEffectKind::Bloom, enabled: false },
+            PostEffect { kind: PostEffectKind::Vignette, enabled: true },
+            PostEffect { kind: PostEffectKind::Tonemapping, enabled: true },
+        ];
+
+        let kinds: Vec<PostEffectKind> = chain(&effects).map(|(kind, _)| kind).collect();
+
+        assert_eq!(kinds, [PostEffectKind::Fxaa, PostEffectKind::Vignette, PostEffectKind::Tonemapping]);
+    }
+
+    #[test]
+    fn chain_alternates_the_targets()
+    {
+        let mut effects = PostEffect::default_chain();
+        effects[1].enabled = true;
+        effects[2].enabled = true;
+        effects[4].enabled = true;
+
+        let targets: Vec<PingPong> = chain(&effects).map(|(_, target)| target).collect();
+
+        // no effect writes the texture it reads
+        assert_eq!(targets, [PingPong::Ping, PingPong::Pong, PingPong::Ping]);
+    }
+
+    #[test]
+    fn disabled_chain_is_empty()
+    {
+        assert_eq!(chain(&PostEffect::default_chain()).count(), 0);
+    }
+
+    #[test]
+    fn tonemappers_cycle()
+    {
+        let tonemapper = Tonemapper::None;
+
+        assert_eq!(tonemapper.next().next().next().next(), tonemapper);
+    }
+}
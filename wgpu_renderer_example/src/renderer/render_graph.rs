@@ -0,0 +1,474 @@
+//! Declares the passes of a frame, their attachments and dependencies
+//!
+//! The graph orders the passes by their dependencies, allocates the transient
+//! textures they render into and is recorded once per frame by the [super::Renderer].
+
+use std::collections::HashMap;
+
+use super::picking::PICKING_FORMAT;
+use super::pipeline::DEPTH_FORMAT;
+
+/// Transient textures of the picking pass, the draw ids and their depth
+pub const PICKING_ATTACHMENT: &str = "picking";
+pub const PICKING_DEPTH_ATTACHMENT: &str = "picking_depth";
+
+/// A texture of the size of the frame allocated by the graph,
+/// recreated when its description or the frame size changes
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AttachmentDesc {
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl AttachmentDesc {
+    pub fn new(format: wgpu::TextureFormat, usage: wgpu::TextureUsages) -> Self {
+        Self {
+            format,
+            usage,
+        }
+    }
+
+    fn extent(frame_size: winit::dpi::PhysicalSize<u32>) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: frame_size.width.max(1),
+            height: frame_size.height.max(1),
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+struct TransientTexture {
+    desc: AttachmentDesc,
+    extent: wgpu::Extent3d,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// A texture a pass draws into
+#[derive(Copy, Clone)]
+pub struct AttachmentView<'a> {
+    pub view: &'a wgpu::TextureView,
+    /// Set if the view is multisampled, it is resolved into this view
+    pub resolve_target: Option<&'a wgpu::TextureView>,
+}
+
+impl<'a> AttachmentView<'a> {
+    /// Keeps what is drawn, so a following pass can load it again
+    pub fn color_attachment(&self, load: wgpu::LoadOp<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'a> {
+        wgpu::RenderPassColorAttachment {
+            view: self.view,
+            resolve_target: self.resolve_target,
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        }
+    }
+}
+
+/// Everything a pass can use while it is recorded
+pub struct PassContext<'a> {
+    pub encoder: &'a mut wgpu::CommandEncoder,
+
+    /// The texture which is presented at the end of the frame
+    pub frame_view: &'a wgpu::TextureView,
+    pub frame_size: winit::dpi::PhysicalSize<u32>,
+
+    scene_color: AttachmentView<'a>,
+    scene_depth: &'a wgpu::TextureView,
+    transient_textures: &'a HashMap<String, TransientTexture>,
+}
+
+impl<'a> PassContext<'a> {
+    /// A transient texture declared with [RenderGraphNode::with_attachment] by any pass
+    pub fn attachment(&self, name: &str) -> Option<AttachmentView<'a>> {
+        self.transient_textures.get(name).map(|transient| AttachmentView {
+            view: &transient.view,
+            resolve_target: None,
+        })
+    }
+
+    /// The texture of a declared transient texture, e.g. to copy from it
+    pub fn attachment_texture(&self, name: &str) -> Option<&'a wgpu::Texture> {
+        self.transient_textures.get(name).map(|transient| &transient.texture)
+    }
+
+    /// The scene before post processing, multisampled with the sample count of the renderer
+    pub fn scene_color(&self) -> AttachmentView<'a> {
+        self.scene_color
+    }
+
+    /// Depth of the scene, with the same sample count as its color
+    pub fn scene_depth(&self) -> AttachmentView<'a> {
+        AttachmentView {
+            view: self.scene_depth,
+            resolve_target: None,
+        }
+    }
+}
+
+/// A pass provided by the application
+pub trait RenderGraphPass {
+    fn record(&self, context: &mut PassContext);
+
+    /// Creates the resources of the pass again after the device was lost
+    fn recreate(&mut self, _device: &wgpu::Device) {}
+}
+
+/// The stages of the [super::Renderer] and passes inserted by the application
+pub enum PassKind {
+    /// Depth of the lit meshes from the directional light into the shadow map
+    Shadow,
+    /// Colored, lit and textured meshes with the perspective camera
+    Forward,
+    /// The infinite ground grid, blended over the opaque meshes
+    Grid,
+    /// Alpha blended meshes back to front, on top of the forward pass without writing depth
+    Transparent,
+    /// Draw ids into the picking target, only for frames with a pick request
+    Picking,
+    /// The opaque meshes from above into the minimap texture
+    Minimap,
+    /// Lines of the debug draw API on top of the scene
+    DebugDraw,
+    /// The effect chain on the output of the forward pass
+    PostProcessing,
+    /// Draws the post processed scene into the frame
+    Composite,
+    PerformanceMonitor,
+    /// Orientation of the camera in the corner of the frame
+    Gizmo,
+    Gui,
+    Custom(Box<dyn RenderGraphPass>),
+}
+
+pub struct RenderGraphNode {
+    name: String,
+    kind: PassKind,
+    after: Vec<String>,
+    before: Vec<String>,
+    attachments: Vec<(String, AttachmentDesc)>,
+}
+
+impl RenderGraphNode {
+    pub fn new(name: &str, kind: PassKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            after: Vec::new(),
+            before: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn custom(name: &str, pass: impl RenderGraphPass + 'static) -> Self {
+        Self::new(name, PassKind::Custom(Box::new(pass)))
+    }
+
+    /// Records this pass after the named one
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(name.to_string());
+        self
+    }
+
+    /// Records this pass before the named one
+    pub fn before(mut self, name: &str) -> Self {
+        self.before.push(name.to_string());
+        self
+    }
+
+    /// Declares a transient texture, which is allocated by the graph
+    pub fn with_attachment(mut self, name: &str, desc: AttachmentDesc) -> Self {
+        self.attachments.push((name.to_string(), desc));
+        self
+    }
+
+    pub fn kind(&self) -> &PassKind {
+        &self.kind
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderGraphError {
+    DuplicatePass(String),
+    UnknownDependency { pass: String, dependency: String },
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderGraphError::DuplicatePass(name) => write!(f, "pass {name} already exists"),
+            RenderGraphError::UnknownDependency { pass, dependency } =>
+                write!(f, "pass {pass} depends on {dependency}, which does not exist"),
+            RenderGraphError::Cycle(names) => write!(f, "passes {} depend on each other", names.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+pub struct RenderGraph {
+    nodes: Vec<RenderGraphNode>,
+    order: Vec<usize>,
+
+    transient_textures: HashMap<String, TransientTexture>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            order: Vec::new(),
+
+            transient_textures: HashMap::new(),
+        }
+    }
+
+    /// The stages of the renderer in their default order
+    pub fn new_default() -> Self {
+        let mut graph = Self::new();
+
+        let nodes = [
+            RenderGraphNode::new("shadow", PassKind::Shadow),
+            RenderGraphNode::new("forward", PassKind::Forward).after("shadow"),
+            RenderGraphNode::new("grid", PassKind::Grid).after("forward"),
+            RenderGraphNode::new("transparent", PassKind::Transparent).after("grid"),
+            RenderGraphNode::new("picking", PassKind::Picking).after("transparent")
+                .with_attachment(PICKING_ATTACHMENT, AttachmentDesc::new(PICKING_FORMAT,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC))
+                .with_attachment(PICKING_DEPTH_ATTACHMENT, AttachmentDesc::new(DEPTH_FORMAT,
+                    wgpu::TextureUsages::RENDER_ATTACHMENT)),
+            RenderGraphNode::new("minimap", PassKind::Minimap).after("picking"),
+            RenderGraphNode::new("debug_draw", PassKind::DebugDraw).after("minimap"),
+            RenderGraphNode::new("post_processing", PassKind::PostProcessing).after("debug_draw"),
+            RenderGraphNode::new("composite", PassKind::Composite).after("post_processing"),
+            RenderGraphNode::new("performance_monitor", PassKind::PerformanceMonitor).after("composite"),
+            RenderGraphNode::new("gizmo", PassKind::Gizmo).after("performance_monitor"),
+            RenderGraphNode::new("gui", PassKind::Gui).after("gizmo"),
+        ];
+
+        for node in nodes {
+            graph.add_pass(node).unwrap();
+        }
+
+        graph
+    }
+
+    pub fn add_pass(&mut self, node: RenderGraphNode) -> Result<(), RenderGraphError> {
+        if self.nodes.iter().any(|existing| existing.name == node.name) {
+            return Err(RenderGraphError::DuplicatePass(node.name));
+        }
+
+        self.nodes.push(node);
+        if let Err(err) = self.compile() {
+            self.nodes.pop();
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Orders the passes, passes without a dependency between them keep the order they were added in
+    fn compile(&mut self) -> Result<(), RenderGraphError> {
+        let index_of = |name: &str, pass: &str| {
+            self.nodes.iter()
+                .position(|node| node.name == name)
+                .ok_or_else(|| RenderGraphError::UnknownDependency {
+                    pass: pass.to_string(),
+                    dependency: name.to_string(),
+                })
+        };
+
+        // edges from a pass to the passes which have to wait for it
+        let mut edges = vec![Vec::new(); self.nodes.len()];
+        let mut in_degree = vec![0; self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            for name in &node.after {
+                let dependency = index_of(name, &node.name)?;
+                edges[dependency].push(index);
+                in_degree[index] += 1;
+            }
+            for name in &node.before {
+                let dependent = index_of(name, &node.name)?;
+                edges[index].push(dependent);
+                in_degree[dependent] += 1;
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut done = vec![false; self.nodes.len()];
+        while order.len() < self.nodes.len() {
+            let next = (0..self.nodes.len()).find(|&index| !done[index] && in_degree[index] == 0);
+
+            let Some(next) = next else {
+                let names = (0..self.nodes.len())
+                    .filter(|&index| !done[index])
+                    .map(|index| self.nodes[index].name.clone())
+                    .collect();
+                return Err(RenderGraphError::Cycle(names));
+            };
+
+            done[next] = true;
+            order.push(next);
+            for &dependent in &edges[next] {
+                in_degree[dependent] -= 1;
+            }
+        }
+
+        self.order = order;
+
+        Ok(())
+    }
+
+    /// The passes in the order they are recorded
+    pub fn passes(&self) -> impl Iterator<Item = &RenderGraphNode> {
+        self.order.iter().map(|&index| &self.nodes[index])
+    }
+
+    /// Recreates the custom passes on a new device, the next prepare allocates the transient textures
+    pub fn recreate(&mut self, device: &wgpu::Device)
+    {
+        self.transient_textures.clear();
+
+        for node in &mut self.nodes {
+            if let PassKind::Custom(pass) = &mut node.kind {
+                pass.recreate(device);
+            }
+        }
+    }
+
+    /// Allocates the transient textures of all passes, call once before recording a frame
+    pub fn prepare(&mut self, device: &wgpu::Device, frame_size: winit::dpi::PhysicalSize<u32>) {
+        let declared: Vec<(&String, &AttachmentDesc)> = self.nodes.iter()
+            .flat_map(|node| node.attachments.iter().map(|(name, desc)| (name, desc)))
+            .collect();
+
+        self.transient_textures.retain(|name, _| declared.iter().any(|(declared, _)| *declared == name));
+
+        let extent = AttachmentDesc::extent(frame_size);
+        for (name, desc) in declared {
+            let up_to_date = self.transient_textures.get(name)
+                .map(|transient| transient.desc == *desc && transient.extent == extent)
+                .unwrap_or(false);
+
+            if up_to_date {
+                continue;
+            }
+
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(name),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: desc.format,
+                usage: desc.usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            self.transient_textures.insert(name.clone(), TransientTexture {
+                desc: *desc,
+                extent,
+                texture,
+                view,
+            });
+        }
+    }
+
+    pub fn context<'a>(&'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        frame_view: &'a wgpu::TextureView,
+        frame_size: winit::dpi::PhysicalSize<u32>,
+        scene_color: AttachmentView<'a>,
+        scene_depth: &'a wgpu::TextureView,
+    ) -> PassContext<'a>
+    {
+        PassContext {
+            encoder,
+            frame_view,
+            frame_size,
+
+            scene_color,
+            scene_depth,
+            transient_textures: &self.transient_textures,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct EmptyPass;
+
+    impl RenderGraphPass for EmptyPass {
+        fn record(&self, _context: &mut PassContext) {}
+    }
+
+    fn names(graph: &RenderGraph) -> Vec<&str> {
+        graph.passes().map(|node| node.name.as_str()).collect()
+    }
+
+    #[test]
+    fn passes_follow_their_dependencies()
+    {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(RenderGraphNode::custom("composite", EmptyPass)).unwrap();
+        graph.add_pass(RenderGraphNode::custom("forward", EmptyPass).before("composite")).unwrap();
+        graph.add_pass(RenderGraphNode::custom("shadow", EmptyPass).before("forward")).unwrap();
+        graph.add_pass(RenderGraphNode::custom("outline", EmptyPass).after("forward").before("composite")).unwrap();
+
+        assert_eq!(names(&graph), ["shadow", "forward", "outline", "composite"]);
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order()
+    {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(RenderGraphNode::custom("a", EmptyPass)).unwrap();
+        graph.add_pass(RenderGraphNode::custom("b", EmptyPass)).unwrap();
+        graph.add_pass(RenderGraphNode::custom("c", EmptyPass).before("a")).unwrap();
+
+        assert_eq!(names(&graph), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn cycle_is_rejected()
+    {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(RenderGraphNode::custom("a", EmptyPass)).unwrap();
+        graph.add_pass(RenderGraphNode::custom("b", EmptyPass).after("a")).unwrap();
+
+        let result = graph.add_pass(RenderGraphNode::custom("c", EmptyPass).after("b").before("a"));
+
+        assert_eq!(result, Err(RenderGraphError::Cycle(vec!["a".to_string(), "b".to_string(), "c".to_string()])));
+        // the graph is unchanged
+        assert_eq!(names(&graph), ["a", "b"]);
+    }
+
+    #[test]
+    fn unknown_dependency_is_rejected()
+    {
+        let mut graph = RenderGraph::new();
+
+        let result = graph.add_pass(RenderGraphNode::custom("a", EmptyPass).after("missing"));
+
+        assert_eq!(result, Err(RenderGraphError::UnknownDependency {
+            pass: "a".to_string(),
+            dependency: "missing".to_string(),
+        }));
+        assert!(graph.add_pass(RenderGraphNode::custom("a", EmptyPass)).is_ok());
+    }
+
+    #[test]
+    fn duplicate_pass_is_rejected()
+    {
+        let mut graph = RenderGraph::new_default();
+
+        let result = graph.add_pass(RenderGraphNode::custom("forward", EmptyPass));
+
+        assert_eq!(result, Err(RenderGraphError::DuplicatePass("forward".to_string())));
+    }
+}
@@ -0,0 +1,637 @@
+//! Collects the drawables of a frame and orders them to minimize state changes
+//!
+//! Every draw item gets a sort key made of its pipeline, its texture bind group and
+//! its depth. Sorting by the key binds every pipeline once and draws meshes with the
+//! same texture one after another, opaque meshes front to back. Instance ranges of
+//! the same buffers which end up next to each other are merged into one draw.
+//!
+//! Transparent meshes and meshes of blending materials are kept apart and drawn after
+//! all opaque ones, back to front, so they blend over everything behind them. Their order
+//! depends on the view, every viewport sorts them along its own view direction.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use wgpu_renderer::vertex_texture_shader::VertexTextureShaderDraw;
+use wgpu_renderer::vertex_color_shader;
+
+use super::culling::{self, Bounded, ColorDrawable, CullingStats, Frustum, LitDrawable, MaterialDrawable, TextureDrawable};
+use super::material::MaterialHandle;
+
+/// Identifies the state a drawable binds, so the [RenderQueue] can group draws
+pub trait Batched {
+    /// Drawables with the same key share their texture bind group, 0 if there is none
+    fn texture_key(&self) -> u64 {
+        0
+    }
+
+    /// Binds the texture bind group, [culling::DrawInstances::draw_instances] expects it to be bound
+    fn bind_texture<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+
+    /// Drawables with the same key share their vertex, index and instance buffers,
+    /// by default only the pushes of the same drawable, it is only compared and never ordered
+    fn buffer_key(&self) -> u64 {
+        self as *const Self as *const () as usize as u64
+    }
+}
+
+/// Meshes of wgpu_renderer have no texture
+impl Batched for vertex_color_shader::Mesh {}
+
+/// Pipelines in the order they are drawn, materials after the built in pipelines
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum QueuePipeline {
+    Color,
+    Lit,
+    Texture,
+    Material(MaterialHandle),
+}
+
+impl QueuePipeline {
+    /// pipeline | material, 16 bits
+    fn sort_bits(&self) -> u64 {
+        match self {
+            QueuePipeline::Color => 0,
+            QueuePipeline::Lit => 1 << 12,
+            QueuePipeline::Texture => 2 << 12,
+            QueuePipeline::Material(handle) => (3 << 12) | (handle.index() as u64 & 0x0fff),
+        }
+    }
+}
+
+/// pipeline | material | texture | depth, from the most to the least significant bits
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey(u64);
+
+impl SortKey {
+    /// The texture index counts the textures of a frame, textures after the first 65536 share the last index
+    pub fn new(pipeline: QueuePipeline, texture_index: u64, depth: f32) -> Self
+    {
+        let pipeline = pipeline.sort_bits() << 48;
+        let texture = texture_index.min(0xffff) << 32;
+        // the bits of positive floats sort like the floats
+        let depth = depth.max(0.0).to_bits() as u64;
+
+        Self(pipeline | texture | depth)
+    }
+
+    /// The bits of the depth, they compare like the depth
+    #[cfg(test)]
+    pub fn depth(&self) -> u32 {
+        self.0 as u32
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum QueueMesh<'a> {
+    Color(&'a dyn ColorDrawable),
+    Lit(&'a dyn LitDrawable),
+    Texture(&'a dyn TextureDrawable),
+    Material(&'a dyn MaterialDrawable),
+}
+
+impl<'a> QueueMesh<'a> {
+    pub fn pipeline(&self) -> QueuePipeline {
+        match self {
+            QueueMesh::Color(_) => QueuePipeline::Color,
+            QueueMesh::Lit(_) => QueuePipeline::Lit,
+            QueueMesh::Texture(_) => QueuePipeline::Texture,
+            QueueMesh::Material(mesh) => QueuePipeline::Material(mesh.material()),
+        }
+    }
+
+    fn bounded(&self) -> &'a dyn Bounded {
+        match *self {
+            QueueMesh::Color(mesh) => mesh,
+            QueueMesh::Lit(mesh) => mesh,
+            QueueMesh::Texture(mesh) => mesh,
+            QueueMesh::Material(mesh) => mesh,
+        }
+    }
+
+    fn texture_key(&self) -> u64 {
+        match self {
+            QueueMesh::Color(mesh) => mesh.texture_key(),
+            QueueMesh::Lit(mesh) => mesh.texture_key(),
+            QueueMesh::Texture(mesh) => mesh.texture_key(),
+            QueueMesh::Material(mesh) => mesh.texture_key(),
+        }
+    }
+
+    fn buffer_key(&self) -> u64 {
+        match self {
+            QueueMesh::Color(mesh) => mesh.buffer_key(),
+            QueueMesh::Lit(mesh) => mesh.buffer_key(),
+            QueueMesh::Texture(mesh) => mesh.buffer_key(),
+            QueueMesh::Material(mesh) => mesh.buffer_key(),
+        }
+    }
+
+    fn bind_texture(&self, render_pass: &mut wgpu::RenderPass<'a>) {
+        match *self {
+            QueueMesh::Color(mesh) => mesh.bind_texture(render_pass),
+            QueueMesh::Lit(mesh) => mesh.bind_texture(render_pass),
+            QueueMesh::Texture(mesh) => mesh.bind_texture(render_pass),
+            QueueMesh::Material(mesh) => mesh.bind_texture(render_pass),
+        }
+    }
+
+    /// Draws the instances, or all of them, without culling, after [TextureBinds::bind]
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass<'a>, instances: Option<Range<u32>>)
+    {
+        if let (Some(instances), Some(instance_draw)) = (instances, self.bounded().instance_draw()) {
+            instance_draw.draw_instances(render_pass, instances);
+            return;
+        }
+
+        match *self {
+            QueueMesh::Color(mesh) => mesh.draw(render_pass),
+            QueueMesh::Lit(mesh) => mesh.draw(render_pass),
+            QueueMesh::Texture(mesh) => mesh.draw(render_pass),
+            QueueMesh::Material(mesh) => mesh.draw(render_pass),
+        }
+    }
+
+    /// Draws the visible instances, see [culling::draw_culled]
+    pub fn draw_culled(&self,
+        frustum: &Frustum,
+        stats: &mut CullingStats,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        instances: Option<Range<u32>>,
+    )
+    {
+        match *self {
+            QueueMesh::Color(mesh) => culling::draw_culled(frustum, stats, render_pass, mesh, instances, |mesh, render_pass| mesh.draw(render_pass)),
+            QueueMesh::Lit(mesh) => culling::draw_culled(frustum, stats, render_pass, mesh, instances, |mesh, render_pass| mesh.draw(render_pass)),
+            QueueMesh::Texture(mesh) => culling::draw_culled(frustum, stats, render_pass, mesh, instances, |mesh, render_pass| mesh.draw(render_pass)),
+            QueueMesh::Material(mesh) => culling::draw_culled(frustum, stats, render_pass, mesh, instances, |mesh, render_pass| mesh.draw(render_pass)),
+        }
+    }
+}
+
+/// Binds the textures of the queue items, skips the bind if the previous draw had the same texture
+#[derive(Default)]
+pub struct TextureBinds {
+    current: Option<u64>,
+}
+
+impl TextureBinds {
+    /// Call after binding a pipeline, it may reset the bind groups
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+
+    pub fn bind<'a>(&mut self, render_pass: &mut wgpu::RenderPass<'a>, mesh: QueueMesh<'a>)
+    {
+        if self.changes(mesh.texture_key()) {
+            mesh.bind_texture(render_pass);
+        }
+    }
+
+    fn changes(&mut self, texture_key: u64) -> bool {
+        self.current.replace(texture_key) != Some(texture_key)
+    }
+}
+
+/// Identifies a pushed mesh within one frame, ids count up from 0 in the order of the pushes
+///
+/// Merged instance ranges of the same buffers keep the id of the first push.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DrawId(pub u32);
+
+#[derive(Clone)]
+pub struct DrawItem<'a> {
+    pub id: DrawId,
+    pub mesh: QueueMesh<'a>,
+    /// None draws all instances
+    pub instances: Option<Range<u32>>,
+    pub key: SortKey,
+}
+
+#[derive(Default)]
+pub struct RenderQueue<'a> {
+    items: Vec<DrawItem<'a>>,
+    transparent: Vec<DrawItem<'a>>,
+    gui: Vec<&'a dyn VertexTextureShaderDraw>,
+
+    pushed: u32,
+}
+
+impl<'a> RenderQueue<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_color(&mut self, mesh: &'a dyn ColorDrawable) -> DrawId {
+        self.push(QueueMesh::Color(mesh), None)
+    }
+
+    pub fn push_lit(&mut self, mesh: &'a dyn LitDrawable) -> DrawId {
+        self.push(QueueMesh::Lit(mesh), None)
+    }
+
+    #[cfg(test)]
+    pub fn push_texture(&mut self, mesh: &'a dyn TextureDrawable) -> DrawId {
+        self.push(QueueMesh::Texture(mesh), None)
+    }
+
+    /// Drawn with the pipeline of its material, register it with the renderer before,
+    /// meshes of blending materials are drawn with the transparent ones
+    pub fn push_material(&mut self, mesh: &'a dyn MaterialDrawable) -> DrawId {
+        self.push(QueueMesh::Material(mesh), None)
+    }
+
+    /// Draws only the given instances with [Bounded::instance_draw],
+    /// meshes which can only be drawn whole are drawn with all of their instances
+    #[cfg(test)]
+    pub fn push_instances(&mut self, mesh: QueueMesh<'a>, instances: Range<u32>) -> DrawId {
+        self.push(mesh, Some(instances))
+    }
+
+    /// Alpha blended after the opaque meshes, without writing depth
+    pub fn push_transparent(&mut self, mesh: &'a dyn TextureDrawable) -> DrawId
+    {
+        let id = self.next_id();
+        self.transparent.push(DrawItem {
+            id,
+            mesh: QueueMesh::Texture(mesh),
+            instances: None,
+            key: SortKey(0),
+        });
+
+        id
+    }
+
+    /// Drawn on top of the scene with the orthographic camera, in the order they are pushed
+    #[cfg(test)]
+    pub fn push_gui(&mut self, mesh: &'a dyn VertexTextureShaderDraw) {
+        self.gui.push(mesh);
+    }
+
+    fn push(&mut self, mesh: QueueMesh<'a>, instances: Option<Range<u32>>) -> DrawId
+    {
+        let id = self.next_id();
+        self.items.push(DrawItem {
+            id,
+            mesh,
+            instances,
+            key: SortKey(0),
+        });
+
+        id
+    }
+
+    fn next_id(&mut self) -> DrawId
+    {
+        let id = DrawId(self.pushed);
+        self.pushed += 1;
+        id
+    }
+
+    /// Moves the meshes of transparent materials to the transparent meshes, call before [RenderQueue::sort]
+    pub fn split_transparent(&mut self, is_transparent: impl Fn(MaterialHandle) -> bool)
+    {
+        let (transparent, opaque) = std::mem::take(&mut self.items).into_iter()
+            .partition(|item| matches!(item.mesh.pipeline(), QueuePipeline::Material(handle) if is_transparent(handle)));
+
+        self.items = opaque;
+        self.transparent.extend::<Vec<_>>(transparent);
+    }
+
+    /// Sorts by pipeline, texture and distance to the camera, then merges adjacent instance ranges
+    ///
+    /// Transparent meshes keep their order, they are sorted per view with [RenderQueue::transparent_back_to_front].
+    pub fn sort(&mut self, camera_position: glam::Vec3)
+    {
+        // textures and buffers are numbered in the order of their first push, so the order
+        // does not depend on their keys, items sharing buffers get the depth of the first one
+        let mut texture_indices = HashMap::<u64, u64>::new();
+        let mut buffers = HashMap::<u64, (u32, f32)>::new();
+        for item in &mut self.items {
+            let next_texture = texture_indices.len() as u64;
+            let texture_index = *texture_indices.entry(item.mesh.texture_key()).or_insert(next_texture);
+
+            let next_buffer = buffers.len() as u32;
+            let depth = Self::depth(item, camera_position);
+            let (_, depth) = *buffers.entry(item.mesh.buffer_key()).or_insert((next_buffer, depth));
+
+            item.key = SortKey::new(item.mesh.pipeline(), texture_index, depth);
+        }
+
+        self.items.sort_by_key(|item| (
+            item.key,
+            buffers[&item.mesh.buffer_key()].0,
+            item.instances.as_ref().map(|range| range.start),
+        ));
+
+        self.items = Self::merge(std::mem::take(&mut self.items));
+    }
+
+    /// The transparent meshes ordered by their depth along the view direction, the farthest first
+    ///
+    /// Unlike the distance to the camera, the depth also orders orthographic views.
+    pub fn transparent_back_to_front(&self, view: glam::Mat4) -> Vec<&DrawItem<'a>>
+    {
+        let mut items: Vec<_> = self.transparent.iter()
+            .map(|item| {
+                let depth = item.mesh.bounded()
+                    .bounding_volume()
+                    .map_or(0.0, |volume| -view.transform_point3(volume.center()).z);
+                (depth, item)
+            })
+            .collect();
+        items.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        items.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Distance of the center of the bounds to the camera, 0 for unbounded meshes
+    fn depth(item: &DrawItem, camera_position: glam::Vec3) -> f32
+    {
+        item.mesh.bounded()
+            .bounding_volume()
+            .map_or(0.0, |volume| volume.center().distance(camera_position))
+    }
+
+    fn merge(items: Vec<DrawItem<'a>>) -> Vec<DrawItem<'a>>
+    {
+        let mut merged: Vec<DrawItem<'a>> = Vec::with_capacity(items.len());
+
+        for item in items {
+            if let Some(last) = merged.last_mut() {
+                let same_buffers = last.key == item.key
+                    && last.mesh.pipeline() == item.mesh.pipeline()
+                    && last.mesh.buffer_key() == item.mesh.buffer_key();
+
+                match (&mut last.instances, &item.instances) {
+                    // the whole mesh is already drawn
+                    (None, _) if same_buffers => continue,
+                    (last_range, None) if same_buffers => {
+                        *last_range = None;
+                        continue;
+                    }
+                    (Some(last_range), Some(range)) if same_buffers && range.start <= last_range.end => {
+                        last_range.end = last_range.end.max(range.end);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            merged.push(item);
+        }
+
+        merged
+    }
+
+    /// The items in the order they are drawn, call [RenderQueue::sort] before
+    pub fn items(&self) -> &[DrawItem<'a>] {
+        &self.items
+    }
+
+    /// Opaque and transparent items, for passes which draw all meshes of the scene
+    pub fn scene_items(&self) -> impl Iterator<Item = &DrawItem<'a>> {
+        self.items.iter().chain(self.transparent.iter())
+    }
+
+    /// In the order they were pushed
+    pub fn transparent(&self) -> &[DrawItem<'a>] {
+        &self.transparent
+    }
+
+    pub fn gui(&self) -> &[&'a dyn VertexTextureShaderDraw] {
+        &self.gui
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu_renderer::vertex_color_shader::VertexColorShaderDraw;
+
+    use super::*;
+    use crate::renderer::{Aabb, MaterialShaderDraw};
+
+    struct Fake {
+        texture: u64,
+        center: glam::Vec3,
+    }
+
+    impl Fake {
+        fn new(texture: u64, distance: f32) -> Self {
+            Self { texture, center: glam::Vec3::new(0.0, 0.0, -distance) }
+        }
+    }
+
+    impl VertexColorShaderDraw for Fake {
+        fn draw<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+    }
+
+    impl VertexTextureShaderDraw for Fake {
+        fn draw<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+    }
+
+    /// The texture key doubles as the material
+    impl MaterialShaderDraw for Fake {
+        fn material(&self) -> MaterialHandle {
+            MaterialHandle::new(self.texture as u32)
+        }
+
+        fn draw<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+    }
+
+    impl Bounded for Fake {
+        fn bounding_volume(&self) -> Option<Aabb> {
+            Some(Aabb::new(self.center, self.center))
+        }
+    }
+
+    impl Batched for Fake {
+        fn texture_key(&self) -> u64 {
+            self.texture
+        }
+    }
+
+    fn ids(items: &[DrawItem]) -> Vec<u32> {
+        items.iter().map(|item| item.id.0).collect()
+    }
+
+    fn ids_of(items: Vec<&DrawItem>) -> Vec<u32> {
+        items.iter().map(|item| item.id.0).collect()
+    }
+
+    #[test]
+    fn sort_key_orders_pipeline_then_texture_then_depth()
+    {
+        assert!(SortKey::new(QueuePipeline::Color, 2, 100.0) < SortKey::new(QueuePipeline::Lit, 1, 1.0));
+        assert!(SortKey::new(QueuePipeline::Texture, 1, 100.0) < SortKey::new(QueuePipeline::Texture, 2, 1.0));
+        assert!(SortKey::new(QueuePipeline::Texture, 1, 1.0) < SortKey::new(QueuePipeline::Texture, 1, 2.0));
+        assert_eq!(SortKey::new(QueuePipeline::Lit, 0, -1.0).depth(), 0);
+    }
+
+    #[test]
+    fn sort_groups_by_pipeline_and_texture_front_to_back()
+    {
+        let near_a = Fake::new(1, 1.0);
+        let far_a = Fake::new(1, 10.0);
+        let near_b = Fake::new(2, 2.0);
+        let color = Fake::new(0, 50.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_texture(&near_b);
+        queue.push_texture(&far_a);
+        queue.push_color(&color);
+        queue.push_texture(&near_a);
+        queue.sort(glam::Vec3::ZERO);
+
+        // textures in the order of their first push
+        assert_eq!(ids(queue.items()), [2, 0, 3, 1]);
+    }
+
+    #[test]
+    fn textures_with_the_same_low_bits_stay_apart()
+    {
+        // the same low 16 bits, and keys which folded into the same 16 bits
+        for (key_a, key_b) in [(0x1_0005, 0x2_0005), (0x1, 0x1_0000)] {
+            let near_a = Fake::new(key_a, 1.0);
+            let middle_b = Fake::new(key_b, 2.0);
+            let far_a = Fake::new(key_a, 10.0);
+
+            let mut queue = RenderQueue::new();
+            queue.push_texture(&near_a);
+            queue.push_texture(&middle_b);
+            queue.push_texture(&far_a);
+            queue.sort(glam::Vec3::ZERO);
+
+            assert_eq!(ids(queue.items()), [0, 2, 1], "{key_a:#x} and {key_b:#x}");
+        }
+    }
+
+    #[test]
+    fn transparent_sorts_back_to_front()
+    {
+        let near = Fake::new(1, 1.0);
+        let far = Fake::new(2, 10.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_transparent(&near);
+        queue.push_transparent(&far);
+        queue.sort(glam::Vec3::ZERO);
+
+        assert_eq!(ids(queue.transparent()), [0, 1]);
+        assert_eq!(ids_of(queue.transparent_back_to_front(glam::Mat4::IDENTITY)), [1, 0]);
+    }
+
+    #[test]
+    fn transparent_order_depends_on_the_view()
+    {
+        let near = Fake::new(1, 1.0);
+        let far = Fake::new(2, 10.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_transparent(&near);
+        queue.push_transparent(&far);
+
+        // looking from behind the meshes back towards the origin
+        let behind = glam::Mat4::look_at_rh(glam::Vec3::new(0.0, 0.0, -20.0), glam::Vec3::ZERO, glam::Vec3::Y);
+        assert_eq!(ids_of(queue.transparent_back_to_front(behind)), [0, 1]);
+
+        // looking down, both are at the same depth and keep their order
+        let top = glam::Mat4::look_at_rh(glam::Vec3::new(0.0, 20.0, 0.0), glam::Vec3::ZERO, glam::Vec3::NEG_Z);
+        assert_eq!(ids_of(queue.transparent_back_to_front(top)), [0, 1]);
+    }
+
+    #[test]
+    fn transparent_materials_are_split_off()
+    {
+        let opaque = Fake::new(0, 1.0);
+        let transparent = Fake::new(1, 2.0);
+        let texture = Fake::new(1, 3.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_material(&opaque);
+        queue.push_material(&transparent);
+        queue.push_transparent(&texture);
+        queue.split_transparent(|handle| handle.index() == 1);
+        queue.sort(glam::Vec3::ZERO);
+
+        assert_eq!(ids(queue.items()), [0]);
+        assert_eq!(ids_of(queue.transparent_back_to_front(glam::Mat4::IDENTITY)), [2, 1]);
+    }
+
+    #[test]
+    fn gui_keeps_the_push_order_without_draw_ids()
+    {
+        let far = Fake::new(1, 10.0);
+        let near = Fake::new(2, 1.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_gui(&far);
+        queue.push_gui(&near);
+        let id = queue.push_texture(&near);
+        queue.sort(glam::Vec3::ZERO);
+
+        assert_eq!(id.0, 0);
+        assert_eq!(queue.gui().len(), 2);
+        assert!(std::ptr::addr_eq(queue.gui()[0], &far as &dyn VertexTextureShaderDraw));
+    }
+
+    #[test]
+    fn merge_joins_touching_instance_ranges()
+    {
+        let mesh = Fake::new(1, 1.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_instances(QueueMesh::Texture(&mesh), 4..8);
+        queue.push_instances(QueueMesh::Texture(&mesh), 0..4);
+        queue.push_instances(QueueMesh::Texture(&mesh), 10..12);
+        queue.sort(glam::Vec3::ZERO);
+
+        let items = queue.items();
+        assert_eq!(ids(items), [1, 2]);
+        assert_eq!(items[0].instances, Some(0..8));
+        assert_eq!(items[1].instances, Some(10..12));
+    }
+
+    #[test]
+    fn merge_keeps_the_whole_mesh()
+    {
+        let mesh = Fake::new(1, 1.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_instances(QueueMesh::Texture(&mesh), 2..3);
+        queue.push_texture(&mesh);
+        queue.sort(glam::Vec3::ZERO);
+
+        assert_eq!(queue.items().len(), 1);
+        assert_eq!(queue.items()[0].instances, None);
+    }
+
+    #[test]
+    fn merge_keeps_other_buffers_and_pipelines_apart()
+    {
+        let a = Fake::new(1, 1.0);
+        let b = Fake::new(1, 1.0);
+
+        let mut queue = RenderQueue::new();
+        queue.push_instances(QueueMesh::Texture(&a), 0..1);
+        queue.push_instances(QueueMesh::Texture(&b), 1..2);
+        queue.push_instances(QueueMesh::Color(&a), 1..2);
+        queue.sort(glam::Vec3::ZERO);
+
+        assert_eq!(queue.items().len(), 3);
+    }
+
+    #[test]
+    fn texture_binds_skip_the_same_texture()
+    {
+        let mut textures = TextureBinds::default();
+
+        assert!(textures.changes(1));
+        assert!(!textures.changes(1));
+        assert!(textures.changes(2));
+
+        textures.reset();
+        assert!(textures.changes(2));
+    }
+}
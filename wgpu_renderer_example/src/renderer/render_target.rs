@@ -0,0 +1,205 @@
+//! Abstraction over the texture a frame is rendered into
+//!
+//! Also how the renderer gets through errors of the surface and the loss of the device.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use wgpu_renderer::renderer::WgpuRendererInterface;
+
+/// A texture acquired from a [RenderTarget] for drawing one frame
+pub struct Frame {
+    surface_texture: Option<wgpu::SurfaceTexture>,
+    pub view: wgpu::TextureView,
+}
+
+impl Frame {
+    pub fn new(surface_texture: Option<wgpu::SurfaceTexture>, view: wgpu::TextureView) -> Self {
+        Self {
+            surface_texture,
+            view,
+        }
+    }
+
+    /// Schedules the frame to be shown on the window, does nothing for offscreen frames
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
+
+/// How the renderer handles an error of acquiring a frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SurfaceRecovery {
+    /// Configure the surface again, the next frame is drawn
+    Reconfigure,
+    /// Skip the frame and try again with the next one
+    Skip,
+    /// Can not be recovered from, the app should exit
+    Fatal,
+}
+
+impl SurfaceRecovery {
+    pub fn of(error: &wgpu::SurfaceError) -> Self {
+        match error {
+            wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => SurfaceRecovery::Reconfigure,
+            wgpu::SurfaceError::Timeout => SurfaceRecovery::Skip,
+            wgpu::SurfaceError::OutOfMemory => SurfaceRecovery::Fatal,
+        }
+    }
+}
+
+/// What happened to the frame passed to [super::Renderer::render]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameStatus {
+    Presented,
+    /// The surface was lost or outdated and is configured again
+    Reconfigured,
+    /// Acquiring the frame timed out
+    Skipped,
+    /// Nothing was drawn, recover with [super::Renderer::recover_device]
+    DeviceLost,
+}
+
+/// The device could not be created again
+#[derive(Debug)]
+pub enum DeviceError {
+    /// Only the offscreen target of the tests requests its adapter and device itself
+    #[cfg(test)]
+    NoAdapter,
+    #[cfg(test)]
+    RequestDevice(wgpu::RequestDeviceError),
+    /// Requesting a device is asynchronous on the web and can not be waited for in a frame
+    #[cfg(target_arch = "wasm32")]
+    Unsupported,
+}
+
+impl DeviceError {
+    /// Native builds without the tests have no variant, an empty match in [std::fmt::Display::fmt] would leave the formatter unused
+    fn message(&self) -> String
+    {
+        match *self {
+            #[cfg(test)]
+            DeviceError::NoAdapter => "no adapter is available".to_string(),
+            #[cfg(test)]
+            DeviceError::RequestDevice(ref error) => format!("requesting the device failed: {error}"),
+            #[cfg(target_arch = "wasm32")]
+            DeviceError::Unsupported => "the device can not be requested again on this platform".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+/// Set once the device it watches is lost
+#[derive(Clone, Default)]
+pub struct DeviceLost(Arc<AtomicBool>);
+
+impl DeviceLost {
+    pub fn watch(device: &wgpu::Device) -> Self
+    {
+        let lost = Self::default();
+        let flag = lost.0.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // dropping the device on recovery or replacing the callback is not a loss
+            if matches!(reason, wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::Destroyed) {
+                log::error!("device lost: {message}");
+                flag.store(true, Ordering::Release);
+            }
+        });
+
+        lost
+    }
+
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// For targets which lose their device on purpose, e.g. to test the recovery
+    #[cfg(test)]
+    pub fn set(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// Waits for the device request, which can not block on the web
+pub fn block_on_device<T>(request: impl std::future::Future<Output = Result<T, DeviceError>>) -> Result<T, DeviceError>
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    return pollster::block_on(request);
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        drop(request);
+        Err(DeviceError::Unsupported)
+    }
+}
+
+/// Everything the [super::Renderer] needs from a window surface or an offscreen texture
+pub trait RenderTarget: WgpuRendererInterface {
+    fn size(&self) -> winit::dpi::PhysicalSize<u32>;
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>);
+    fn surface_format(&self) -> wgpu::TextureFormat;
+    fn get_current_frame(&mut self) -> Result<Frame, wgpu::SurfaceError>;
+
+    /// Configures the surface again after it was lost or outdated
+    fn reconfigure(&mut self) {}
+
+    /// True once the device is lost, nothing created with it can be used anymore
+    fn device_lost(&self) -> bool;
+
+    /// Requests a new device and queue, the surface is configured for them
+    fn recreate_device(&mut self) -> Result<(), DeviceError>;
+
+    /// Whether the format can be rendered with the sample count, e.g. queried with [sample_count_supported]
+    fn sample_count_supported(&self, format: wgpu::TextureFormat, sample_count: u32) -> bool;
+}
+
+/// The sample counts of the adapter apply only if the device enabled them,
+/// otherwise the ones guaranteed by WebGPU
+pub fn sample_count_supported(adapter: &wgpu::Adapter,
+    device_features: wgpu::Features,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> bool
+{
+    let features = if device_features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+        adapter.get_texture_format_features(format)
+    } else {
+        format.guaranteed_format_features(device_features)
+    };
+
+    features.flags.sample_count_supported(sample_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn surface_errors_are_recovered()
+    {
+        assert_eq!(SurfaceRecovery::of(&wgpu::SurfaceError::Lost), SurfaceRecovery::Reconfigure);
+        assert_eq!(SurfaceRecovery::of(&wgpu::SurfaceError::Outdated), SurfaceRecovery::Reconfigure);
+        assert_eq!(SurfaceRecovery::of(&wgpu::SurfaceError::Timeout), SurfaceRecovery::Skip);
+        assert_eq!(SurfaceRecovery::of(&wgpu::SurfaceError::OutOfMemory), SurfaceRecovery::Fatal);
+    }
+
+    #[test]
+    fn device_lost_is_shared()
+    {
+        let lost = DeviceLost::default();
+        let watched = lost.clone();
+        assert!(!watched.get());
+
+        lost.set();
+        assert!(watched.get());
+    }
+}
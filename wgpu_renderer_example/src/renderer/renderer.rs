@@ -1,103 +1,196 @@
 //! implements all the render pipelines
 
-use crate::performance_monitor::PerformanceMonitor;
-use wgpu_renderer::renderer::{WgpuRenderer, self};
+use crate::performance_monitor::{self, PerformanceMonitor};
+use wgpu_renderer::renderer;
+#[cfg(test)]
+use super::offscreen::OffscreenRenderer;
+use super::window_target::WindowTarget;
+use super::frame_capture::{CaptureError, CaptureTexture, FrameCapture};
+use super::render_target::{DeviceError, FrameStatus, RenderTarget, SurfaceRecovery};
+use super::multisample::{self, MultisampleTargets};
+use super::post_processing::{ColorGrading, PostEffect, PostEffectKind, PostOutput, PostProcessing, PostSettings};
+use super::render_graph::{PassContext, PassKind, RenderGraph, PICKING_ATTACHMENT, PICKING_DEPTH_ATTACHMENT};
+use super::shadow::{ShadowCamera, ShadowSettings, Shadows};
 use wgpu_renderer::vertex_color_shader::{self, VertexColorShaderDraw};
 use wgpu_renderer::vertex_texture_shader::{self, VertexTextureShaderDraw};
+use crate::lit_shader;
+use super::culling::{self, CullingStats};
+use super::render_queue::{QueuePipeline, RenderQueue, TextureBinds};
+use super::debug_draw::DebugDraw;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use super::shader_reload::{ShaderKind, ShaderReload};
+use super::shader_validation::ShaderDiagnostic;
+use super::grid::Grid;
+use super::gizmo::{GizmoAxis, OrientationGizmo};
+use super::picking::{PickResult, PickTarget, Picking};
+use super::material::{BuiltinMaterials, MaterialBindGroup, MaterialDesc, MaterialFormats, MaterialHandle, Materials, RendererLayouts};
+use super::camera_controller::SAFE_FRAC_PI_2;
+use super::minimap::Minimap;
+use super::resources::ResourceManager;
+use super::viewport::{self, Viewport, ViewportLayout, ViewportProjection, ViewportRect, CAMERA_FOVY, CAMERA_ZFAR, CAMERA_ZNEAR, ORTHOGRAPHIC_HEIGHT};
+use crate::math::Ray;
+use std::f32::consts::{FRAC_PI_2, PI};
 use winit::event::{ElementState, MouseScrollDelta};
 
-pub struct Renderer<'a>
+pub struct Renderer<R: RenderTarget>
 {   
     // wgpu_renderer
-    pub wgpu_renderer: WgpuRenderer<'a>,
+    pub wgpu_renderer: R,
 
     pub camera_bind_group_layout: vertex_color_shader::CameraBindGroupLayout,
     pub texture_bind_group_layout: vertex_texture_shader::TextureBindGroupLayout,
+    pub light_bind_group_layout: lit_shader::LightBindGroupLayout,
 
-    _pipeline_color: vertex_color_shader::Pipeline,
-    pipeline_lines: vertex_color_shader::Pipeline,
-    pipeline_texture: vertex_texture_shader::Pipeline,
-    pipeline_texture_gui: vertex_texture_shader::Pipeline,
+    // materials, the built in pipelines are materials as well
+    materials: Materials,
+    builtin_materials: BuiltinMaterials,
 
-    // camera
-    camera: renderer::camera::Camera,
-    camera_controller: super::camera_controller::CameraController,
-    camera_projection: renderer::camera::Projection,
+    // msaa, the scene targets are in the scene format and the others in the surface format
+    scene_format: wgpu::TextureFormat,
+    scene_targets: MultisampleTargets,
+    multisample_targets: MultisampleTargets,
+
+    // post processing
+    post_processing: PostProcessing,
+
+    // frame capture
+    capture_requested: bool,
+    captured_frame: Option<Result<image::RgbaImage, CaptureError>>,
 
-    camera_uniform: vertex_color_shader::CameraUniform,
-    camera_uniform_buffer: vertex_color_shader::CameraUniformBuffer,
+    // render graph
+    render_graph: RenderGraph,
 
+    // viewports
+    viewports: Vec<Viewport>,
+    viewport_layout: ViewportLayout,
+    /// Receives the input, follows the cursor
+    active_viewport: usize,
+    /// Viewport of the pending pick request
+    pick_viewport: usize,
+
+    // camera
     camera_uniform_orthographic: vertex_color_shader::CameraUniform,
     camera_uniform_orthographic_buffer: vertex_color_shader::CameraUniformBuffer,
+
+    // lights
+    lights: lit_shader::Lights,
+    light_uniform_buffer: lit_shader::LightUniformBuffer,
+
+    // shadows
+    shadows: Shadows,
+
+    // debug draw
+    debug_draw: DebugDraw,
+
+    // grid and gizmo
+    grid: Grid,
+    gizmo: OrientationGizmo,
+
+    // picking
+    picking: Picking,
+
+    // minimap
+    minimap: Minimap,
+
+    // shared meshes and textures of the app
+    pub resources: ResourceManager,
+
+    // shader hot reload
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    shader_reload: ShaderReload,
+}
+
+impl<'a> Renderer<WindowTarget<'a>> {
+    /// The sample count is clamped to what the formats support,
+    /// the present mode falls back to a supported one
+    pub async fn new(window: &'a winit::window::Window, sample_count: u32, present_mode: wgpu::PresentMode) -> Self 
+    {
+        let wgpu_renderer = WindowTarget::new(window, present_mode).await; 
+
+        Self::with_target(wgpu_renderer, sample_count)
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode 
+    {
+        self.wgpu_renderer.present_mode()
+    }
+
+    /// Returns the mode used from now on, which may differ from the requested one
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode 
+    {
+        self.wgpu_renderer.set_present_mode(present_mode)
+    }
 }
 
-impl<'a> Renderer<'a> {
-    pub async fn new(window: &'a winit::window::Window) -> Self 
+#[cfg(test)]
+impl Renderer<OffscreenRenderer> {
+    /// Renders into an offscreen texture, returns None if no adapter is available
+    pub async fn new_headless(width: u32, height: u32, sample_count: u32) -> Option<Self> 
+    {
+        let wgpu_renderer = OffscreenRenderer::new(width, height).await?;
+
+        Some(Self::with_target(wgpu_renderer, sample_count))
+    }
+
+    pub fn read_frame(&mut self) -> image::RgbaImage {
+        self.wgpu_renderer.read_frame()
+    }
+}
+
+impl<R: RenderTarget> Renderer<R> {
+    fn with_target(mut wgpu_renderer: R, sample_count: u32) -> Self 
     {   
         // wgpu renderer
-        let mut wgpu_renderer = WgpuRenderer::new(window, None).await; 
-        let surface_format = wgpu_renderer.config().format;
-        let _surface_width = wgpu_renderer.config().width;
-        let _surface_height = wgpu_renderer.config().height;
+        let surface_format = wgpu_renderer.surface_format();
+        let sample_count = multisample::clamp_sample_count(&wgpu_renderer, sample_count);
+        let scene_format = multisample::scene_format(&wgpu_renderer, sample_count);
         
-        // pipeline color
+        // layouts
         let camera_bind_group_layout = vertex_color_shader::CameraBindGroupLayout::new(wgpu_renderer.device());
-        let pipeline_color = vertex_color_shader::Pipeline::new(
-            wgpu_renderer.device(), 
-            &camera_bind_group_layout, 
-            surface_format,
-        );
+        let texture_bind_group_layout = vertex_texture_shader::TextureBindGroupLayout::new(wgpu_renderer.device());
+        let light_bind_group_layout = lit_shader::LightBindGroupLayout::new(wgpu_renderer.device());
+
+        // materials, their pipelines are built with the first frame
+        let mut materials = Materials::new(wgpu_renderer.device());
+        let builtin_materials = BuiltinMaterials::register(wgpu_renderer.device(), &mut materials);
+
+        // shadows
+        let shadows = Shadows::new(wgpu_renderer.device(), ShadowSettings::default());
 
-        // pipeline lines
-        let pipeline_lines = vertex_color_shader::Pipeline::new_lines(
+        // msaa
+        let size = wgpu_renderer.size();
+        let scene_targets = MultisampleTargets::new(
             wgpu_renderer.device(), 
-            &camera_bind_group_layout, 
-            surface_format,
-        );
+            scene_format, 
+            size, 
+            sample_count);
+        let multisample_targets = MultisampleTargets::new(
+            wgpu_renderer.device(), 
+            surface_format, 
+            size, 
+            sample_count);
 
-        // pipeline texture
-        let texture_bind_group_layout = vertex_texture_shader::TextureBindGroupLayout::new(wgpu_renderer.device());
-        let pipeline_texture = vertex_texture_shader::Pipeline::new(
+        // post processing
+        let mut post_processing = PostProcessing::new(
             wgpu_renderer.device(), 
-            &camera_bind_group_layout, 
-            &texture_bind_group_layout, 
-            surface_format
-        );
+            scene_format, 
+            surface_format, 
+            size, 
+            sample_count);
+        post_processing.set_color_grading(wgpu_renderer.queue(), ColorGrading::default());
 
-        // pipeline texture gui
-        let pipeline_texture_gui = vertex_texture_shader::Pipeline::new_gui(
+        // viewports
+        let viewport_layout = ViewportLayout::Single;
+        let viewports = Self::create_viewports(
             wgpu_renderer.device(), 
             &camera_bind_group_layout, 
-            &texture_bind_group_layout, 
-            surface_format
-        );
+            viewport_layout, 
+            size, 
+            None);
 
         // camera 
-        let position = cgmath::Point3::new(0.0, 0.0, 0.0);
-        let yaw = cgmath::Deg(0.0);
-        let pitch = cgmath::Deg(0.0);
-        let mut camera = renderer::camera::Camera::new(position, yaw, pitch);
-        // Self::top_view_point(&mut camera);
-        Self::side_view_point(&mut camera);
-
-        let speed = 4.0;
-        let sensitivity = 1.0;
-        let sensitivity_scroll = 1.0;
-        let camera_controller = super::camera_controller::CameraController::new(speed, sensitivity, sensitivity_scroll);
-
-        let width = wgpu_renderer.config().width;
-        let height = wgpu_renderer.config().height;
-        let fovy = cgmath::Deg(45.0);
-        let znear = 0.1;
-        let zfar = 100.0;
-        let camera_projection = renderer::camera::Projection::new(width, height, fovy, znear, zfar);
-
-        let camera_uniform = vertex_color_shader::CameraUniform::new();
-
-        let camera_uniform_buffer = vertex_color_shader::CameraUniformBuffer::new(
-            wgpu_renderer.device(), 
-            &camera_bind_group_layout);
-
+        let width = wgpu_renderer.size().width;
+        let height = wgpu_renderer.size().height;
         let camera_uniform_orthographic: vertex_color_shader::CameraUniform = vertex_color_shader::CameraUniform::new_orthographic(width, height);
         let mut camera_uniform_orthographic_buffer = vertex_color_shader::CameraUniformBuffer::new(
                 wgpu_renderer.device(), 
@@ -105,30 +198,88 @@ impl<'a> Renderer<'a> {
 
         camera_uniform_orthographic_buffer.update(wgpu_renderer.queue(), camera_uniform_orthographic);   // add uniform identity matrix
 
+        // debug draw
+        let debug_draw = DebugDraw::new(wgpu_renderer.device());
+
+        // grid and gizmo
+        let grid = Grid::new(wgpu_renderer.device(), scene_format, sample_count);
+        let gizmo = OrientationGizmo::new(wgpu_renderer.device(), &camera_bind_group_layout);
+
+        // picking
+        let picking = Picking::new(wgpu_renderer.device());
+
+        // minimap
+        let mut minimap_camera = renderer::camera::Camera::new(
+            cgmath::Point3::new(0.0, 0.0, 0.0), 
+            cgmath::Deg(0.0), 
+            cgmath::Deg(0.0));
+        Self::top_view_point(&mut minimap_camera);
+        let minimap = Minimap::new(
+            wgpu_renderer.device(), 
+            &camera_bind_group_layout, 
+            &texture_bind_group_layout, 
+            minimap_camera, 
+            scene_format, 
+            sample_count, 
+            size);
+
+        // lights
+        let lights = lit_shader::Lights::default();
+        let light_uniform_buffer = lit_shader::LightUniformBuffer::new(
+            wgpu_renderer.device(), 
+            &light_bind_group_layout);
+
         Self {
             wgpu_renderer,
 
             camera_bind_group_layout,
             texture_bind_group_layout,
+            light_bind_group_layout,
+
+            materials,
+            builtin_materials,
+
+            scene_format,
+            scene_targets,
+            multisample_targets,
 
-            _pipeline_color: pipeline_color,
-            pipeline_lines,
-            pipeline_texture,
-            pipeline_texture_gui,
+            post_processing,
 
-            camera,
-            camera_controller,
-            camera_projection,
+            capture_requested: false,
+            captured_frame: None,
 
-            camera_uniform,
-            camera_uniform_buffer,
+            render_graph: RenderGraph::new_default(),
+
+            viewports,
+            viewport_layout,
+            active_viewport: 0,
+            pick_viewport: 0,
 
             camera_uniform_orthographic,
             camera_uniform_orthographic_buffer,
+
+            lights,
+            light_uniform_buffer,
+
+            shadows,
+
+            debug_draw,
+
+            grid,
+            gizmo,
+
+            picking,
+
+            minimap,
+
+            resources: ResourceManager::new(),
+
+            #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+            shader_reload: ShaderReload::new(),
         } 
     }
 
-    fn _top_view_point(camera: &mut renderer::camera::Camera) {
+    fn top_view_point(camera: &mut renderer::camera::Camera) {
         let position = cgmath::Point3::new(0.0, 0.0, 10.0);
         let yaw = cgmath::Deg(-90.0).into();
         let pitch = cgmath::Deg(0.0).into();
@@ -148,6 +299,113 @@ impl<'a> Renderer<'a> {
         camera.pitch = pitch;
     }
 
+    /// Looks at the origin from the end of the axis, along the axis
+    fn axis_view_point(camera: &mut renderer::camera::Camera, axis: GizmoAxis) {
+        let direction = axis.direction() * 10.0;
+        let position = cgmath::Point3::new(direction.x, direction.y, direction.z);
+        let (yaw, pitch) = match axis {
+            GizmoAxis::PositiveX => (PI, 0.0),
+            GizmoAxis::NegativeX => (0.0, 0.0),
+            GizmoAxis::PositiveY => (-FRAC_PI_2, -SAFE_FRAC_PI_2),
+            GizmoAxis::NegativeY => (-FRAC_PI_2, SAFE_FRAC_PI_2),
+            GizmoAxis::PositiveZ => (-FRAC_PI_2, 0.0),
+            GizmoAxis::NegativeZ => (FRAC_PI_2, 0.0),
+        };
+
+        camera.position = position;
+        camera.yaw = cgmath::Rad(yaw);
+        camera.pitch = cgmath::Rad(pitch);
+    }
+
+    /// Viewports of the layout, the first one keeps the given perspective camera,
+    /// the others look along the axes
+    fn create_viewports(device: &wgpu::Device,
+        camera_bind_group_layout: &vertex_color_shader::CameraBindGroupLayout,
+        layout: ViewportLayout,
+        frame_size: winit::dpi::PhysicalSize<u32>,
+        perspective: Option<&renderer::camera::Camera>,
+    ) -> Vec<Viewport>
+    {
+        layout.rects(frame_size).into_iter()
+            .enumerate()
+            .map(|(index, rect)| {
+                let mut camera = renderer::camera::Camera::new(
+                    cgmath::Point3::new(0.0, 0.0, 0.0), 
+                    cgmath::Deg(0.0), 
+                    cgmath::Deg(0.0));
+
+                match (index, perspective) {
+                    (0, Some(perspective)) => {
+                        camera.position = perspective.position;
+                        camera.yaw = perspective.yaw;
+                        camera.pitch = perspective.pitch;
+                    }
+                    (0, None) => Self::side_view_point(&mut camera),
+                    (1, _) => Self::axis_view_point(&mut camera, GizmoAxis::PositiveZ),
+                    (2, _) => Self::axis_view_point(&mut camera, GizmoAxis::NegativeY),
+                    _ => Self::axis_view_point(&mut camera, GizmoAxis::PositiveX),
+                }
+
+                let projection = match index {
+                    0 => ViewportProjection::Perspective,
+                    _ => ViewportProjection::Orthographic { height: ORTHOGRAPHIC_HEIGHT },
+                };
+
+                Viewport::new(device, camera_bind_group_layout, camera, projection, rect)
+            })
+            .collect()
+    }
+
+    fn active_viewport(&self) -> &Viewport {
+        &self.viewports[self.active_viewport]
+    }
+
+    fn active_viewport_mut(&mut self) -> &mut Viewport {
+        &mut self.viewports[self.active_viewport]
+    }
+
+    /// The whole frame, for the passes drawn after the viewports
+    fn frame_rect(&self) -> ViewportRect
+    {
+        let size = self.wgpu_renderer.size();
+        ViewportRect { x: 0, y: 0, width: size.width, height: size.height }
+    }
+
+    pub fn viewport_layout(&self) -> ViewportLayout 
+    {
+        self.viewport_layout
+    }
+
+    /// Replaces the viewports, the perspective camera is kept
+    pub fn set_viewport_layout(&mut self, layout: ViewportLayout) 
+    {
+        let size = self.wgpu_renderer.size();
+        let viewports = Self::create_viewports(
+            self.wgpu_renderer.device(), 
+            &self.camera_bind_group_layout, 
+            layout, 
+            size, 
+            Some(self.viewports[0].camera()));
+
+        self.viewports = viewports;
+        self.viewport_layout = layout;
+        self.active_viewport = 0;
+        self.pick_viewport = 0;
+    }
+
+    /// Routes the input to the viewport under the position in physical pixels of the frame
+    pub fn process_cursor_moved(&mut self, x: f32, y: f32) 
+    {
+        let Some(index) = viewport::viewport_at(&self.viewports, x, y) else {
+            return;
+        };
+
+        if index != self.active_viewport {
+            self.active_viewport_mut().controller_mut().release();
+            self.active_viewport = index;
+        }
+    }
+
     pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
         self.wgpu_renderer.size()
     }
@@ -155,103 +413,880 @@ impl<'a> Renderer<'a> {
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         // self.size = new_size;
         
-        self.camera_projection.resize(new_size.width, new_size.height);
         self.wgpu_renderer.resize(new_size);
+
+        for (viewport, rect) in self.viewports.iter_mut().zip(self.viewport_layout.rects(new_size)) {
+            viewport.set_rect(rect);
+        }
+
+        let surface_format = self.wgpu_renderer.surface_format();
+        let sample_count = self.multisample_targets.sample_count();
+        self.scene_targets = MultisampleTargets::new(
+            self.wgpu_renderer.device(), 
+            self.scene_format, 
+            new_size, 
+            sample_count);
+        self.multisample_targets = MultisampleTargets::new(
+            self.wgpu_renderer.device(), 
+            surface_format, 
+            new_size, 
+            sample_count);
+        self.post_processing.resize(self.wgpu_renderer.device(), new_size);
+        self.minimap.resize(self.wgpu_renderer.device(), new_size);
     
         self.camera_uniform_orthographic.resize_orthographic(new_size.width, new_size.height);
         self.camera_uniform_orthographic_buffer.update(self.wgpu_renderer.queue(), self.camera_uniform_orthographic);
     }
 
-    pub fn update(&mut self, dt: instant::Duration) {
+    /// True once the device is lost, recover with [Renderer::recover_device] before the next frame
+    pub fn device_lost(&self) -> bool 
+    {
+        self.wgpu_renderer.device_lost()
+    }
+
+    /// Requests a new device and creates every GPU resource again from the host data,
+    /// meshes and textures of the app have to be created again as well
+    pub fn recover_device(&mut self) -> Result<(), DeviceError> 
+    {
+        self.wgpu_renderer.recreate_device()?;
+
+        let surface_format = self.wgpu_renderer.surface_format();
+        let scene_format = self.scene_format;
+        let sample_count = self.multisample_targets.sample_count();
+        let size = self.wgpu_renderer.size();
+        let device = self.wgpu_renderer.device();
+
+        // layouts
+        self.camera_bind_group_layout = vertex_color_shader::CameraBindGroupLayout::new(device);
+        self.texture_bind_group_layout = vertex_texture_shader::TextureBindGroupLayout::new(device);
+        self.light_bind_group_layout = lit_shader::LightBindGroupLayout::new(device);
+        let camera_layout = &self.camera_bind_group_layout;
+        let texture_layout = &self.texture_bind_group_layout;
+
+        // shadows and grid, the pipelines of the materials are built with the next frame
+        self.shadows = Shadows::new(device, self.shadows.settings().clone());
+
+        let mut grid = Grid::new(device, scene_format, sample_count);
+        grid.visible = self.grid.visible;
+        grid.settings = self.grid.settings;
+        self.grid = grid;
+
+        // targets and post processing
+        self.scene_targets = MultisampleTargets::new(device, scene_format, size, sample_count);
+        self.multisample_targets = MultisampleTargets::new(device, surface_format, size, sample_count);
+
+        let post_settings = self.post_processing.settings();
+        let mut post_processing = PostProcessing::new(device, scene_format, surface_format, size, sample_count);
+        post_processing.set_effects(self.post_processing.effects().to_vec());
+        self.post_processing = post_processing;
+
+        // cameras and lights
+        for viewport in &mut self.viewports {
+            viewport.recreate(device, camera_layout);
+        }
+        self.camera_uniform_orthographic_buffer = vertex_color_shader::CameraUniformBuffer::new(device, camera_layout);
+        self.light_uniform_buffer = lit_shader::LightUniformBuffer::new(device, &self.light_bind_group_layout);
+
+        // overlays
+        self.debug_draw = DebugDraw::new(device);
+        let gizmo_visible = self.gizmo.visible;
+        self.gizmo = OrientationGizmo::new(device, camera_layout);
+        self.gizmo.visible = gizmo_visible;
+        self.picking = Picking::new(device);
+
+        let mut minimap_camera = renderer::camera::Camera::new(
+            cgmath::Point3::new(0.0, 0.0, 0.0), 
+            cgmath::Deg(0.0), 
+            cgmath::Deg(0.0));
+        Self::top_view_point(&mut minimap_camera);
+        let minimap_visible = self.minimap.visible;
+        self.minimap = Minimap::new(device, camera_layout, texture_layout, minimap_camera, scene_format, sample_count, size);
+        self.minimap.visible = minimap_visible;
+
+        // app resources
+        self.materials.recreate(device);
+        self.render_graph.recreate(device);
+        self.resources.clear();
+
+        // uniforms and lookup tables
+        self.post_processing.set_settings(self.wgpu_renderer.queue(), post_settings);
+        self.post_processing.set_color_grading(self.wgpu_renderer.queue(), ColorGrading::default());
+        self.camera_uniform_orthographic_buffer.update(self.wgpu_renderer.queue(), self.camera_uniform_orthographic);
+
+        Ok(())
+    }
+
+    /// Moves the cameras by one tick
+    pub fn fixed_update(&mut self, dt: instant::Duration)
+    {
+        for viewport in &mut self.viewports {
+            viewport.fixed_update(dt);
+        }
+    }
+
+    /// Alpha is how far the frame is between the last tick and the next one
+    pub fn update(&mut self, dt: instant::Duration, alpha: f32) {
+        // nothing can be written to a lost device
+        if self.wgpu_renderer.device_lost() {
+            return;
+        }
+
+        // shader hot reload
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        self.reload_shaders();
+
+        // viewports and grid
+        self.grid.prepare(self.wgpu_renderer.device(), self.viewports.len());
+        for (index, viewport) in self.viewports.iter_mut().enumerate() {
+            viewport.update(self.wgpu_renderer.queue(), alpha);
+            self.grid.update(self.wgpu_renderer.queue(), index, viewport.view_proj(), viewport.camera_position());
+        }
 
-        // camera
-        self.camera_controller.update_camera(&mut self.camera, dt);
-        self.camera_uniform.update_view_proj(&self.camera, &self.camera_projection);
-        self.camera_uniform_buffer.update(self.wgpu_renderer.queue(), self.camera_uniform);
+        // gizmo
+        self.gizmo.update(self.wgpu_renderer.queue(), self.viewports[self.active_viewport].render_camera());
+
+        // minimap
+        self.minimap.update(self.wgpu_renderer.queue(), self.viewports[0].render_camera());
+
+        // auto exposure
+        self.post_processing.update(self.wgpu_renderer.queue(), dt);
+
+        // lights
+        let light_uniform = lit_shader::LightUniform::new(&self.lights, self.camera_position());
+        self.light_uniform_buffer.update(self.wgpu_renderer.queue(), light_uniform);
+
+        // shadows, fitted to the perspective view so the cascades stay when the cursor moves to another viewport
+        let main_viewport = &self.viewports[0];
+        let size = main_viewport.rect().size();
+        let shadow_camera = ShadowCamera {
+            view: main_viewport.view(),
+            fovy: cgmath::Rad::from(CAMERA_FOVY).0,
+            aspect: size.width.max(1) as f32 / size.height.max(1) as f32,
+            znear: CAMERA_ZNEAR,
+            zfar: CAMERA_ZFAR,
+        };
+        self.shadows.update(self.wgpu_renderer.queue(), &shadow_camera, self.lights.directional.direction);
+    }
+
+    /// Rebuilds the pipelines of changed shaders, the old pipelines stay if a shader does not compile
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    fn reload_shaders(&mut self)
+    {
+        for (kind, source) in self.shader_reload.poll() {
+            let result = source.and_then(|source| self.rebuild_pipelines(kind, &source));
+            match &result {
+                Ok(()) => log::info!("shader reload: {} rebuilt", kind.file_name()),
+                Err(diagnostic) => log::error!("shader reload: {diagnostic}"),
+            }
+            self.shader_reload.set_result(kind, result);
+        }
+
+        // red frame around the window and the diagnostics in its top left corner while a shader is broken
+        if self.shader_reload.errors().next().is_some() {
+            let size = self.wgpu_renderer.size();
+            let (width, height) = (size.width as f32, size.height as f32);
+            let lines = self.debug_draw.screen();
+            for inset in 1..=4 {
+                let inset = inset as f32;
+                let corners = [
+                    glam::Vec3::new(inset, inset, 0.0),
+                    glam::Vec3::new(width - inset, inset, 0.0),
+                    glam::Vec3::new(width - inset, height - inset, 0.0),
+                    glam::Vec3::new(inset, height - inset, 0.0),
+                ];
+                for (a, b) in corners.iter().zip(corners.iter().cycle().skip(1)) {
+                    lines.line(*a, *b, super::debug_draw::RED);
+                }
+            }
+
+            let margin = 12.0;
+            let mut top = height - margin;
+            for diagnostic in self.shader_reload.errors() {
+                let origin = glam::Vec3::new(margin, top, 0.0);
+                top = lines.text(origin, super::debug_draw::TEXT_HEIGHT, width - 2.0 * margin, &diagnostic.to_string(), super::debug_draw::RED);
+            }
+        }
+    }
+
+    /// Rebuilds the pipelines of the built in materials using the shader
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    fn rebuild_pipelines(&mut self, kind: ShaderKind, source: &str) -> Result<(), ShaderDiagnostic>
+    {
+        let builtins = &self.builtin_materials;
+        let handles = match kind {
+            ShaderKind::VertexColor => vec![builtins.color, builtins.lines, builtins.lines_overlay, builtins.frame_lines, builtins.frame_lines_overlay],
+            ShaderKind::VertexTexture => vec![builtins.texture, builtins.texture_transparent, builtins.gui],
+        };
+
+        let formats = self.material_formats();
+        let layouts = RendererLayouts {
+            camera: self.camera_bind_group_layout.get(),
+            lights: self.light_bind_group_layout.get(),
+            shadows: self.shadows.bind_group_layout(),
+            texture: self.texture_bind_group_layout.get(),
+            cascade: self.shadows.cascade_bind_group_layout(),
+            pick_object: self.picking.object_bind_group_layout(),
+        };
+
+        self.materials.reload(self.wgpu_renderer.device(), &layouts, formats, &handles, kind.file_name(), source)
+    }
+
+    /// Of the perspective view, the lighting and the order of the opaque meshes follow it
+    fn camera_position(&self) -> glam::Vec3 
+    {
+        self.viewports[0].camera_position()
     }
 
     pub fn process_keyboard(&mut self, key: winit::keyboard::KeyCode, state: ElementState) -> bool 
     {
-        self.camera_controller.process_keyboard(key, state)
+        self.active_viewport_mut().controller_mut().process_keyboard(key, state)
     }
 
     pub fn process_scroll(&mut self, delta: &MouseScrollDelta) 
     {
-        self.camera_controller.process_scroll(delta);
+        self.active_viewport_mut().process_scroll(delta);
+    }
+
+    /// Takes effect with the next update
+    pub fn set_lights(&mut self, lights: lit_shader::Lights) 
+    {
+        self.lights = lights;
+    }
+
+    pub fn shadow_settings(&self) -> &ShadowSettings 
+    {
+        self.shadows.settings()
+    }
+
+    /// Recreates the shadow map, takes effect with the next update
+    pub fn set_shadow_settings(&mut self, settings: ShadowSettings) 
+    {
+        self.shadows.set_settings(self.wgpu_renderer.device(), settings);
+    }
+
+    pub fn post_effects(&self) -> &[PostEffect] 
+    {
+        self.post_processing.effects()
+    }
+
+    /// Effects are applied in the given order
+    pub fn set_post_effects(&mut self, effects: Vec<PostEffect>) 
+    {
+        self.post_processing.set_effects(effects);
+    }
+
+    pub fn post_settings(&self) -> PostSettings 
+    {
+        self.post_processing.settings()
+    }
+
+    pub fn set_post_settings(&mut self, settings: PostSettings) 
+    {
+        self.post_processing.set_settings(self.wgpu_renderer.queue(), settings);
+    }
+
+    /// Returns the new state, or None if the effect is not part of the chain
+    pub fn toggle_post_effect(&mut self, kind: PostEffectKind) -> Option<bool> 
+    {
+        self.post_processing.toggle(kind)
+    }
+
+    pub fn grid_visible(&self) -> bool 
+    {
+        self.grid.visible
+    }
+
+    pub fn set_grid_visible(&mut self, visible: bool) 
+    {
+        self.grid.visible = visible;
+    }
+
+    pub fn gizmo_visible(&self) -> bool 
+    {
+        self.gizmo.visible
+    }
+
+    pub fn set_gizmo_visible(&mut self, visible: bool) 
+    {
+        self.gizmo.visible = visible;
+    }
+
+    /// Snaps the camera to the axis of the gizmo under the position in physical pixels,
+    /// returns false if no axis was hit
+    pub fn click_gizmo(&mut self, x: f32, y: f32) -> bool 
+    {
+        let Some(axis) = self.gizmo.hit_test(self.active_viewport().rect(), x, y) else {
+            return false;
+        };
+
+        self.active_viewport_mut().teleport(|camera| Self::axis_view_point(camera, axis));
+        true
+    }
+
+    /// Moves the main camera above the point of the minimap under the position in physical pixels,
+    /// returns false if the minimap was not hit
+    pub fn click_minimap(&mut self, x: f32, y: f32) -> bool 
+    {
+        let Some(point) = self.minimap.ground_point(x, y) else {
+            return false;
+        };
+
+        self.viewports[0].teleport(|camera| {
+            camera.position.x = point.x;
+            camera.position.y = point.y;
+        });
+        true
+    }
+
+    pub fn minimap_visible(&self) -> bool 
+    {
+        self.minimap.visible
     }
 
+    pub fn set_minimap_visible(&mut self, visible: bool) 
+    {
+        self.minimap.visible = visible;
+    }
+
+    /// Picks the mesh at the position in physical pixels of the frame with the next call to render,
+    /// the result is returned by [Renderer::poll_pick] once it is read back
+    pub fn request_pick(&mut self, x: f32, y: f32) 
+    {
+        let Some(index) = viewport::viewport_at(&self.viewports, x, y) else {
+            return;
+        };
+
+        self.pick_viewport = index;
+        self.picking.request(x as u32, y as u32);
+    }
+
+    /// World space ray through the position in physical pixels of the frame, for picking on the CPU,
+    /// with the camera of the viewport under the position
+    pub fn cursor_ray(&self, x: f32, y: f32) -> Ray 
+    {
+        let viewport = viewport::viewport_at(&self.viewports, x, y)
+            .map_or(self.active_viewport(), |index| &self.viewports[index]);
+        let rect = viewport.rect();
+
+        Ray::from_screen(viewport.view_proj(), x - rect.x as f32, y - rect.y as f32, rect.width, rect.height)
+    }
+
+    /// The result of the last pick request, None while it is in flight
+    pub fn poll_pick(&mut self) -> Option<PickResult> 
+    {
+        self.picking.poll(self.wgpu_renderer.device())
+    }
+
+    /// Meshes drawn with the material refer to it by the returned handle,
+    /// returns the diagnostic if the shader or the vertex layouts are not valid
+    pub fn register_material(&mut self, desc: MaterialDesc) -> Result<MaterialHandle, ShaderDiagnostic> 
+    {
+        self.materials.register(self.wgpu_renderer.device(), desc)
+    }
+
+    /// Creates a custom bind group of the material for a mesh, group 1 is the first one after the camera,
+    /// returns None if the group is not a custom one
+    pub fn create_material_bind_group(&mut self, 
+        handle: MaterialHandle, 
+        group: u32, 
+        entries: &[wgpu::BindGroupEntry],
+    ) -> Option<wgpu::BindGroup> 
+    {
+        let layout = self.materials.bind_group_layout(handle, group)?;
+
+        Some(self.wgpu_renderer.device().create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Material Bind Group"),
+            layout,
+            entries,
+        }))
+    }
+
+    fn material_formats(&self) -> MaterialFormats
+    {
+        MaterialFormats {
+            scene: self.scene_format,
+            frame: self.wgpu_renderer.surface_format(),
+            sample_count: self.multisample_targets.sample_count(),
+        }
+    }
+
+    /// Builds the pipelines of new materials, and of all if a format or the sample count changed
+    fn prepare_materials(&mut self)
+    {
+        let formats = self.material_formats();
+        let layouts = RendererLayouts {
+            camera: self.camera_bind_group_layout.get(),
+            lights: self.light_bind_group_layout.get(),
+            shadows: self.shadows.bind_group_layout(),
+            texture: self.texture_bind_group_layout.get(),
+            cascade: self.shadows.cascade_bind_group_layout(),
+            pick_object: self.picking.object_bind_group_layout(),
+        };
+
+        self.materials.prepare(self.wgpu_renderer.device(), &layouts, formats);
+    }
+
+    /// Lines added here are drawn by the next call to render
+    pub fn debug_draw(&mut self) -> &mut DebugDraw 
+    {
+        &mut self.debug_draw
+    }
+
+    /// Custom passes are inserted relative to the stages of the default graph:
+    /// shadow, forward, grid, transparent, picking, minimap, debug_draw, post_processing, composite, performance_monitor, gizmo and gui
+    pub fn render_graph_mut(&mut self) -> &mut RenderGraph 
+    {
+        &mut self.render_graph
+    }
+
+    /// Sorts the queue and draws it, only an error which can not be recovered from is returned
     pub fn render(&mut self, 
-        meshes: &[&dyn VertexTextureShaderDraw],
-        mesh_gui: &[&dyn VertexTextureShaderDraw],
+        queue: &mut RenderQueue,
         performance_monitor: &mut PerformanceMonitor
-    ) -> Result<(), wgpu::SurfaceError>
+    ) -> Result<FrameStatus, wgpu::SurfaceError>
     {
-        performance_monitor.watch.start(0);
-        let output = self.wgpu_renderer.get_current_texture()?;
-        performance_monitor.watch.stop(0);
+        if self.wgpu_renderer.device_lost() {
+            return Ok(FrameStatus::DeviceLost);
+        }
+
+        performance_monitor.watch.start(performance_monitor::WATCH_GET_FRAME);
+        let output = self.wgpu_renderer.get_current_frame();
+        performance_monitor.watch.stop(performance_monitor::WATCH_GET_FRAME);
+
+        let output = match output {
+            Ok(output) => output,
+            Err(error) => return match SurfaceRecovery::of(&error) {
+                SurfaceRecovery::Reconfigure => {
+                    self.wgpu_renderer.reconfigure();
+                    Ok(FrameStatus::Reconfigured)
+                }
+                SurfaceRecovery::Skip => Ok(FrameStatus::Skipped),
+                SurfaceRecovery::Fatal => Err(error),
+            },
+        };
 
-        performance_monitor.watch.start(1);
+        performance_monitor.watch.start(performance_monitor::WATCH_RENDER);
 
-        let view: wgpu::TextureView = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let materials = &self.materials;
+        queue.split_transparent(|handle| materials.is_transparent(handle));
+        queue.sort(self.camera_position());
+        self.debug_draw.upload(&mut self.wgpu_renderer);
+
+        let size = self.wgpu_renderer.size();
+        self.render_graph.prepare(self.wgpu_renderer.device(), size);
+        self.prepare_materials();
+        self.picking.prepare(self.wgpu_renderer.device(), size);
 
         let mut encoder: wgpu::CommandEncoder = self.wgpu_renderer.device().create_command_encoder(&wgpu::CommandEncoderDescriptor{
             label: Some("Render Encoder"),
         });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
-                label: Some("Forward Render Pass"), 
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.01,
-                            g: 0.02,
-                            b: 0.03,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    }
-                })], 
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: self.wgpu_renderer.get_depth_texture_view(),
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
+        // a captured frame is drawn into a texture which can be copied from, and then into the frame
+        let surface_format = self.wgpu_renderer.surface_format();
+        let capture = std::mem::take(&mut self.capture_requested)
+            .then(|| CaptureTexture::new(self.wgpu_renderer.device(), surface_format, size));
+
+        self.draw_frame(
+            &mut encoder, 
+            capture.as_ref().map_or(&output.view, |capture| &capture.view), 
+            queue, 
+            performance_monitor);
+
+        let readback = capture.map(|capture| {
+            let source = self.post_processing.copy_source(self.wgpu_renderer.device(), &capture.view);
+            self.post_processing.copy(&mut encoder, &source, &output.view);
+            FrameCapture::new(self.wgpu_renderer.device(), &mut encoder, &capture.texture)
+        });
+
+        self.wgpu_renderer.queue().submit(std::iter::once(encoder.finish()));
+        self.picking.submitted();
+        self.resources.maintain();
+        output.present();
+
+        if let Some(readback) = readback {
+            self.captured_frame = Some(readback.read(self.wgpu_renderer.device()));
+        }
+
+        performance_monitor.watch.stop(performance_monitor::WATCH_RENDER);
+        
+        Ok(FrameStatus::Presented)
+    }
+
+    /// Copies the next presented frame back to the host, read it with [Renderer::take_captured_frame]
+    ///
+    /// The result is always converted to RGBA, independent of the surface format.
+    pub fn capture_frame(&mut self) 
+    {
+        self.capture_requested = true;
+    }
+
+    /// The frame captured by the last call to render, if one was requested
+    pub fn take_captured_frame(&mut self) -> Option<Result<image::RgbaImage, CaptureError>> 
+    {
+        self.captured_frame.take()
+    }
+
+    /// Records the passes of the render graph in their order
+    fn draw_frame(&self, 
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        queue: &RenderQueue,
+        performance_monitor: &mut PerformanceMonitor
+    )
+    {
+        let mut post_result = self.post_processing.scene_output();
+        let scene_color = self.scene_targets.color_view(self.post_processing.scene_view());
+
+        for node in self.render_graph.passes() {
+            let mut context = self.render_graph.context(encoder,
+                view,
+                self.wgpu_renderer.size(),
+                scene_color,
+                self.scene_targets.depth_view());
+            let encoder = &mut *context.encoder;
+
+            match node.kind() {
+                PassKind::Shadow => {
+                    performance_monitor.watch.start(performance_monitor::WATCH_SHADOW_ENCODE);
+                    self.shadows.draw(encoder, queue, &self.materials, &self.builtin_materials);
+                    performance_monitor.watch.stop(performance_monitor::WATCH_SHADOW_ENCODE);
+                }
+                PassKind::Forward => {
+                    performance_monitor.culling = self.draw_forward_pass(&mut context, queue);
+                }
+                PassKind::Grid => self.draw_grid_pass(&mut context),
+                PassKind::Transparent => {
+                    performance_monitor.culling += self.draw_transparent_pass(&mut context, queue);
+                }
+                PassKind::Picking => {
+                    let (Some(texture), Some(target), Some(depth)) = (
+                        context.attachment_texture(PICKING_ATTACHMENT),
+                        context.attachment(PICKING_ATTACHMENT),
+                        context.attachment(PICKING_DEPTH_ATTACHMENT)) else {
+                        continue;
+                    };
+                    let target = PickTarget { texture, view: target.view, depth_view: depth.view };
+
+                    let viewport = &self.viewports[self.pick_viewport.min(self.viewports.len() - 1)];
+                    self.picking.draw(context.encoder, target, queue, &self.materials, &self.builtin_materials, viewport);
+                }
+                PassKind::Minimap => self.draw_minimap_pass(encoder, queue),
+                PassKind::DebugDraw => self.draw_debug_pass(&mut context),
+                PassKind::PostProcessing => post_result = self.post_processing.run(encoder),
+                PassKind::Composite => self.draw_composite_pass(encoder, view, post_result),
+                PassKind::PerformanceMonitor => self.draw_performance_monitor_pass(encoder, view, performance_monitor),
+                PassKind::Gizmo => self.draw_gizmo_pass(encoder, view),
+                PassKind::Gui => self.draw_gui_pass(encoder, view, queue.gui()),
+                PassKind::Custom(pass) => pass.record(&mut context),
+            }
+        }
+    }
+
+    /// Draws the sorted queue into each viewport, pipelines and their bind groups are only bound when they change
+    fn draw_forward_pass(&self, 
+        context: &mut PassContext,
+        queue: &RenderQueue,
+    ) -> CullingStats
+    {
+        let scene_color = context.scene_color();
+        let scene_depth = context.scene_depth();
+        let mut render_pass = context.encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
+            label: Some("Forward Render Pass"), 
+            color_attachments: &[Some(scene_color.color_attachment(
+                wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.01,
+                    g: 0.02,
+                    b: 0.03,
+                    a: 1.0,
+                }),
+            ))], 
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: scene_depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
                 }),
-                timestamp_writes: Default::default(),
-                occlusion_query_set: Default::default(),
-            });
-
-            self.pipeline_texture.bind(&mut render_pass);
-            self.camera_uniform_buffer.bind(&mut render_pass);
-            for mesh in meshes {
-                mesh.draw(&mut render_pass);
+                stencil_ops: None,
+            }),
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        });
+
+        let mut stats = CullingStats::default();
+
+        for viewport in &self.viewports {
+            viewport.bind(&mut render_pass);
+            self.draw_queue(&mut render_pass, queue, viewport.frustum(), &mut stats);
+        }
+
+        stats
+    }
+
+    /// Draws the opaque items of the sorted queue with the camera which is bound,
+    /// pipelines and their bind groups are only bound when they change
+    fn draw_queue<'a>(&'a self, 
+        render_pass: &mut wgpu::RenderPass<'a>,
+        queue: &'a RenderQueue,
+        frustum: &culling::Frustum,
+        stats: &mut CullingStats,
+    )
+    {
+        let mut current_material = None;
+        let mut material_bound = false;
+        let mut textures = TextureBinds::default();
+        for item in queue.items() {
+            let material = self.builtin_materials.handle(item.mesh.pipeline());
+            if current_material != Some(material) {
+                current_material = Some(material);
+                material_bound = self.bind_material(render_pass, material);
+                textures.reset();
+            }
+            if !material_bound {
+                continue;
             }
 
-            // performance monitor
-            self.pipeline_lines.bind(&mut render_pass);
-            self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
-            performance_monitor.draw(&mut render_pass);
+            textures.bind(render_pass, item.mesh);
+            item.mesh.draw_culled(frustum, stats, render_pass, item.instances.clone());
+        }
+    }
+
+    fn draw_grid_pass(&self, context: &mut PassContext)
+    {
+        if !self.grid.visible {
+            return;
+        }
+
+        let mut render_pass = Self::begin_scene_pass(context, "Grid Render Pass");
+
+        for (index, viewport) in self.viewports.iter().enumerate() {
+            viewport.rect().apply(&mut render_pass);
+            self.grid.draw(&mut render_pass, index);
+        }
+    }
+
+    /// Draws the transparent meshes back to front on top of the forward pass,
+    /// textured meshes with the transparent texture material and the others with their own
+    fn draw_transparent_pass(&self, 
+        context: &mut PassContext,
+        queue: &RenderQueue,
+    ) -> CullingStats
+    {
+        let mut stats = CullingStats::default();
+        if queue.transparent().is_empty() {
+            return stats;
+        }
+
+        let mut render_pass = Self::begin_scene_pass(context, "Transparent Render Pass");
+
+        let mut current_material = None;
+        let mut material_bound = false;
+        let mut textures = TextureBinds::default();
+        for viewport in &self.viewports {
+            viewport.bind(&mut render_pass);
+            for item in queue.transparent_back_to_front(viewport.view()) {
+                let material = match item.mesh.pipeline() {
+                    QueuePipeline::Texture => self.builtin_materials.texture_transparent,
+                    pipeline => self.builtin_materials.handle(pipeline),
+                };
+                if current_material != Some(material) {
+                    current_material = Some(material);
+                    material_bound = self.bind_material(&mut render_pass, material);
+                    textures.reset();
+                }
+                if !material_bound {
+                    continue;
+                }
 
-            // gui
-            self.pipeline_texture_gui.bind(&mut render_pass);
-            self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
-            for mesh in mesh_gui {
-                mesh.draw(&mut render_pass);
+                textures.bind(&mut render_pass, item.mesh);
+                item.mesh.draw_culled(viewport.frustum(), &mut stats, &mut render_pass, item.instances.clone());
             }
         }
 
-        self.wgpu_renderer.queue().submit(std::iter::once(encoder.finish()));
-        output.present();
+        stats
+    }
 
-        performance_monitor.watch.stop(1);
-        
-        Ok(())
+    /// Draws the debug lines into the scene, before post processing
+    fn draw_debug_pass(&self, context: &mut PassContext)
+    {
+        if self.debug_draw.is_empty() {
+            return;
+        }
+
+        let mut render_pass = Self::begin_scene_pass(context, "Debug Draw Render Pass");
+
+        for viewport in &self.viewports {
+            viewport.bind(&mut render_pass);
+
+            self.bind_builtin(&mut render_pass, self.builtin_materials.lines);
+            self.debug_draw.draw_world(&mut render_pass);
+
+            self.bind_builtin(&mut render_pass, self.builtin_materials.lines_overlay);
+            self.debug_draw.draw_overlay(&mut render_pass);
+        }
+
+        self.frame_rect().apply(&mut render_pass);
+        self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
+        self.debug_draw.draw_screen(&mut render_pass);
     }
-}
 
+    /// Binds the pipeline and the bind groups the renderer owns except the camera,
+    /// false for materials registered after the frame was prepared
+    fn bind_material<'a>(&'a self, 
+        render_pass: &mut wgpu::RenderPass<'a>, 
+        material: MaterialHandle, 
+    ) -> bool
+    {
+        let Some(pipeline) = self.materials.pipeline(material) else {
+            return false;
+        };
+        pipeline.bind(render_pass);
 
+        for (index, bind_group) in self.materials.bind_groups(material).iter().enumerate() {
+            let index = index as u32 + 1;
+            match bind_group {
+                MaterialBindGroup::Lights => self.light_uniform_buffer.bind_at(render_pass, index),
+                MaterialBindGroup::Shadows => self.shadows.bind_at(render_pass, index),
+                MaterialBindGroup::Texture | MaterialBindGroup::Custom(_) => {}
+            }
+        }
+
+        true
+    }
+
+    /// The built in materials are prepared with every frame
+    fn bind_builtin<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, material: MaterialHandle)
+    {
+        let bound = self.bind_material(render_pass, material);
+        debug_assert!(bound, "built in materials are prepared before the frame is drawn");
+    }
+
+    /// Draws the opaque meshes and the marker of the main camera into the minimap texture
+    fn draw_minimap_pass(&self, 
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &RenderQueue,
+    )
+    {
+        if !self.minimap.visible {
+            return;
+        }
+
+        let mut render_pass = self.minimap.begin_pass(encoder);
+
+        // the minimap is not counted in the culling stats of the frame
+        let mut stats = CullingStats::default();
+        self.minimap.bind_camera(&mut render_pass);
+        self.draw_queue(&mut render_pass, queue, self.minimap.frustum(), &mut stats);
+
+        self.bind_builtin(&mut render_pass, self.builtin_materials.lines_overlay);
+        self.minimap.draw_marker(&mut render_pass);
+    }
+
+    /// Draws the post processed scene tonemapped into the frame
+    fn draw_composite_pass(&self, 
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        post_result: PostOutput,
+    )
+    {
+        self.post_processing.prepare_blit(encoder, post_result);
+
+        let mut render_pass = self.begin_overlay_pass(encoder, "Composite Render Pass", view, true);
+
+        self.post_processing.blit(&mut render_pass, post_result);
+    }
+
+    fn draw_performance_monitor_pass(&self, 
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        performance_monitor: &PerformanceMonitor
+    )
+    {
+        let mut render_pass = self.begin_overlay_pass(encoder, "Performance Monitor Render Pass", view, false);
+
+        self.bind_builtin(&mut render_pass, self.builtin_materials.frame_lines);
+        self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
+        performance_monitor.draw(&mut render_pass);
+    }
+
+    fn draw_gizmo_pass(&self, 
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    )
+    {
+        if !self.gizmo.visible {
+            return;
+        }
+
+        let mut render_pass = self.begin_overlay_pass(encoder, "Gizmo Render Pass", view, false);
+
+        self.bind_builtin(&mut render_pass, self.builtin_materials.frame_lines_overlay);
+        self.gizmo.draw(&mut render_pass, self.active_viewport().rect());
+    }
+
+    fn draw_gui_pass(&self, 
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        mesh_gui: &[&dyn VertexTextureShaderDraw],
+    )
+    {
+        let mut render_pass = self.begin_overlay_pass(encoder, "Gui Render Pass", view, false);
+
+        self.bind_builtin(&mut render_pass, self.builtin_materials.gui);
+        self.camera_uniform_orthographic_buffer.bind(&mut render_pass);
+        self.minimap.draw_quad(&mut render_pass);
+        for mesh in mesh_gui {
+            mesh.draw(&mut render_pass);
+        }
+    }
+
+    /// Render pass into the scene after the forward pass, keeps its color and depth
+    fn begin_scene_pass<'a>(context: &'a mut PassContext, label: &str) -> wgpu::RenderPass<'a>
+    {
+        let scene_color = context.scene_color();
+        let scene_depth = context.scene_depth();
+        context.encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
+            label: Some(label), 
+            color_attachments: &[Some(scene_color.color_attachment(wgpu::LoadOp::Load))], 
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: scene_depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        })
+    }
+
+    /// Render pass into the frame, the first overlay pass clears it and the following ones draw on top
+    fn begin_overlay_pass<'a>(&'a self, 
+        encoder: &'a mut wgpu::CommandEncoder,
+        label: &str,
+        view: &'a wgpu::TextureView,
+        clear: bool,
+    ) -> wgpu::RenderPass<'a>
+    {
+        let (color_load, depth_load) = if clear {
+            (wgpu::LoadOp::Clear(wgpu::Color::BLACK), wgpu::LoadOp::Clear(1.0))
+        } else {
+            (wgpu::LoadOp::Load, wgpu::LoadOp::Load)
+        };
+
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
+            label: Some(label), 
+            color_attachments: &[Some(self.multisample_targets.color_attachment(view, color_load))], 
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: self.multisample_targets.depth_view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: depth_load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: Default::default(),
+            occlusion_query_set: Default::default(),
+        })
+    }
+}
\ No newline at end of file
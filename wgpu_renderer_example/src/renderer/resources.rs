@@ -0,0 +1,445 @@
+//! Meshes and textures shared between the objects which draw them
+//!
+//! Resources are stored once per source, the path of a texture or a hash of the vertices
+//! and indices of a mesh, and handed out behind typed handles. A handle counts as a user of its resource. A resource without
+//! users is kept for a few frames, the GPU may still read it in frames in flight, and then
+//! destroyed. Loading it again in the meantime brings it back without uploading it again.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use wgpu::util::DeviceExt;
+use wgpu_renderer::renderer::WgpuRendererInterface;
+use wgpu_renderer::vertex_texture_shader::{Texture, TextureBindGroupLayout};
+
+/// Frames a resource without users is kept before it is destroyed
+pub const FRAMES_IN_FLIGHT: u64 = 3;
+
+/// Where a resource comes from, resources with the same key are stored once
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceKey {
+    Path(PathBuf),
+    /// 128 bits of hashes and the length of the data, different data practically never shares a key
+    Data { hash: u128, len: u64 },
+}
+
+impl ResourceKey {
+    pub fn of_data(parts: &[&[u8]]) -> Self
+    {
+        // two hashes seeded differently
+        let hash = |seed: u64| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            seed.hash(&mut hasher);
+            for part in parts {
+                part.hash(&mut hasher);
+            }
+            hasher.finish() as u128
+        };
+
+        ResourceKey::Data {
+            hash: hash(0) << 64 | hash(1),
+            len: parts.iter().map(|part| part.len() as u64).sum(),
+        }
+    }
+}
+
+impl std::fmt::Display for ResourceKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResourceKey::Path(path) => write!(f, "{}", path.display()),
+            ResourceKey::Data { hash, .. } => write!(f, "#{hash:032x}"),
+        }
+    }
+}
+
+struct Resource<T> {
+    label: String,
+    bytes: u64,
+    value: T,
+}
+
+/// Shares a resource of the [ResourceManager], the resource lives as long as any of its handles
+pub struct Handle<T>(Rc<Resource<T>>);
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> std::ops::Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0.value
+    }
+}
+
+impl<T> Handle<T> {
+    /// The same for all handles of a resource
+    pub fn id(&self) -> u64 {
+        Rc::as_ptr(&self.0) as *const () as usize as u64
+    }
+}
+
+pub type MeshHandle = Handle<GpuMesh>;
+pub type TextureHandle = Handle<GpuTexture>;
+
+/// Indexed triangles of any vertex layout
+pub struct GpuMesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+}
+
+impl GpuMesh {
+    pub fn num_indices(&self) -> u32 {
+        self.num_indices
+    }
+
+    /// Binds the vertices to slot 0 and the indices
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>)
+    {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    }
+}
+
+/// Texture of the vertex_texture_shader with its size
+pub struct GpuTexture {
+    texture: Texture,
+    width: u32,
+    height: u32,
+}
+
+impl GpuTexture {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.texture.bind(render_pass);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    Mesh,
+    Texture,
+}
+
+/// A line of the debug listing
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceInfo {
+    pub kind: ResourceKind,
+    pub key: ResourceKey,
+    pub label: String,
+    pub bytes: u64,
+    /// Handles outside of the manager, 0 while the resource waits for its destruction
+    pub users: usize,
+}
+
+impl std::fmt::Display for ResourceInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} {} ({}): {} bytes, {} users", self.kind, self.label, self.key, self.bytes, self.users)
+    }
+}
+
+struct Entry<T> {
+    resource: Rc<Resource<T>>,
+    /// Frame in which the last user was gone
+    unused_since: Option<u64>,
+}
+
+/// Resources of one type by their key
+struct Storage<T> {
+    entries: HashMap<ResourceKey, Entry<T>>,
+}
+
+impl<T> Storage<T> {
+    fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The stored resource of the key, or the one created now
+    fn get_or_create(&mut self, key: ResourceKey, create: impl FnOnce() -> (String, u64, T)) -> Handle<T>
+    {
+        let entry = self.entries.entry(key).or_insert_with(|| {
+            let (label, bytes, value) = create();
+            Entry {
+                resource: Rc::new(Resource { label, bytes, value }),
+                unused_since: None,
+            }
+        });
+        entry.unused_since = None;
+
+        Handle(entry.resource.clone())
+    }
+
+    fn maintain(&mut self, frame: u64)
+    {
+        self.entries.retain(|_, entry| {
+            if Rc::strong_count(&entry.resource) > 1 {
+                entry.unused_since = None;
+                return true;
+            }
+
+            let unused_since = *entry.unused_since.get_or_insert(frame);
+            frame - unused_since < FRAMES_IN_FLIGHT
+        });
+    }
+
+    fn infos(&self, kind: ResourceKind) -> impl Iterator<Item = ResourceInfo> + '_
+    {
+        self.entries.iter().map(move |(key, entry)| ResourceInfo {
+            kind,
+            key: key.clone(),
+            label: entry.resource.label.clone(),
+            bytes: entry.resource.bytes,
+            users: Rc::strong_count(&entry.resource) - 1,
+        })
+    }
+}
+
+pub struct ResourceManager {
+    meshes: Storage<GpuMesh>,
+    textures: Storage<GpuTexture>,
+
+    frame: u64,
+}
+
+impl ResourceManager {
+    pub fn new() -> Self {
+        Self {
+            meshes: Storage::new(),
+            textures: Storage::new(),
+
+            frame: 0,
+        }
+    }
+
+    /// Stored once for the same vertices and indices
+    pub fn mesh<V: bytemuck::Pod>(&mut self,
+        device: &wgpu::Device,
+        label: &str,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> MeshHandle
+    {
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(vertices);
+        let index_bytes: &[u8] = bytemuck::cast_slice(indices);
+        let key = ResourceKey::of_data(&[vertex_bytes, index_bytes]);
+
+        self.meshes.get_or_create(key, || {
+            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: vertex_bytes,
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: index_bytes,
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+            let mesh = GpuMesh {
+                vertex_buffer,
+                index_buffer,
+                num_indices: indices.len() as u32,
+            };
+            (label.to_string(), (vertex_bytes.len() + index_bytes.len()) as u64, mesh)
+        })
+    }
+
+    /// Stored once per path of the encoded image, e.g. the one it was included from with `include_bytes!`,
+    /// it is only decoded when it is not stored yet
+    pub fn texture_from_memory(&mut self,
+        wgpu_renderer: &mut impl WgpuRendererInterface,
+        texture_bind_group_layout: &TextureBindGroupLayout,
+        path: &Path,
+        bytes: &[u8],
+    ) -> Result<TextureHandle, String>
+    {
+        let key = ResourceKey::Path(path.to_path_buf());
+        let label = path.display().to_string();
+
+        self.create_texture(wgpu_renderer, texture_bind_group_layout, key, &label, || {
+            image::load_from_memory(bytes)
+                .map(|image| image.to_rgba8())
+                .map_err(|error| format!("{}: {error}", path.display()))
+        })
+    }
+
+    fn create_texture(&mut self,
+        wgpu_renderer: &mut impl WgpuRendererInterface,
+        texture_bind_group_layout: &TextureBindGroupLayout,
+        key: ResourceKey,
+        label: &str,
+        decode: impl FnOnce() -> Result<image::RgbaImage, String>,
+    ) -> Result<TextureHandle, String>
+    {
+        if let Some(entry) = self.textures.entries.get_mut(&key) {
+            entry.unused_since = None;
+            return Ok(Handle(entry.resource.clone()));
+        }
+
+        let rgba = decode()?;
+        let texture = Texture::new(wgpu_renderer, texture_bind_group_layout, &rgba, Some(label))?;
+        let (width, height) = rgba.dimensions();
+
+        Ok(self.textures.get_or_create(key, || {
+            let texture = GpuTexture {
+                texture,
+                width,
+                height,
+            };
+            (label.to_string(), width as u64 * height as u64 * 4, texture)
+        }))
+    }
+
+    /// Destroys the resources which had no users for [FRAMES_IN_FLIGHT] frames, call once per frame
+    pub fn maintain(&mut self)
+    {
+        self.frame += 1;
+
+        self.meshes.maintain(self.frame);
+        self.textures.maintain(self.frame);
+    }
+
+    /// Forgets all resources after the device was lost, the owners create them again
+    pub fn clear(&mut self)
+    {
+        self.meshes.entries.clear();
+        self.textures.entries.clear();
+    }
+
+    /// All stored resources, including those waiting for their destruction, the largest first
+    pub fn live_resources(&self) -> Vec<ResourceInfo>
+    {
+        let mut infos: Vec<ResourceInfo> = self.meshes.infos(ResourceKind::Mesh)
+            .chain(self.textures.infos(ResourceKind::Texture))
+            .collect();
+        infos.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.label.cmp(&b.label)));
+
+        infos
+    }
+
+    /// One line per resource and the total size
+    pub fn debug_listing(&self) -> String
+    {
+        let infos = self.live_resources();
+        let total: u64 = infos.iter().map(|info| info.bytes).sum();
+
+        let mut listing: String = infos.iter().map(|info| format!("{info}\n")).collect();
+        listing.push_str(&format!("{} resources, {total} bytes", infos.len()));
+
+        listing
+    }
+}
+
+impl Default for ResourceManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(label: &str, bytes: u64) -> impl FnOnce() -> (String, u64, u32) + '_ {
+        move || (label.to_string(), bytes, 7)
+    }
+
+    #[test]
+    fn same_key_is_stored_once()
+    {
+        let mut storage = Storage::new();
+        let key = ResourceKey::of_data(&[b"quad"]);
+
+        let a = storage.get_or_create(key.clone(), create("a", 16));
+        let b = storage.get_or_create(key, || panic!("created twice"));
+        let c = storage.get_or_create(ResourceKey::Path(PathBuf::from("c.png")), create("c", 16));
+
+        assert_eq!(a.id(), b.id());
+        assert_ne!(a.id(), c.id());
+        assert_eq!(*a, 7);
+        assert_eq!(storage.entries.len(), 2);
+    }
+
+    #[test]
+    fn data_keys_differ_with_the_data()
+    {
+        let key = ResourceKey::of_data(&[b"quad", b"indices"]);
+
+        assert_eq!(key, ResourceKey::of_data(&[b"quad", b"indices"]));
+        assert_ne!(key, ResourceKey::of_data(&[b"quad", b"indices!"]));
+        // the same bytes split differently are other vertices and indices
+        assert_ne!(key, ResourceKey::of_data(&[b"quadi", b"ndices"]));
+        assert!(matches!(key, ResourceKey::Data { len: 11, .. }));
+    }
+
+    #[test]
+    fn unused_resources_are_destroyed_after_the_frames_in_flight()
+    {
+        let mut storage = Storage::new();
+        let key = ResourceKey::of_data(&[b"quad"]);
+
+        let handle = storage.get_or_create(key.clone(), create("quad", 16));
+        storage.maintain(1);
+        assert_eq!(storage.infos(ResourceKind::Mesh).next().unwrap().users, 1);
+
+        drop(handle);
+        for frame in 2..2 + FRAMES_IN_FLIGHT {
+            storage.maintain(frame);
+            assert_eq!(storage.entries.len(), 1, "frame {frame}");
+        }
+        storage.maintain(2 + FRAMES_IN_FLIGHT);
+        assert!(storage.entries.is_empty());
+    }
+
+    #[test]
+    fn loading_again_keeps_a_resource_alive()
+    {
+        let mut storage = Storage::new();
+        let key = ResourceKey::of_data(&[b"quad"]);
+
+        let first = storage.get_or_create(key.clone(), create("quad", 16));
+        let first_id = first.id();
+        drop(first);
+        storage.maintain(1);
+
+        let again = storage.get_or_create(key, || panic!("uploaded again"));
+        for frame in 2..10 {
+            storage.maintain(frame);
+        }
+
+        assert_eq!(again.id(), first_id);
+        assert_eq!(storage.entries.len(), 1);
+    }
+
+    #[test]
+    fn listing_reports_sizes_and_users()
+    {
+        let mut storage = Storage::new();
+        let a = storage.get_or_create(ResourceKey::Data { hash: 1, len: 64 }, create("a", 64));
+        let _b = a.clone();
+        drop(storage.get_or_create(ResourceKey::Data { hash: 2, len: 16 }, create("b", 16)));
+
+        let mut infos: Vec<ResourceInfo> = storage.infos(ResourceKind::Texture).collect();
+        infos.sort_by_key(|info| info.label.clone());
+
+        assert_eq!(infos.len(), 2);
+        assert_eq!((infos[0].bytes, infos[0].users), (64, 2));
+        assert_eq!((infos[1].bytes, infos[1].users), (16, 0));
+        assert_eq!(infos[1].to_string(), "Texture b (#00000000000000000000000000000002): 16 bytes, 0 users");
+    }
+}
@@ -0,0 +1,137 @@
+//! Hot reloading of the vertex color and vertex texture shaders in debug builds
+//!
+//! The WGSL sources of the wgpu_renderer submodule are polled for changes. A changed source
+//! is validated with naga before the renderer rebuilds the pipelines using it, on an
+//! error the old pipelines keep running and the diagnostic is kept for the overlay.
+
+use std::path::Path;
+use std::time::SystemTime;
+
+use wgpu_renderer::{vertex_color_shader, vertex_texture_shader};
+
+use super::shader_validation::{validate_wgsl, ShaderDiagnostic};
+
+/// Sources are checked for changes at most this often
+const POLL_INTERVAL: instant::Duration = instant::Duration::from_millis(500);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaderKind {
+    VertexColor,
+    VertexTexture,
+}
+
+impl ShaderKind {
+    const ALL: [ShaderKind; 2] = [
+        ShaderKind::VertexColor,
+        ShaderKind::VertexTexture,
+    ];
+
+    /// For the log and the diagnostics
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ShaderKind::VertexColor => "vertex_color_shader/shader.wgsl",
+            ShaderKind::VertexTexture => "vertex_texture_shader/shader.wgsl",
+        }
+    }
+
+    /// The source file wgpu_renderer was built from
+    fn path(&self) -> &'static Path {
+        Path::new(match self {
+            ShaderKind::VertexColor => vertex_color_shader::SHADER_PATH,
+            ShaderKind::VertexTexture => vertex_texture_shader::SHADER_PATH,
+        })
+    }
+}
+
+pub struct ShaderReload {
+    modified: Vec<(ShaderKind, Option<SystemTime>)>,
+    last_poll: instant::Instant,
+    errors: Vec<(ShaderKind, ShaderDiagnostic)>,
+}
+
+impl ShaderReload {
+    pub fn new() -> Self
+    {
+        let modified = ShaderKind::ALL.iter()
+            .map(|kind| (*kind, Self::modified(*kind)))
+            .collect();
+
+        Self {
+            modified,
+            last_poll: instant::Instant::now(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn modified(kind: ShaderKind) -> Option<SystemTime> {
+        std::fs::metadata(kind.path()).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    /// Validated sources of the shaders which changed on disk since the last poll
+    pub fn poll(&mut self) -> Vec<(ShaderKind, Result<String, ShaderDiagnostic>)>
+    {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = instant::Instant::now();
+
+        let mut changed = Vec::new();
+        for (kind, modified) in &mut self.modified {
+            let current = Self::modified(*kind);
+            if current.is_none() || current == *modified {
+                continue;
+            }
+            *modified = current;
+
+            let result = std::fs::read_to_string(kind.path())
+                .map_err(|error| ShaderDiagnostic::new(kind.file_name(), None, error.to_string()))
+                .and_then(|source| validate_wgsl(kind.file_name(), &source).map(|_| source));
+
+            changed.push((*kind, result));
+        }
+
+        changed
+    }
+
+    pub fn set_result(&mut self, kind: ShaderKind, result: Result<(), ShaderDiagnostic>)
+    {
+        self.errors.retain(|(error_kind, _)| *error_kind != kind);
+        if let Err(diagnostic) = result {
+            self.errors.push((kind, diagnostic));
+        }
+    }
+
+    /// Diagnostics of the shaders whose last change did not compile
+    pub fn errors(&self) -> impl Iterator<Item = &ShaderDiagnostic> {
+        self.errors.iter().map(|(_, diagnostic)| diagnostic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_shaders_pass()
+    {
+        for kind in ShaderKind::ALL {
+            let source = std::fs::read_to_string(kind.path()).unwrap();
+            if let Err(diagnostic) = validate_wgsl(kind.file_name(), &source) {
+                panic!("{diagnostic}");
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_shader_clears_its_error()
+    {
+        let mut reload = ShaderReload::new();
+        let diagnostic = validate_wgsl("broken.wgsl", "fn").unwrap_err();
+
+        reload.set_result(ShaderKind::VertexColor, Err(diagnostic.clone()));
+        reload.set_result(ShaderKind::VertexTexture, Err(diagnostic));
+        reload.set_result(ShaderKind::VertexColor, Ok(()));
+
+        assert_eq!(reload.errors().count(), 1);
+    }
+}
@@ -0,0 +1,119 @@
+//! Validation of WGSL sources with naga
+//!
+//! Shaders are checked before wgpu sees them, so a broken source is reported with
+//! its file, line and column instead of failing inside wgpu.
+
+/// A compile error, the line and column start at 1 and are 0 if the error has no location
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderDiagnostic {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub message: String,
+}
+
+impl ShaderDiagnostic {
+    pub fn new(file: &str, location: Option<naga::SourceLocation>, message: String) -> Self
+    {
+        let (line, column) = location.map_or((0, 0), |location| (location.line_number, location.line_position));
+
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+            message,
+        }
+    }
+
+    /// Errors of wgpu, like a mismatch with the vertex layout, carry no location
+    #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+    pub fn from_wgpu(file: &str, error: &wgpu::Error) -> Self {
+        Self::new(file, None, error.to_string())
+    }
+}
+
+impl std::fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+        }
+    }
+}
+
+/// Parses and validates the source with naga, returns the module to inspect its entry points
+pub fn validate_wgsl(file: &str, source: &str) -> Result<naga::Module, ShaderDiagnostic>
+{
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|error| ShaderDiagnostic::new(file, error.location(source), error.message().to_string()))?;
+
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all());
+
+    validator.validate(&module)
+        .map_err(|error| {
+            // the outer error only names the function, the causes say what is wrong
+            let mut message = error.as_inner().to_string();
+            let mut source_error = std::error::Error::source(error.as_inner());
+            while let Some(cause) = source_error {
+                message += &format!(": {cause}");
+                source_error = cause.source();
+            }
+
+            ShaderDiagnostic::new(file, error.location(source), message)
+        })?;
+
+    Ok(module)
+}
+
+/// The module has an entry point with the name for the stage
+pub fn has_entry_point(module: &naga::Module, name: &str, stage: naga::ShaderStage) -> bool {
+    module.entry_points.iter().any(|entry_point| entry_point.name == name && entry_point.stage == stage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+}
+";
+
+    #[test]
+    fn valid_source_passes()
+    {
+        let module = validate_wgsl("valid.wgsl", VALID).unwrap();
+
+        assert!(has_entry_point(&module, "vs_main", naga::ShaderStage::Vertex));
+        assert!(!has_entry_point(&module, "vs_main", naga::ShaderStage::Fragment));
+        assert!(!has_entry_point(&module, "fs_main", naga::ShaderStage::Fragment));
+    }
+
+    #[test]
+    fn parse_error_has_line_and_column()
+    {
+        let source = VALID.replace("return vec4<f32>", "return vec4<f32");
+
+        let diagnostic = validate_wgsl("broken.wgsl", &source).unwrap_err();
+
+        assert_eq!(diagnostic.file, "broken.wgsl");
+        assert_eq!(diagnostic.line, 4);
+        assert!(diagnostic.column > 1);
+        assert!(diagnostic.to_string().starts_with("broken.wgsl:4:"));
+    }
+
+    #[test]
+    fn validation_error_has_line()
+    {
+        let source = VALID.replace("f32(index)", "index");
+
+        let diagnostic = validate_wgsl("invalid.wgsl", &source).unwrap_err();
+
+        assert_eq!(diagnostic.line, 4);
+    }
+}
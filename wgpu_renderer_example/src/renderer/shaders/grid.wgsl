@@ -0,0 +1,90 @@
+// Infinite grid on the z = 0 plane
+//
+// A fullscreen triangle casts a ray through every pixel and intersects it with the
+// ground plane. Minor and major lines are antialiased with the screen space
+// derivative of the plane coordinates and fade out with the distance to the camera.
+
+struct GridUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    // minor spacing, major spacing, fade distance, unused
+    params: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> grid: GridUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let x = f32((vertex_index << 1u) & 2u) * 2.0 - 1.0;
+    let y = f32(vertex_index & 2u) * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+fn unproject(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let position = grid.inv_view_proj * vec4<f32>(ndc, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// 1 on a line, 0 between the lines
+fn grid_lines(coord: vec2<f32>, spacing: f32) -> f32 {
+    let scaled = coord / spacing;
+    let width = max(fwidth(scaled), vec2<f32>(1e-4));
+    let distance = abs(fract(scaled - 0.5) - 0.5) / width;
+    return 1.0 - min(min(distance.x, distance.y), 1.0);
+}
+
+// 1 on the line through the origin along the other axis
+fn axis_line(coord: f32) -> f32 {
+    let width = max(fwidth(coord), 1e-4);
+    return 1.0 - min(abs(coord) / width, 1.0);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let near = unproject(in.ndc, 0.0);
+    let far = unproject(in.ndc, 1.0);
+    let t = -near.z / (far.z - near.z);
+    let position = near + t * (far - near);
+
+    let minor = grid_lines(position.xy, grid.params.x);
+    let major = grid_lines(position.xy, grid.params.y);
+    let x_axis = axis_line(position.y);
+    let y_axis = axis_line(position.x);
+
+    var color = vec4<f32>(0.35, 0.35, 0.35, 0.35 * minor);
+    color = mix(color, vec4<f32>(0.55, 0.55, 0.55, 0.7), major);
+    color = mix(color, vec4<f32>(0.9, 0.2, 0.2, 0.9), x_axis);
+    color = mix(color, vec4<f32>(0.2, 0.9, 0.2, 0.9), y_axis);
+
+    let distance = length(position.xy - grid.camera_position.xy);
+    let fade = 1.0 - smoothstep(grid.params.z * 0.5, grid.params.z, distance);
+    color.a = color.a * fade;
+
+    let clip = grid.view_proj * vec4<f32>(position, 1.0);
+
+    var out: FragmentOutput;
+    out.color = color;
+    out.depth = clip.z / clip.w;
+
+    // the ray points away from the plane
+    if (t <= 0.0 || color.a <= 0.0) {
+        discard;
+    }
+
+    return out;
+}
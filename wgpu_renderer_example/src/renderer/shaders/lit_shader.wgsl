@@ -0,0 +1,163 @@
+// Vertex shader
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    // instances only rotate and translate, so the model matrix also transforms the normals
+    let normal_matrix = mat3x3<f32>(
+        model_matrix[0].xyz,
+        model_matrix[1].xyz,
+        model_matrix[2].xyz,
+    );
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    out.color = model.color;
+    return out;
+}
+
+// Fragment shader
+
+const MAX_POINT_LIGHTS: u32 = 16u;
+const SHININESS: f32 = 32.0;
+
+struct DirectionalLight {
+    direction: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+};
+
+struct PointLight {
+    position: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    intensity: f32,
+};
+
+struct LightUniform {
+    view_position: vec3<f32>,
+    point_light_count: u32,
+    ambient: vec3<f32>,
+    directional: DirectionalLight,
+    point_lights: array<PointLight, MAX_POINT_LIGHTS>,
+};
+@group(1) @binding(0)
+var<uniform> light: LightUniform;
+
+const MAX_CASCADES: u32 = 4u;
+
+struct ShadowUniform {
+    cascades: array<mat4x4<f32>, MAX_CASCADES>,
+    cascade_count: u32,
+    texel_size: f32,
+    depth_bias: f32,
+};
+@group(2) @binding(0)
+var t_shadow: texture_depth_2d_array;
+@group(2) @binding(1)
+var s_shadow: sampler_comparison;
+@group(2) @binding(2)
+var<uniform> shadow: ShadowUniform;
+
+// 1.0 if lit by the directional light, 3x3 PCF in the first cascade containing the position
+fn directional_shadow(world_position: vec3<f32>) -> f32 {
+    for (var i = 0u; i < min(shadow.cascade_count, MAX_CASCADES); i++) {
+        let clip = shadow.cascades[i] * vec4<f32>(world_position, 1.0);
+        let ndc = clip.xyz / clip.w;
+        if (any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z < 0.0 || ndc.z > 1.0) {
+            continue;
+        }
+
+        let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+        let depth = ndc.z - shadow.depth_bias;
+
+        var lit = 0.0;
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+                lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, i, depth);
+            }
+        }
+        return lit / 9.0;
+    }
+
+    // outside of all cascades
+    return 1.0;
+}
+
+// Blinn-Phong, light_dir points towards the light
+fn blinn_phong(normal: vec3<f32>, view_dir: vec3<f32>, light_dir: vec3<f32>, radiance: vec3<f32>, albedo: vec3<f32>) -> vec3<f32> {
+    let diffuse = max(dot(normal, light_dir), 0.0);
+
+    let half_dir = normalize(view_dir + light_dir);
+    let specular = pow(max(dot(normal, half_dir), 0.0), SHININESS) * select(0.0, 1.0, diffuse > 0.0);
+
+    return radiance * (albedo * diffuse + vec3<f32>(specular));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let normal = normalize(in.world_normal);
+    let view_dir = normalize(light.view_position - in.world_position);
+
+    var color = light.ambient * in.color;
+
+    let directional = light.directional;
+    let directional_radiance = directional.color * directional.intensity * directional_shadow(in.world_position);
+    color += blinn_phong(normal, view_dir, -directional.direction, directional_radiance, in.color);
+
+    for (var i = 0u; i < min(light.point_light_count, MAX_POINT_LIGHTS); i++) {
+        let point = light.point_lights[i];
+
+        let to_light = point.position - in.world_position;
+        let distance = length(to_light);
+
+        // inverse square falloff, windowed to reach zero at the range
+        let window = clamp(1.0 - pow(distance / max(point.range, 0.0001), 4.0), 0.0, 1.0);
+        let attenuation = window * window / (distance * distance + 1.0);
+
+        let radiance = point.color * point.intensity * attenuation;
+        color += blinn_phong(normal, view_dir, to_light / max(distance, 0.0001), radiance, in.color);
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,283 @@
+// Fullscreen vertex shader
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    // one triangle covering the whole screen
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: FullscreenOutput;
+    out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Bindings
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct PostParams {
+    exposure: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    bloom_threshold: f32,
+    bloom_intensity: f32,
+    tonemapper: u32,
+    auto_exposure: u32,
+    exposure_key: f32,
+    exposure_min: f32,
+    exposure_max: f32,
+    adaptation: f32,
+    _padding: f32,
+};
+@group(1) @binding(0)
+var<uniform> params: PostParams;
+
+@group(2) @binding(0)
+var t_bloom: texture_2d<f32>;
+@group(2) @binding(1)
+var s_bloom: sampler;
+
+@group(2) @binding(2)
+var t_lut: texture_3d<f32>;
+@group(2) @binding(3)
+var s_lut: sampler;
+
+@group(2) @binding(4)
+var t_luminance: texture_2d<f32>;
+@group(2) @binding(5)
+var s_luminance: sampler;
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Blit
+
+@fragment
+fn fs_blit(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return textureSample(t_input, s_input, in.uv);
+}
+
+// copies every pixel without filtering, the input has the size of the target
+@fragment
+fn fs_copy(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return textureLoad(t_input, vec2<i32>(in.clip_position.xy), 0);
+}
+
+// Auto exposure
+
+@fragment
+fn fs_log_luminance(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv).rgb;
+
+    // averaged in log space, so a few bright pixels do not darken the whole scene
+    return vec4<f32>(log2(max(luminance(color), 0.0001)), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_adapt_luminance(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let average = exp2(textureSample(t_input, s_input, vec2<f32>(0.5)).r);
+    let adapted = textureSample(t_luminance, s_luminance, vec2<f32>(0.5)).r;
+
+    return vec4<f32>(mix(adapted, average, params.adaptation), 0.0, 0.0, 1.0);
+}
+
+fn exposure() -> f32 {
+    let adapted = textureSample(t_luminance, s_luminance, vec2<f32>(0.5)).r;
+    let auto_exposure = clamp(params.exposure_key / max(adapted, 0.0001), params.exposure_min, params.exposure_max);
+
+    return params.exposure * select(1.0, auto_exposure, params.auto_exposure != 0u);
+}
+
+// Tonemapping
+
+fn tonemap_reinhard(x: vec3<f32>) -> vec3<f32> {
+    return x / (1.0 + x);
+}
+
+// ACES filmic curve fitted by Krzysztof Narkowicz
+fn tonemap_aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return (x * (a * x + b)) / (x * (c * x + d) + e);
+}
+
+// Minimal AgX by Benjamin Wrensch, with the polynomial fit of the default contrast
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+fn tonemap_agx(x: vec3<f32>) -> vec3<f32> {
+    let agx_mat = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+    let agx_mat_inv = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let encoded = clamp(log2(max(agx_mat * x, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    let curve = agx_contrast((encoded - min_ev) / (max_ev - min_ev));
+
+    // the curve ends in display encoding, the frame expects linear colors
+    return pow(max(agx_mat_inv * curve, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@fragment
+fn fs_tonemapping(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let x = max(color.rgb * exposure(), vec3<f32>(0.0));
+
+    var mapped = x;
+    switch params.tonemapper {
+        case 1u: { mapped = tonemap_reinhard(x); }
+        case 2u: { mapped = tonemap_aces(x); }
+        case 3u: { mapped = tonemap_agx(x); }
+        default: {}
+    }
+
+    return vec4<f32>(clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);
+}
+
+// FXAA
+
+const FXAA_SPAN_MAX: f32 = 8.0;
+const FXAA_REDUCE_MUL: f32 = 0.125;
+const FXAA_REDUCE_MIN: f32 = 0.0078125;
+
+@fragment
+fn fs_fxaa(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+
+    let color_m = textureSample(t_input, s_input, in.uv);
+    let rgb_nw = textureSample(t_input, s_input, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb;
+    let rgb_ne = textureSample(t_input, s_input, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb;
+    let rgb_sw = textureSample(t_input, s_input, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb;
+    let rgb_se = textureSample(t_input, s_input, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb;
+
+    let luma_nw = luma(rgb_nw);
+    let luma_ne = luma(rgb_ne);
+    let luma_sw = luma(rgb_sw);
+    let luma_se = luma(rgb_se);
+    let luma_m = luma(color_m.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * texel;
+
+    let rgb_a = 0.5 * (
+        textureSample(t_input, s_input, in.uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        textureSample(t_input, s_input, in.uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    let rgb_b = rgb_a * 0.5 + 0.25 * (
+        textureSample(t_input, s_input, in.uv + dir * -0.5).rgb +
+        textureSample(t_input, s_input, in.uv + dir * 0.5).rgb);
+
+    let luma_b = luma(rgb_b);
+    let use_a = luma_b < luma_min || luma_b > luma_max;
+
+    return vec4<f32>(select(rgb_b, rgb_a, use_a), color_m.a);
+}
+
+// Vignette
+
+@fragment
+fn fs_vignette(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+
+    let distance_center = distance(in.uv, vec2<f32>(0.5, 0.5));
+    let vignette = 1.0 - smoothstep(params.vignette_radius, params.vignette_radius + 0.4, distance_center);
+
+    return vec4<f32>(color.rgb * mix(1.0, vignette, params.vignette_intensity), color.a);
+}
+
+// Color grading
+
+@fragment
+fn fs_color_grading(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+
+    // sample the centers of the outer texels
+    let lut_size = f32(textureDimensions(t_lut).x);
+    let coords = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)) * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+    let graded = textureSample(t_lut, s_lut, coords).rgb;
+
+    return vec4<f32>(graded, color.a);
+}
+
+// Bloom
+
+@fragment
+fn fs_bloom_threshold(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv).rgb;
+
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - params.bloom_threshold, 0.0) / max(brightness, 0.0001);
+
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let texel = direction / vec2<f32>(textureDimensions(t_input));
+
+    var result = textureSample(t_input, s_input, uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = texel * f32(i);
+        result += textureSample(t_input, s_input, uv + offset).rgb * weights[i];
+        result += textureSample(t_input, s_input, uv - offset).rgb * weights[i];
+    }
+
+    return vec4<f32>(result, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+@fragment
+fn fs_bloom_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_input, s_input, in.uv);
+    let bloom = textureSample(t_bloom, s_bloom, in.uv).rgb;
+
+    return vec4<f32>(color.rgb + bloom * params.bloom_intensity, color.a);
+}
@@ -0,0 +1,483 @@
+//! Cascaded shadow maps of the directional light
+//!
+//! The view frustum of the camera is split into cascades along the view direction.
+//! Each cascade is rendered depth only from the light's point of view into one layer
+//! of the shadow map, with the shadow pipelines of the materials casting shadows.
+//! The lit shader picks the first cascade containing a fragment and filters the
+//! shadow map with PCF.
+
+use bytemuck::Zeroable;
+use wgpu::util::DeviceExt;
+
+use super::material::{BuiltinMaterials, Materials};
+use super::render_queue::{RenderQueue, TextureBinds};
+
+pub const MAX_CASCADES: usize = 4;
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Casters this far in front of a cascade, seen from the light, still throw shadows into it
+const CASTER_MARGIN: f32 = 50.0;
+
+#[derive(Clone, Debug)]
+pub struct ShadowSettings {
+    /// Width and height of every cascade in texels, clamped to the texture size of the device
+    pub resolution: u32,
+    /// View distances at which the cascades end, ascending, splits after the first
+    /// [MAX_CASCADES] are dropped with a warning
+    pub cascade_splits: Vec<f32>,
+    /// Subtracted from the depth of a fragment before it is compared against the shadow map
+    pub depth_bias: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            cascade_splits: vec![4.0, 12.0, 35.0, 100.0],
+            depth_bias: 0.002,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// The settings the device can render, what is changed is logged
+    fn supported(mut self, limits: &wgpu::Limits) -> Self
+    {
+        let resolution = self.resolution.clamp(1, limits.max_texture_dimension_2d);
+        if resolution != self.resolution {
+            log::warn!("shadow: resolution {} is clamped to {resolution}", self.resolution);
+            self.resolution = resolution;
+        }
+
+        if self.cascade_splits.len() > MAX_CASCADES {
+            log::warn!("shadow: {} cascade splits are given, the ones after the first {MAX_CASCADES} are dropped",
+                self.cascade_splits.len());
+            self.cascade_splits.truncate(MAX_CASCADES);
+        }
+
+        self
+    }
+
+    fn cascade_count(&self) -> usize {
+        self.cascade_splits.len().clamp(1, MAX_CASCADES)
+    }
+}
+
+/// The perspective camera the cascades are fitted to
+pub struct ShadowCamera {
+    pub view: glam::Mat4,
+    pub fovy: f32,
+    pub aspect: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct CascadeUniform {
+    view_proj: [[f32; 4]; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    cascades: [[[f32; 4]; 4]; MAX_CASCADES],
+    cascade_count: u32,
+    texel_size: f32,
+    depth_bias: f32,
+    _padding: f32,
+}
+
+/// Resources which depend on the settings
+struct ShadowMap {
+    cascade_count: usize,
+
+    layer_views: Vec<wgpu::TextureView>,
+    cascade_buffers: Vec<wgpu::Buffer>,
+    cascade_bind_groups: Vec<wgpu::BindGroup>,
+
+    shadow_bind_group: wgpu::BindGroup,
+}
+
+pub struct Shadows {
+    settings: ShadowSettings,
+
+    cascade_bind_group_layout: wgpu::BindGroupLayout,
+    shadow_bind_group_layout: wgpu::BindGroupLayout,
+
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+
+    shadow_map: ShadowMap,
+}
+
+impl Shadows {
+    pub fn new(device: &wgpu::Device, settings: ShadowSettings) -> Self
+    {
+        let settings = settings.supported(&device.limits());
+
+        let cascade_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Cascade Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let shadow_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Buffer"),
+            contents: bytemuck::cast_slice(&[ShadowUniform::zeroed()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let shadow_map = ShadowMap::new(
+            device,
+            &settings,
+            &cascade_bind_group_layout,
+            &shadow_bind_group_layout,
+            &sampler,
+            &uniform_buffer);
+
+        Self {
+            settings,
+
+            cascade_bind_group_layout,
+            shadow_bind_group_layout,
+
+            sampler,
+            uniform_buffer,
+
+            shadow_map,
+        }
+    }
+
+    /// Bound at group 2 of the lit shader
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.shadow_bind_group_layout
+    }
+
+    /// Bound at group 0 of the shadow pipelines
+    pub fn cascade_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.cascade_bind_group_layout
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    /// Recreates the shadow map, takes effect with the next update
+    pub fn set_settings(&mut self, device: &wgpu::Device, settings: ShadowSettings) {
+        let settings = settings.supported(&device.limits());
+        self.shadow_map = ShadowMap::new(
+            device,
+            &settings,
+            &self.cascade_bind_group_layout,
+            &self.shadow_bind_group_layout,
+            &self.sampler,
+            &self.uniform_buffer);
+        self.settings = settings;
+    }
+
+    /// Fits the cascades to the camera
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &ShadowCamera, light_direction: glam::Vec3)
+    {
+        let light_direction = light_direction.try_normalize().unwrap_or(glam::Vec3::NEG_Z);
+        let inv_view = camera.view.inverse();
+
+        let mut cascades = [[[0.0; 4]; 4]; MAX_CASCADES];
+        let mut near = camera.znear;
+        for (index, cascade) in cascades.iter_mut().enumerate().take(self.shadow_map.cascade_count) {
+            let split = self.settings.cascade_splits.get(index).copied().unwrap_or(camera.zfar);
+            let far = split.clamp(near + 0.001, camera.zfar.max(near + 0.001));
+
+            let projection = glam::Mat4::perspective_rh(camera.fovy, camera.aspect, near, far);
+            let view_proj = Self::fit_cascade(inv_view * projection.inverse(), light_direction, self.settings.resolution);
+
+            queue.write_buffer(
+                &self.shadow_map.cascade_buffers[index],
+                0,
+                bytemuck::cast_slice(&[CascadeUniform { view_proj: view_proj.to_cols_array_2d() }]));
+            *cascade = view_proj.to_cols_array_2d();
+
+            near = far;
+        }
+
+        let uniform = ShadowUniform {
+            cascades,
+            cascade_count: self.shadow_map.cascade_count as u32,
+            texel_size: 1.0 / self.settings.resolution as f32,
+            depth_bias: self.settings.depth_bias,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Orthographic projection of the light around the bounding sphere of a frustum slice
+    ///
+    /// The sphere keeps the size of the projection constant when the camera rotates and the
+    /// origin is snapped to whole texels, so the shadow edges do not shimmer when it moves.
+    fn fit_cascade(inv_view_proj: glam::Mat4, light_direction: glam::Vec3, resolution: u32) -> glam::Mat4
+    {
+        let mut corners = [glam::Vec3::ZERO; 8];
+        for (index, corner) in corners.iter_mut().enumerate() {
+            let x = if index & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if index & 2 == 0 { -1.0 } else { 1.0 };
+            let z = if index & 4 == 0 { 0.0 } else { 1.0 };
+            *corner = inv_view_proj.project_point3(glam::Vec3::new(x, y, z));
+        }
+
+        let center = corners.iter().sum::<glam::Vec3>() / corners.len() as f32;
+        let radius = corners.iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        // any axis which is not parallel to the light
+        let up = if light_direction.dot(glam::Vec3::Z).abs() < 0.99 { glam::Vec3::Z } else { glam::Vec3::Y };
+
+        let eye = center - light_direction * (radius + CASTER_MARGIN);
+        let light_view = glam::Mat4::look_at_rh(eye, center, up);
+        let mut light_projection = glam::Mat4::orthographic_rh(
+            -radius, radius,
+            -radius, radius,
+            0.0, 2.0 * radius + CASTER_MARGIN);
+
+        let half_resolution = resolution as f32 / 2.0;
+        let origin = (light_projection * light_view).project_point3(glam::Vec3::ZERO) * half_resolution;
+        let offset = (origin.round() - origin) / half_resolution;
+        light_projection.w_axis.x += offset.x;
+        light_projection.w_axis.y += offset.y;
+
+        light_projection * light_view
+    }
+
+    /// Renders the opaque meshes of materials casting shadows into every cascade, they are not culled
+    /// since casters outside of the view still throw shadows into it
+    pub fn draw<'a>(&'a self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &'a RenderQueue,
+        materials: &'a Materials,
+        builtin_materials: &BuiltinMaterials,
+    )
+    {
+        let shadow_map = &self.shadow_map;
+        for (view, bind_group) in shadow_map.layer_views.iter().zip(shadow_map.cascade_bind_groups.iter()) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Render Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: Default::default(),
+                occlusion_query_set: Default::default(),
+            });
+
+            let mut current_material = None;
+            let mut textures = TextureBinds::default();
+            for item in queue.items() {
+                let material = builtin_materials.handle(item.mesh.pipeline());
+                let Some(pipeline) = materials.shadow_pipeline(material) else {
+                    continue;
+                };
+                if current_material != Some(material) {
+                    current_material = Some(material);
+                    pipeline.bind(&mut render_pass);
+                    render_pass.set_bind_group(0, bind_group, &[]);
+                    // the shadow map is the target, so the shadows are never bound here
+                    materials.bind_empty_groups(material, &mut render_pass);
+                    textures.reset();
+                }
+
+                textures.bind(&mut render_pass, item.mesh);
+                item.mesh.draw(&mut render_pass, item.instances.clone());
+            }
+        }
+    }
+
+    /// At the group of the material which holds the shadows
+    pub fn bind_at<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, index: u32) {
+        render_pass.set_bind_group(index, &self.shadow_map.shadow_bind_group, &[]);
+    }
+}
+
+impl ShadowMap {
+    fn new(device: &wgpu::Device,
+        settings: &ShadowSettings,
+        cascade_bind_group_layout: &wgpu::BindGroupLayout,
+        shadow_bind_group_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform_buffer: &wgpu::Buffer,
+    ) -> Self
+    {
+        let cascade_count = settings.cascade_count();
+        let resolution = settings.resolution;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map"),
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: cascade_count as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Shadow Map View"),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        let layer_views = (0..cascade_count as u32)
+            .map(|layer| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Shadow Cascade View"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        let cascade_buffers: Vec<wgpu::Buffer> = (0..cascade_count)
+            .map(|_| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Shadow Cascade Buffer"),
+                contents: bytemuck::cast_slice(&[CascadeUniform::zeroed()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }))
+            .collect();
+
+        let cascade_bind_groups = cascade_buffers.iter()
+            .map(|buffer| device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Shadow Cascade Bind Group"),
+                layout: cascade_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ],
+            }))
+            .collect();
+
+        let shadow_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Bind Group"),
+            layout: shadow_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&array_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            cascade_count,
+
+            layer_views,
+            cascade_buffers,
+            cascade_bind_groups,
+
+            shadow_bind_group,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_limited_to_the_device()
+    {
+        let limits = wgpu::Limits::downlevel_defaults();
+        let settings = ShadowSettings {
+            resolution: limits.max_texture_dimension_2d * 2,
+            cascade_splits: vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0],
+            depth_bias: 0.0,
+        };
+
+        let supported = settings.supported(&limits);
+
+        assert_eq!(supported.resolution, limits.max_texture_dimension_2d);
+        assert_eq!(supported.cascade_splits, [1.0, 2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn supported_settings_are_kept()
+    {
+        let supported = ShadowSettings::default().supported(&wgpu::Limits::downlevel_defaults());
+
+        assert_eq!(supported.resolution, ShadowSettings::default().resolution);
+        assert_eq!(supported.cascade_splits, ShadowSettings::default().cascade_splits);
+    }
+}
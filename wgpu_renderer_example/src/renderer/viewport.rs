@@ -0,0 +1,451 @@
+//! Viewports with their own camera, laid out side by side in one frame
+//!
+//! Every viewport owns a camera, its projection, controller and uniform buffer.
+//! The perspective view projects with a field of view, the top, front and side views
+//! project orthographically and zoom by changing the height they show. The scene passes
+//! draw once per viewport, restricted to its rect. Input goes to the controller of the
+//! viewport under the cursor.
+//!
+//! The controller moves the camera in fixed ticks. The scene is drawn with a copy of the
+//! camera interpolated between the poses of the last two ticks.
+
+use winit::event::MouseScrollDelta;
+
+use wgpu_renderer::renderer::camera::{Camera, Projection};
+use wgpu_renderer::vertex_color_shader;
+
+use super::camera_controller::CameraController;
+use super::culling::Frustum;
+
+pub const CAMERA_FOVY: cgmath::Deg<f32> = cgmath::Deg(45.0);
+pub const CAMERA_ZNEAR: f32 = 0.1;
+pub const CAMERA_ZFAR: f32 = 100.0;
+
+/// Height of the scene shown by the orthographic views when they are created
+pub const ORTHOGRAPHIC_HEIGHT: f32 = 10.0;
+/// The shown height shrinks by this factor per line scrolled up
+const ORTHOGRAPHIC_ZOOM: f32 = 0.9;
+const ORTHOGRAPHIC_MIN_HEIGHT: f32 = 0.5;
+
+/// Area of a viewport in physical pixels, from the top left corner of the frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ViewportRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ViewportRect {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x as f32
+            && y >= self.y as f32
+            && x < (self.x + self.width) as f32
+            && y < (self.y + self.height) as f32
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        winit::dpi::PhysicalSize::new(self.width, self.height)
+    }
+
+    /// Restricts drawing to the rect
+    pub fn apply(&self, render_pass: &mut wgpu::RenderPass)
+    {
+        render_pass.set_viewport(self.x as f32, self.y as f32, self.width.max(1) as f32, self.height.max(1) as f32, 0.0, 1.0);
+        render_pass.set_scissor_rect(self.x, self.y, self.width.max(1), self.height.max(1));
+    }
+}
+
+/// Position and orientation of a camera
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct CameraPose {
+    position: cgmath::Point3<f32>,
+    yaw: cgmath::Rad<f32>,
+    pitch: cgmath::Rad<f32>,
+}
+
+impl CameraPose {
+    fn of(camera: &Camera) -> Self {
+        Self {
+            position: camera.position,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+        }
+    }
+
+    fn lerp(&self, other: &Self, alpha: f32) -> Self
+    {
+        Self {
+            position: self.position + (other.position - self.position) * alpha,
+            yaw: self.yaw + (other.yaw - self.yaw) * alpha,
+            pitch: self.pitch + (other.pitch - self.pitch) * alpha,
+        }
+    }
+
+    fn apply(&self, camera: &mut Camera)
+    {
+        camera.position = self.position;
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViewportLayout {
+    Single,
+    /// Perspective on the left, top view on the right
+    SplitScreen,
+    /// Perspective in the bottom right, top, front and side views in the other corners
+    FourView,
+}
+
+impl ViewportLayout {
+    /// Each layout after the other, back to the first
+    pub fn next(&self) -> Self {
+        match self {
+            ViewportLayout::Single => ViewportLayout::SplitScreen,
+            ViewportLayout::SplitScreen => ViewportLayout::FourView,
+            ViewportLayout::FourView => ViewportLayout::Single,
+        }
+    }
+
+    /// The rects of the viewports, the first one is the perspective view
+    pub fn rects(&self, frame_size: winit::dpi::PhysicalSize<u32>) -> Vec<ViewportRect>
+    {
+        let (width, height) = (frame_size.width, frame_size.height);
+        let (left, top) = (width / 2, height / 2);
+        let (right, bottom) = (width - left, height - top);
+
+        match self {
+            ViewportLayout::Single => vec![
+                ViewportRect { x: 0, y: 0, width, height },
+            ],
+            ViewportLayout::SplitScreen => vec![
+                ViewportRect { x: 0, y: 0, width: left, height },
+                ViewportRect { x: left, y: 0, width: right, height },
+            ],
+            ViewportLayout::FourView => vec![
+                ViewportRect { x: left, y: top, width: right, height: bottom },
+                ViewportRect { x: 0, y: 0, width: left, height: top },
+                ViewportRect { x: left, y: 0, width: right, height: top },
+                ViewportRect { x: 0, y: top, width: left, height: bottom },
+            ],
+        }
+    }
+}
+
+/// How a viewport projects its camera
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ViewportProjection {
+    Perspective,
+    /// Parallel along the view direction, showing this height of the scene
+    Orthographic { height: f32 },
+}
+
+impl ViewportProjection {
+    /// Smaller heights for scrolling up, perspective projections do not zoom
+    fn zoomed(&self, delta: &MouseScrollDelta) -> Self
+    {
+        let ViewportProjection::Orthographic { height } = *self else {
+            return *self;
+        };
+
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => *lines,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / 100.0,
+        };
+
+        ViewportProjection::Orthographic {
+            height: (height * ORTHOGRAPHIC_ZOOM.powf(lines)).clamp(ORTHOGRAPHIC_MIN_HEIGHT, 2.0 * CAMERA_ZFAR),
+        }
+    }
+
+    /// Orthographic views see everything within the far distance in front of and behind the camera,
+    /// the position along the view direction does not matter for them
+    fn matrix(&self, perspective: &Projection, size: winit::dpi::PhysicalSize<u32>) -> glam::Mat4
+    {
+        match *self {
+            ViewportProjection::Perspective => {
+                let projection: [[f32; 4]; 4] = perspective.calc_matrix().into();
+                glam::Mat4::from_cols_array_2d(&projection)
+            }
+            ViewportProjection::Orthographic { height } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * size.width.max(1) as f32 / size.height.max(1) as f32;
+                glam::Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, -CAMERA_ZFAR, CAMERA_ZFAR)
+            }
+        }
+    }
+}
+
+pub struct Viewport {
+    /// Moved by the controller in fixed ticks
+    camera: Camera,
+    /// Pose of the camera before the last tick
+    previous: CameraPose,
+    /// Drawn with, between the previous and the current pose
+    render_camera: Camera,
+    /// Used by perspective views
+    perspective: Projection,
+    projection: ViewportProjection,
+    controller: CameraController,
+
+    // the view projection is written directly, the uniform of wgpu_renderer only knows perspective projections
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+
+    frustum: Frustum,
+    rect: ViewportRect,
+}
+
+impl Viewport {
+    pub fn new(device: &wgpu::Device,
+        camera_bind_group_layout: &vertex_color_shader::CameraBindGroupLayout,
+        camera: Camera,
+        projection: ViewportProjection,
+        rect: ViewportRect,
+    ) -> Self
+    {
+        let perspective = Projection::new(rect.width.max(1), rect.height.max(1), CAMERA_FOVY, CAMERA_ZNEAR, CAMERA_ZFAR);
+
+        let speed = 4.0;
+        let sensitivity = 1.0;
+        let sensitivity_scroll = 1.0;
+        let controller = CameraController::new(speed, sensitivity, sensitivity_scroll);
+
+        let previous = CameraPose::of(&camera);
+        let render_camera = Camera::new(camera.position, camera.yaw, camera.pitch);
+
+        let (camera_buffer, camera_bind_group) = Self::create_camera_uniform(device, camera_bind_group_layout);
+
+        Self {
+            camera,
+            previous,
+            render_camera,
+            perspective,
+            projection,
+            controller,
+
+            camera_buffer,
+            camera_bind_group,
+
+            frustum: Frustum::from_view_proj(glam::Mat4::IDENTITY),
+            rect,
+        }
+    }
+
+    fn create_camera_uniform(device: &wgpu::Device,
+        camera_bind_group_layout: &vertex_color_shader::CameraBindGroupLayout,
+    ) -> (wgpu::Buffer, wgpu::BindGroup)
+    {
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Viewport Camera Buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Viewport Camera Bind Group"),
+            layout: camera_bind_group_layout.get(),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (camera_buffer, camera_bind_group)
+    }
+
+    pub fn rect(&self) -> ViewportRect {
+        self.rect
+    }
+
+    pub fn set_rect(&mut self, rect: ViewportRect)
+    {
+        self.rect = rect;
+        self.perspective.resize(rect.width.max(1), rect.height.max(1));
+    }
+
+    /// Zooms orthographic views, perspective views move their camera
+    pub fn process_scroll(&mut self, delta: &MouseScrollDelta)
+    {
+        match self.projection {
+            ViewportProjection::Perspective => self.controller.process_scroll(delta),
+            ViewportProjection::Orthographic { .. } => self.projection = self.projection.zoomed(delta),
+        }
+    }
+
+    pub fn controller_mut(&mut self) -> &mut CameraController {
+        &mut self.controller
+    }
+
+    pub fn frustum(&self) -> &Frustum {
+        &self.frustum
+    }
+
+    /// Creates the uniform buffer on a new device, it is written by the next update
+    pub fn recreate(&mut self, device: &wgpu::Device, camera_bind_group_layout: &vertex_color_shader::CameraBindGroupLayout) {
+        (self.camera_buffer, self.camera_bind_group) = Self::create_camera_uniform(device, camera_bind_group_layout);
+    }
+
+    /// Where the controller moved the camera with the last tick
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    /// Places the camera without interpolating from where it was, it is drawn there with the next update
+    pub fn teleport(&mut self, place: impl FnOnce(&mut Camera))
+    {
+        place(&mut self.camera);
+        self.previous = CameraPose::of(&self.camera);
+    }
+
+    /// The camera as it is drawn in the current frame
+    pub fn render_camera(&self) -> &Camera {
+        &self.render_camera
+    }
+
+    /// From world to view space of the render camera
+    pub fn view(&self) -> glam::Mat4
+    {
+        let view: [[f32; 4]; 4] = self.render_camera.calc_matrix().into();
+        glam::Mat4::from_cols_array_2d(&view)
+    }
+
+    pub fn view_proj(&self) -> glam::Mat4 {
+        self.projection.matrix(&self.perspective, self.rect.size()) * self.view()
+    }
+
+    pub fn camera_position(&self) -> glam::Vec3
+    {
+        let position = self.render_camera.position;
+        glam::Vec3::new(position.x, position.y, position.z)
+    }
+
+    /// Moves the camera by one tick
+    pub fn fixed_update(&mut self, dt: instant::Duration)
+    {
+        self.previous = CameraPose::of(&self.camera);
+        self.controller.update_camera(&mut self.camera, dt);
+    }
+
+    /// Interpolates the camera between the last two ticks and writes its uniform
+    pub fn update(&mut self, queue: &wgpu::Queue, alpha: f32)
+    {
+        self.previous.lerp(&CameraPose::of(&self.camera), alpha)
+            .apply(&mut self.render_camera);
+
+        let view_proj = self.view_proj();
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&view_proj.to_cols_array_2d()));
+        self.frustum = Frustum::from_view_proj(view_proj);
+    }
+
+    /// Restricts drawing to the rect and binds the camera
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>)
+    {
+        self.rect.apply(render_pass);
+        self.bind_camera(render_pass);
+    }
+
+    /// Binds the camera at group 0
+    pub fn bind_camera<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+    }
+}
+
+/// Index of the viewport under the position
+pub fn viewport_at(viewports: &[Viewport], x: f32, y: f32) -> Option<usize> {
+    viewports.iter().position(|viewport| viewport.rect.contains(x, y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covered_area(rects: &[ViewportRect]) -> u32 {
+        rects.iter().map(|rect| rect.width * rect.height).sum()
+    }
+
+    #[test]
+    fn layouts_cover_the_frame_without_overlap()
+    {
+        let size = winit::dpi::PhysicalSize::new(801, 601);
+
+        for layout in [ViewportLayout::Single, ViewportLayout::SplitScreen, ViewportLayout::FourView] {
+            let rects = layout.rects(size);
+            assert_eq!(covered_area(&rects), 801 * 601, "{layout:?}");
+
+            for (i, a) in rects.iter().enumerate() {
+                for b in &rects[i + 1..] {
+                    let overlap_x = a.x < b.x + b.width && b.x < a.x + a.width;
+                    let overlap_y = a.y < b.y + b.height && b.y < a.y + a.height;
+                    assert!(!(overlap_x && overlap_y), "{layout:?}: {a:?} overlaps {b:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn four_view_has_perspective_bottom_right()
+    {
+        let rects = ViewportLayout::FourView.rects(winit::dpi::PhysicalSize::new(800, 600));
+
+        assert_eq!(rects[0], ViewportRect { x: 400, y: 300, width: 400, height: 300 });
+        assert!(rects[0].contains(799.0, 599.0));
+        assert!(!rects[0].contains(800.0, 300.0));
+        assert!(rects[1].contains(0.0, 0.0));
+    }
+
+    #[test]
+    fn poses_are_interpolated()
+    {
+        let from = CameraPose::of(&Camera::new((0.0, 0.0, 0.0), cgmath::Rad(0.0), cgmath::Rad(0.0)));
+        let to = CameraPose::of(&Camera::new((2.0, 4.0, 0.0), cgmath::Rad(1.0), cgmath::Rad(-0.5)));
+
+        assert_eq!(from.lerp(&to, 0.0), from);
+        assert_eq!(from.lerp(&to, 1.0), to);
+        let half = from.lerp(&to, 0.5);
+        assert_eq!(half.position, cgmath::Point3::new(1.0, 2.0, 0.0));
+        assert_eq!(half.yaw, cgmath::Rad(0.5));
+        assert_eq!(half.pitch, cgmath::Rad(-0.25));
+    }
+
+    #[test]
+    fn orthographic_views_zoom()
+    {
+        let projection = ViewportProjection::Orthographic { height: 10.0 };
+        let height = |lines: f32| match projection.zoomed(&MouseScrollDelta::LineDelta(0.0, lines)) {
+            ViewportProjection::Orthographic { height } => height,
+            ViewportProjection::Perspective => panic!("zooming changed the projection"),
+        };
+
+        assert!((height(1.0) - 9.0).abs() < 1e-4);
+        assert!((height(-1.0) - 10.0 / 0.9).abs() < 1e-4);
+        assert_eq!(height(100.0), ORTHOGRAPHIC_MIN_HEIGHT);
+        assert_eq!(ViewportProjection::Perspective.zoomed(&MouseScrollDelta::LineDelta(0.0, 1.0)), ViewportProjection::Perspective);
+    }
+
+    #[test]
+    fn orthographic_views_keep_the_size_with_the_distance()
+    {
+        let projection = ViewportProjection::Orthographic { height: 10.0 };
+        let perspective = Projection::new(200, 100, CAMERA_FOVY, CAMERA_ZNEAR, CAMERA_ZFAR);
+        let matrix = projection.matrix(&perspective, winit::dpi::PhysicalSize::new(200, 100));
+
+        for distance in [1.0, 50.0] {
+            let corner = matrix.project_point3(glam::Vec3::new(10.0, 5.0, -distance));
+            assert!((corner.x - 1.0).abs() < 1e-5 && (corner.y - 1.0).abs() < 1e-5, "{corner}");
+            assert!((0.0..=1.0).contains(&corner.z));
+        }
+    }
+
+    #[test]
+    fn layouts_cycle()
+    {
+        let layout = ViewportLayout::Single;
+
+        assert_eq!(layout.next().next().next(), layout);
+    }
+}
@@ -0,0 +1,179 @@
+//! Renders into the window with the renderer of wgpu_renderer and a selectable present mode
+//!
+//! The renderer of wgpu_renderer owns the surface and the device. Another present mode
+//! configures its surface again, a requested mode the surface does not support falls back
+//! to the closest one it does, Fifo is supported everywhere. A lost device creates the
+//! renderer of wgpu_renderer again.
+
+use wgpu_renderer::renderer::{WgpuRenderer, WgpuRendererInterface};
+use super::render_target::{self, DeviceError, DeviceLost, Frame, RenderTarget};
+
+/// The requested mode if the surface supports it, otherwise the closest supported one
+pub fn choose_present_mode(requested: wgpu::PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode
+{
+    let fallbacks: &[wgpu::PresentMode] = match requested {
+        // both present without waiting for the vertical blank
+        wgpu::PresentMode::Mailbox => &[wgpu::PresentMode::Immediate],
+        wgpu::PresentMode::Immediate => &[wgpu::PresentMode::Mailbox],
+        _ => &[],
+    };
+
+    std::iter::once(requested)
+        .chain(fallbacks.iter().copied())
+        .find(|mode| supported.contains(mode)
+            // wgpu resolves the automatic modes to a supported one itself
+            || matches!(mode, wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync))
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+pub struct WindowTarget<'a> {
+    window: &'a winit::window::Window,
+    /// None only while it is created again
+    renderer: Option<WgpuRenderer<'a>>,
+    device_lost: DeviceLost,
+    /// Enables the adapter specific sample counts
+    device_features: wgpu::Features,
+
+    /// Kept for the renderer created after a lost device
+    present_mode: wgpu::PresentMode,
+}
+
+impl<'a> WindowTarget<'a> {
+    pub async fn new(window: &'a winit::window::Window, present_mode: wgpu::PresentMode) -> Self
+    {
+        let mut renderer = WgpuRenderer::new(window, None).await;
+        let device_lost = DeviceLost::watch(renderer.device());
+        let device_features = renderer.device().features();
+
+        let mut target = Self {
+            window,
+            renderer: Some(renderer),
+            device_lost,
+            device_features,
+
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        target.set_present_mode(present_mode);
+
+        target
+    }
+
+    fn supported_present_modes(&self) -> Vec<wgpu::PresentMode> {
+        let renderer = self.renderer();
+        renderer.surface().get_capabilities(renderer.adapter()).present_modes
+    }
+
+    fn renderer(&self) -> &WgpuRenderer<'a> {
+        self.renderer.as_ref().expect("the renderer is created again")
+    }
+
+    fn renderer_mut(&mut self) -> &mut WgpuRenderer<'a> {
+        self.renderer.as_mut().expect("the renderer is created again")
+    }
+
+    /// The mode in use, which may differ from the requested one
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.renderer().config().present_mode
+    }
+
+    /// Configures the surface with the mode, returns the mode used from now on
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode
+    {
+        let present_mode = choose_present_mode(present_mode, &self.supported_present_modes());
+        if present_mode != self.present_mode() {
+            self.renderer_mut().set_present_mode(present_mode);
+        }
+        self.present_mode = present_mode;
+
+        present_mode
+    }
+}
+
+impl<'a> WgpuRendererInterface for WindowTarget<'a> {
+    fn device(&mut self) -> &mut wgpu::Device {
+        self.renderer_mut().device()
+    }
+
+    fn queue(&mut self) -> &mut wgpu::Queue {
+        self.renderer_mut().queue()
+    }
+}
+
+impl<'a> RenderTarget for WindowTarget<'a> {
+    fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.renderer().size()
+    }
+
+    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.renderer_mut().resize(new_size);
+        }
+    }
+
+    fn surface_format(&self) -> wgpu::TextureFormat {
+        self.renderer().config().format
+    }
+
+    fn get_current_frame(&mut self) -> Result<Frame, wgpu::SurfaceError> {
+        let output = self.renderer_mut().get_current_texture()?;
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Ok(Frame::new(Some(output), view))
+    }
+
+    /// Resizing to the same size configures the surface again
+    fn reconfigure(&mut self) {
+        let size = self.size();
+        self.renderer_mut().resize(size);
+    }
+
+    fn device_lost(&self) -> bool {
+        self.device_lost.get()
+    }
+
+    fn recreate_device(&mut self) -> Result<(), DeviceError>
+    {
+        let window = self.window;
+        let present_mode = self.present_mode;
+        let renderer = &mut self.renderer;
+        render_target::block_on_device(async move {
+            // a window can only have one configured surface
+            *renderer = None;
+            *renderer = Some(WgpuRenderer::new(window, Some(present_mode)).await);
+            Ok(())
+        })?;
+
+        self.device_lost = DeviceLost::watch(self.renderer_mut().device());
+        self.device_features = self.renderer_mut().device().features();
+
+        Ok(())
+    }
+
+    fn sample_count_supported(&self, format: wgpu::TextureFormat, sample_count: u32) -> bool {
+        render_target::sample_count_supported(self.renderer().adapter(), self.device_features, format, sample_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_mode_is_kept()
+    {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox];
+
+        assert_eq!(choose_present_mode(wgpu::PresentMode::Mailbox, &supported), wgpu::PresentMode::Mailbox);
+        assert_eq!(choose_present_mode(wgpu::PresentMode::AutoNoVsync, &supported), wgpu::PresentMode::AutoNoVsync);
+    }
+
+    #[test]
+    fn unsupported_mode_falls_back()
+    {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+
+        assert_eq!(choose_present_mode(wgpu::PresentMode::Mailbox, &supported), wgpu::PresentMode::Immediate);
+        assert_eq!(choose_present_mode(wgpu::PresentMode::FifoRelaxed, &supported), wgpu::PresentMode::Fifo);
+        assert_eq!(choose_present_mode(wgpu::PresentMode::Immediate, &[wgpu::PresentMode::Fifo]), wgpu::PresentMode::Fifo);
+    }
+}
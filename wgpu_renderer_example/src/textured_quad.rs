@@ -2,65 +2,76 @@ use wgpu_renderer::renderer::WgpuRendererInterface;
 use wgpu_renderer::vertex_texture_shader::{
     Vertex,
     Instance,
-    VertexBuffer,
-    IndexBuffer,
-    Texture,
-    InstanceBuffer, 
     VertexTextureShaderDraw, 
     TextureBindGroupLayout,
 };
 
-use image;
+use crate::instance_pool::{InstanceHandle, InstancePool, PoolInstance};
+use crate::renderer::{Aabb, Batched, Bounded, DrawInstances, MeshHandle, ResourceManager, TextureHandle};
 
 
 pub struct TexturedQuad {
     // host data
-    _texture_rgba: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
-    _instance: Instance,
-
-    // device data
-    vertex_buffer: VertexBuffer,
-    index_buffer: IndexBuffer,
-    texture: Texture,
-    instance_buffer: InstanceBuffer,
+    _instance: InstanceHandle,
+
+    // bounds
+    local_bounds: Aabb,
+    instance_bounds: Vec<Aabb>,
+    bounds: Option<Aabb>,
+
+    // device data, the mesh and the texture are shared by all quads
+    mesh: MeshHandle,
+    texture: TextureHandle,
+    instances: InstancePool<Instance>,
 }
 
 impl TexturedQuad {
-    pub fn new(wgpu_renderer: &mut impl WgpuRendererInterface, 
+    pub fn new(resources: &mut ResourceManager,
+        wgpu_renderer: &mut impl WgpuRendererInterface, 
         texture_bind_group_layout: &TextureBindGroupLayout,
     ) -> Self 
     {
-        let texture_image = image::load_from_memory(include_bytes!("performance.png")).unwrap();
-        let texture_rgba = texture_image.to_rgba8();
-
-        let width = texture_rgba.width();
-        let height = texture_rgba.height();
+        let (mesh, texture) = Self::create_resources(resources, wgpu_renderer, texture_bind_group_layout);
+        let vertices = Self::vertices(texture.width(), texture.height());
 
-        let vertex_buffer = VertexBuffer::new(wgpu_renderer.device(), 
-            &Self::vertices(width, height));
-        let index_buffer = IndexBuffer::new(wgpu_renderer.device(), &Self::indices());
+        let mut instances = InstancePool::new(wgpu_renderer.device());
+        let instance = instances.add(Instance::zero());
+        instances.upload(wgpu_renderer);
 
-        let texture = Texture::new(
-            wgpu_renderer, 
-            &texture_bind_group_layout, 
-            &texture_rgba, 
-            Some("example texture")).unwrap(); 
-
-        let instance = Instance::zero();
-        let instance_raw = instance.to_raw();
-        let instance_buffer = InstanceBuffer::new(wgpu_renderer.device(), &[instance_raw]);
+        let local_bounds = Aabb::from_points(vertices.iter().map(|vertex| glam::Vec3::from(vertex.position))).unwrap();
+        let instance_bounds = Self::instance_bounds(&local_bounds, &instances);
+        let bounds = Aabb::union_all(&instance_bounds);
     
         Self {
-            _texture_rgba: texture_rgba,
             _instance: instance,
 
-            vertex_buffer,
-            index_buffer,
+            local_bounds,
+            instance_bounds,
+            bounds,
+
+            mesh,
             texture,
-            instance_buffer,
+            instances,
         }
     }
 
+    fn create_resources(resources: &mut ResourceManager,
+        wgpu_renderer: &mut impl WgpuRendererInterface, 
+        texture_bind_group_layout: &TextureBindGroupLayout,
+    ) -> (MeshHandle, TextureHandle)
+    {
+        let texture = resources.texture_from_memory(
+            wgpu_renderer, 
+            texture_bind_group_layout, 
+            std::path::Path::new("performance.png"), 
+            include_bytes!("performance.png")).unwrap();
+
+        let vertices = Self::vertices(texture.width(), texture.height());
+        let mesh = resources.mesh(wgpu_renderer.device(), "textured quad", &vertices, &Self::indices());
+
+        (mesh, texture)
+    }
+
 
     fn vertices(width: u32, height: u32) -> [Vertex; 4]
     {
@@ -87,15 +98,78 @@ impl TexturedQuad {
         INDICES
     }
 
-    pub fn _update_texture(&mut self, queue: &wgpu::Queue) 
+    /// Requests the mesh and the texture again on a new device and recreates the instances
+    pub fn recreate(&mut self, resources: &mut ResourceManager,
+        wgpu_renderer: &mut impl WgpuRendererInterface, 
+        texture_bind_group_layout: &TextureBindGroupLayout,
+    )
+    {
+        (self.mesh, self.texture) = Self::create_resources(resources, wgpu_renderer, texture_bind_group_layout);
+
+        self.instances.recreate(wgpu_renderer.device());
+        self.instances.upload(wgpu_renderer);
+    }
+
+    /// Adds a quad next to the last one
+    pub fn add_instance(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
+    {
+        let width = self.local_bounds.max.x - self.local_bounds.min.x;
+        let instance = match self.instances.instances().last() {
+            Some(last) => Instance {
+                position: last.position + glam::Vec3::X * width,
+                rotation: last.rotation,
+            },
+            None => Instance::zero(),
+        };
+
+        self.instances.add(instance);
+        self.update_instance_buffer(wgpu_renderer);
+    }
+
+    /// Takes the index of a picked instance, returns false if there is none
+    pub fn remove_instance(&mut self, index: u32, wgpu_renderer: &mut impl WgpuRendererInterface) -> bool
+    {
+        let Some(handle) = self.instances.handle(index as usize) else {
+            return false;
+        };
+
+        self.instances.remove(handle);
+        self.update_instance_buffer(wgpu_renderer);
+
+        true
+    }
+
+    /// Takes the index of a picked instance, returns false if there is none
+    pub fn move_instance(&mut self, index: u32, offset: glam::Vec3, wgpu_renderer: &mut impl WgpuRendererInterface) -> bool
+    {
+        let Some(handle) = self.instances.handle(index as usize) else {
+            return false;
+        };
+        let Some(instance) = self.instances.get(handle) else {
+            return false;
+        };
+        let instance = Instance {
+            position: instance.position + offset,
+            rotation: instance.rotation,
+        };
+
+        self.instances.update(handle, instance);
+        self.update_instance_buffer(wgpu_renderer);
+
+        true
+    }
+
+    fn update_instance_buffer(&mut self, wgpu_renderer: &mut impl WgpuRendererInterface)
     {
-        self.texture.write(queue, &self._texture_rgba);
+        self.instances.upload(wgpu_renderer);
+
+        self.instance_bounds = Self::instance_bounds(&self.local_bounds, &self.instances);
+        self.bounds = Aabb::union_all(&self.instance_bounds);
     }
 
-    pub fn _update_instance_buffer(&mut self, queue: &wgpu::Queue)
+    fn instance_bounds(local_bounds: &Aabb, instances: &InstancePool<Instance>) -> Vec<Aabb>
     {
-        let instance_raw = self._instance.to_raw();
-        self.instance_buffer.update(queue, &[instance_raw]);
+        local_bounds.instanced(instances.instances().iter().map(PoolInstance::transform))
     }
 
 }
@@ -103,12 +177,42 @@ impl TexturedQuad {
 impl VertexTextureShaderDraw for TexturedQuad {
     fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) 
     {
-        self.vertex_buffer.bind(render_pass);
-        self.texture.bind(render_pass);
-        self.index_buffer.bind(render_pass);
-        self.instance_buffer.bind_slot(render_pass, 1);
+        self.bind_texture(render_pass);
+        self.draw_instances(render_pass, 0..self.instances.size());
+    }
+}
+
+impl Bounded for TexturedQuad {
+    fn bounding_volume(&self) -> Option<Aabb> {
+        self.bounds
+    }
+
+    fn instance_bounding_volumes(&self) -> Option<&[Aabb]> {
+        Some(&self.instance_bounds)
+    }
+
+    fn instance_draw(&self) -> Option<&dyn DrawInstances> {
+        Some(self)
+    }
+}
+
+impl DrawInstances for TexturedQuad {
+    fn draw_instances<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: std::ops::Range<u32>) 
+    {
+        self.mesh.bind(render_pass);
+        self.instances.bind_slot(render_pass, 1);
 
-        render_pass.draw_indexed(0..self.index_buffer.size(), 0, 0..self.instance_buffer.size());
+        render_pass.draw_indexed(0..self.mesh.num_indices(), 0, instances);
     }
 }
 
+
+impl Batched for TexturedQuad {
+    fn texture_key(&self) -> u64 {
+        self.texture.id()
+    }
+
+    fn bind_texture<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.texture.bind(render_pass);
+    }
+}